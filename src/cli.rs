@@ -0,0 +1,207 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use clap::{App, Arg, ArgMatches};
+use log::LevelFilter;
+
+use crate::core::controller::Mode;
+
+/// Options passed on the command line.
+pub struct Options {
+    pub config_dir: String,
+    pub log_file: Option<String>,
+    pub log_level: LevelFilter,
+    pub mqtt_log_file: String,
+    pub mqtt_log_level: LevelFilter,
+    pub mode: Mode,
+    pub team_id: Option<i32>,
+    pub dry_run: bool,
+    pub check_config: bool,
+    pub simulate: bool,
+    pub record_file: Option<String>,
+    pub replay_file: Option<String>,
+    pub speed: f64,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, failure::Error> {
+        Self::from_matches(&Self::app().get_matches())
+    }
+
+    fn app<'a, 'b>() -> App<'a, 'b> {
+        App::new("intersection-controller")
+            .version(env!("CARGO_PKG_VERSION"))
+            .about("Controls the traffic lights and bridge of a simulated intersection")
+            .arg(
+                Arg::with_name("config")
+                    .long("config")
+                    .short("c")
+                    .value_name("DIR")
+                    .default_value("config")
+                    .help("Directory to read the configuration files from"),
+            )
+            .arg(
+                Arg::with_name("log-file")
+                    .long("log-file")
+                    .value_name("FILE")
+                    .help("Also write the controller log to this file"),
+            )
+            .arg(
+                Arg::with_name("log-level")
+                    .long("log-level")
+                    .value_name("LEVEL")
+                    .default_value("trace")
+                    .possible_values(&["off", "error", "warn", "info", "debug", "trace"])
+                    .help("Level of the controller log"),
+            )
+            .arg(
+                Arg::with_name("mqtt-log-file")
+                    .long("mqtt-log-file")
+                    .value_name("FILE")
+                    .default_value("log/mqtt.log")
+                    .help("File to write the MQTT log to"),
+            )
+            .arg(
+                Arg::with_name("mqtt-log-level")
+                    .long("mqtt-log-level")
+                    .value_name("LEVEL")
+                    .default_value("trace")
+                    .possible_values(&["off", "error", "warn", "info", "debug", "trace"])
+                    .help("Level of the MQTT log"),
+            )
+            .arg(
+                Arg::with_name("mode")
+                    .long("mode")
+                    .short("m")
+                    .value_name("MODE")
+                    .default_value("all")
+                    .possible_values(&["all", "traffic_lights", "bridge"])
+                    .help("Intersections to run"),
+            )
+            .arg(
+                Arg::with_name("team-id")
+                    .long("team-id")
+                    .value_name("ID")
+                    .help("Overrides the team id of general.toml"),
+            )
+            .arg(
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("Builds the intersections, prints their topology and exits"),
+            )
+            .arg(
+                Arg::with_name("check-config")
+                    .long("check-config")
+                    .help("Validates the configuration and exits"),
+            )
+            .arg(
+                Arg::with_name("simulate")
+                    .long("simulate")
+                    .help("Runs the controller against simulated traffic and prints a report"),
+            )
+            .arg(
+                Arg::with_name("record-file")
+                    .long("record-file")
+                    .value_name("FILE")
+                    .help(
+                        "Records the MQTT messages of the session to the file, for replaying them",
+                    ),
+            )
+            .arg(
+                Arg::with_name("replay")
+                    .long("replay")
+                    .value_name("FILE")
+                    .conflicts_with("simulate")
+                    .help("Feeds a recording into the controller and compares the actuator states"),
+            )
+            .arg(
+                Arg::with_name("speed")
+                    .long("speed")
+                    .value_name("FACTOR")
+                    .default_value("1")
+                    .help("How much faster than recorded to replay"),
+            )
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Result<Self, failure::Error> {
+        // Values with defaults or possible values are always present and valid.
+        let value = |name| matches.value_of(name).unwrap();
+
+        Ok(Self {
+            config_dir: String::from(value("config")),
+            log_file: matches.value_of("log-file").map(String::from),
+            log_level: LevelFilter::from_str(value("log-level"))?,
+            mqtt_log_file: String::from(value("mqtt-log-file")),
+            mqtt_log_level: LevelFilter::from_str(value("mqtt-log-level"))?,
+            mode: Mode::try_from(value("mode"))?,
+            team_id: match matches.value_of("team-id") {
+                Some(team_id) => Some(team_id.parse::<i32>()?),
+                None => None,
+            },
+            dry_run: matches.is_present("dry-run"),
+            check_config: matches.is_present("check-config"),
+            simulate: matches.is_present("simulate"),
+            record_file: matches.value_of("record-file").map(String::from),
+            replay_file: matches.value_of("replay").map(String::from),
+            speed: match value("speed").parse::<f64>()? {
+                speed if speed > 0.0 && speed.is_finite() => speed,
+                _ => return Err(format_err!("The replay speed must be above 0")),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Options {
+        let matches = Options::app().get_matches_from(args);
+        Options::from_matches(&matches).unwrap()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = parse(&["intersection-controller"]);
+
+        assert_eq!(options.config_dir, "config");
+        assert_eq!(options.mqtt_log_file, "log/mqtt.log");
+        assert!(options.mode == Mode::All);
+        assert_eq!(options.team_id, None);
+        assert_eq!(options.record_file, None);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let options = parse(&[
+            "intersection-controller",
+            "--config",
+            "/etc/controller",
+            "--mode",
+            "bridge",
+            "--team-id",
+            "7",
+            "--log-level",
+            "info",
+            "--dry-run",
+            "--record-file",
+            "log/session.jsonl",
+            "--replay",
+            "log/recording.jsonl",
+            "--speed",
+            "10",
+        ]);
+
+        assert_eq!(options.config_dir, "/etc/controller");
+        assert!(options.mode == Mode::Bridge);
+        assert_eq!(options.team_id, Some(7));
+        assert_eq!(options.log_level, LevelFilter::Info);
+        assert!(options.dry_run);
+        assert_eq!(options.record_file, Some(String::from("log/session.jsonl")));
+        assert_eq!(
+            options.replay_file,
+            Some(String::from("log/recording.jsonl"))
+        );
+        assert_eq!(options.speed, 10.0);
+    }
+}
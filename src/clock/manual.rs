@@ -0,0 +1,176 @@
+use std::sync::{Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+
+use crate::clock::Clock;
+
+struct ManualState {
+    now: DateTime<Utc>,
+    timers: Vec<(DateTime<Utc>, ThreadId, Sender<Instant>)>,
+    /// Fired timers whose thread neither took the wake-up nor waits on the clock again yet.
+    woken: Vec<(ThreadId, Sender<Instant>)>,
+}
+
+/// A clock that only moves when it is advanced, so tests can run long cycles in no time.
+pub struct ManualClock {
+    state: Mutex<ManualState>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(ManualState {
+                now: Utc::now(),
+                timers: vec![],
+                woken: vec![],
+            }),
+        }
+    }
+
+    /// Moves the time forward, firing every timer that expires on the way.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.lock();
+
+        state.now += chrono::Duration::from_std(duration).unwrap();
+
+        let now = state.now;
+        let mut woken = vec![];
+        state.timers.retain(|(deadline, thread, sender)| {
+            if *deadline > now {
+                return true;
+            }
+
+            // Nobody is woken when the timer was dropped.
+            if sender.send(Instant::now()).is_ok() {
+                woken.push((*thread, sender.clone()));
+            }
+
+            false
+        });
+        state.woken.extend(woken);
+    }
+
+    /// Whether every thread woken by advancing the clock took its wake-up, dropped it for another
+    /// one, or already waits on the clock again.
+    pub fn caught_up(&self) -> bool {
+        let mut state = self.lock();
+
+        // Sending on a timer that still holds its wake-up only tells whether it was dropped. It
+        // succeeds when the wake-up was taken in the meantime, but a timer is never read twice.
+        state.woken.retain(|(_, sender)| {
+            !sender.is_empty()
+                && matches!(sender.try_send(Instant::now()), Err(TrySendError::Full(_)))
+        });
+        state.woken.is_empty()
+    }
+
+    /// Advances the time in steps of 100 ms until the condition holds, giving the threads waiting
+    /// on the clock a moment to catch up after every step. Returns the time it took, panics when
+    /// the condition doesn't hold within the limit.
+    #[cfg(test)]
+    pub fn run_until<F>(&self, limit: Duration, condition: F) -> Duration
+    where
+        F: Fn() -> bool,
+    {
+        let start = self.now();
+
+        while !condition() {
+            let elapsed = self.since(start);
+            assert!(elapsed < limit, "condition still false after {:?}", elapsed);
+
+            self.advance(Duration::from_millis(100));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        self.since(start)
+    }
+
+    /// The state is consistent after every statement, so a poisoned lock is still usable.
+    fn lock(&self) -> MutexGuard<'_, ManualState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.lock().now
+    }
+
+    fn after(&self, duration: Duration) -> Receiver<Instant> {
+        let (sender, receiver) = bounded(1);
+        let mut state = self.lock();
+
+        if duration == Duration::from_millis(0) {
+            sender.send(Instant::now()).unwrap_or_default();
+        } else {
+            let thread = thread::current().id();
+            let deadline = state.now + chrono::Duration::from_std(duration).unwrap();
+
+            // A thread that woke up for another reason drops its timer, but is back to waiting.
+            state.woken.retain(|(woken, _)| *woken != thread);
+            state.timers.push((deadline, thread, sender));
+        }
+
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+
+        let timer = clock.after(Duration::from_secs(60));
+        assert!(clock.after(Duration::from_millis(0)).try_recv().is_ok());
+
+        clock.advance(Duration::from_secs(59));
+        assert!(timer.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(1));
+        assert!(timer.try_recv().is_ok());
+        assert_eq!(clock.since(start), Duration::from_secs(60));
+        assert_eq!(
+            clock.since(clock.now() + chrono::Duration::seconds(1)),
+            Duration::from_millis(0)
+        );
+    }
+
+    #[test]
+    fn test_caught_up() {
+        let clock = ManualClock::new();
+
+        let timer = clock.after(Duration::from_secs(1));
+        let dropped = clock.after(Duration::from_secs(1));
+        drop(dropped);
+        assert!(clock.caught_up());
+
+        clock.advance(Duration::from_secs(1));
+        assert!(!clock.caught_up());
+
+        timer.recv().unwrap();
+        assert!(clock.caught_up());
+
+        // Woken up by something else, the thread dropped its timer.
+        let timer = clock.after(Duration::from_secs(1));
+        clock.advance(Duration::from_secs(1));
+        assert!(!clock.caught_up());
+
+        drop(timer);
+        assert!(clock.caught_up());
+
+        // Waiting on the clock again counts as caught up, even with the wake-up left untaken.
+        let _timer = clock.after(Duration::from_secs(1));
+        clock.advance(Duration::from_secs(1));
+        assert!(!clock.caught_up());
+
+        let _next = clock.after(Duration::from_secs(1));
+        assert!(clock.caught_up());
+    }
+}
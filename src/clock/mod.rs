@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use crossbeam_channel::{after, Receiver};
+
+pub mod manual;
+
+pub type ArcClock = Arc<dyn Clock>;
+
+/// The time as seen by the intersections and their runners.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// A channel that receives a single message once the given time has passed, like
+    /// `crossbeam_channel::after`.
+    fn after(&self, duration: Duration) -> Receiver<Instant>;
+
+    fn sleep(&self, duration: Duration) {
+        let _ = self.after(duration).recv();
+    }
+
+    /// The time passed since the given moment, none when it lies in the future.
+    fn since(&self, moment: DateTime<Utc>) -> Duration {
+        (self.now() - moment)
+            .to_std()
+            .unwrap_or_else(|_| Duration::from_millis(0))
+    }
+}
+
+/// The wall-clock time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn after(&self, duration: Duration) -> Receiver<Instant> {
+        after(duration)
+    }
+}
@@ -4,6 +4,7 @@ use crate::config::config_file::ConfigFile;
 pub struct Block {
     pub kind: String,
     pub id: i32,
+    pub clearance: Option<i32>,
 }
 
 #[derive(Deserialize)]
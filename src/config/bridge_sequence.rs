@@ -0,0 +1,26 @@
+use crate::config::config_file::ConfigFile;
+
+#[derive(Deserialize)]
+pub struct Step {
+    pub action: String,
+    pub components: Option<Vec<String>>,
+    pub state: Option<i32>,
+    pub duration: Option<i32>,
+    pub sensor: Option<String>,
+    pub groups: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct General {
+    pub triggers: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BridgeSequence {
+    pub general: General,
+    pub steps: Vec<Step>,
+}
+
+impl<'s> ConfigFile<'s> for BridgeSequence {
+    type Output = BridgeSequence;
+}
@@ -1,16 +1,187 @@
-use conf::{Config, ConfigError, File};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::Path;
+
+use conf::{Config, ConfigError, File, Source, Value};
 use serde::Deserialize;
 
+/// Prefix of the environment variables that override configuration values, e.g.
+/// `IC_IO__PUBLISHER__HOST` overrides `publisher.host` in `io.toml`.
+const ENV_PREFIX: &str = "IC_";
+const ENV_SEPARATOR: &str = "__";
+
+/// Values of keys ending in one of these are not logged.
+const SECRET_KEYS: &[&str] = &["password"];
+
+/// A layer of configuration values, flattened to `key.path[index]` keys.
+struct Layer {
+    source: String,
+    values: BTreeMap<String, String>,
+}
+
 pub trait ConfigFile<'s>
 where
     Self::Output: Deserialize<'s>,
 {
     type Output;
 
+    /// Reads a configuration file, layered as follows:
+    ///
+    /// 1. the base file in `dir`
+    /// 2. the optional file with the same name in `dir/local`
+    /// 3. environment variables prefixed with `IC_<FILE NAME>__`
     fn new(dir: &str, file: &str) -> Result<Self::Output, ConfigError> {
+        Self::with_env(dir, file, env::vars())
+    }
+
+    /// Reads a configuration file like `new`, with the given variables in place of the
+    /// environment.
+    fn with_env<I>(dir: &str, file: &str, vars: I) -> Result<Self::Output, ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
         let mut s = Config::new();
+        let mut layers = vec![];
+
+        for (path, required) in &[
+            (format!("{}/{}", dir, file), true),
+            (format!("{}/local/{}", dir, file), false),
+        ] {
+            let source = File::with_name(path).required(*required);
+
+            layers.push(Layer {
+                source: path.clone(),
+                values: flatten(source.collect()?),
+            });
+
+            s.merge(source)?;
+        }
+
+        for (var, key, value) in env_overrides(file, vars) {
+            s.set(&key, value.clone())?;
+
+            let mut values = BTreeMap::new();
+            values.insert(key, value);
+
+            layers.push(Layer {
+                source: var,
+                values,
+            });
+        }
+
+        log_values(file, &flatten(s.collect()?), &layers);
 
-        s.merge(File::with_name(&format!("{}/{}", dir, file)))?;
         s.try_into()
     }
 }
+
+/// Finds the variables overriding values of the given file, as `(variable, key, value)`.
+fn env_overrides<I>(file: &str, vars: I) -> Vec<(String, String, String)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let section = Path::new(file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+    let prefix = format!("{}{}{}", ENV_PREFIX, section, ENV_SEPARATOR);
+
+    let mut overrides: Vec<(String, String, String)> = vars
+        .into_iter()
+        .filter(|(var, _)| var.starts_with(&prefix))
+        .map(|(var, value)| {
+            let key = var[prefix.len()..]
+                .split(ENV_SEPARATOR)
+                .collect::<Vec<&str>>()
+                .join(".")
+                .to_lowercase();
+
+            (var, key, value)
+        })
+        .collect();
+
+    overrides.sort();
+    overrides
+}
+
+/// Logs every effective value together with the last layer that set it.
+fn log_values(file: &str, values: &BTreeMap<String, String>, layers: &[Layer]) {
+    for (key, value) in values {
+        let source = layers
+            .iter()
+            .rev()
+            .find(|layer| layer.values.contains_key(key))
+            .map(|layer| &layer.source[..])
+            .unwrap_or("unknown");
+
+        let value = if SECRET_KEYS.iter().any(|secret| key.ends_with(secret)) {
+            "********"
+        } else {
+            &value[..]
+        };
+
+        debug!("{}: {} = {} (from {})", file, key, value, source);
+    }
+}
+
+fn flatten(table: HashMap<String, Value>) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::new();
+
+    for (key, value) in table {
+        flatten_value(key, value, &mut values);
+    }
+
+    values
+}
+
+fn flatten_value(key: String, value: Value, values: &mut BTreeMap<String, String>) {
+    if let Ok(table) = value.clone().into_table() {
+        for (child, value) in table {
+            flatten_value(format!("{}.{}", key, child), value, values);
+        }
+    } else if let Ok(array) = value.clone().into_array() {
+        for (index, value) in array.into_iter().enumerate() {
+            flatten_value(format!("{}[{}]", key, index), value, values);
+        }
+    } else {
+        values.insert(key, value.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::general::General;
+    use crate::config::io::Io;
+
+    #[test]
+    fn test_env_overrides() {
+        // The process environment is shared with the tests running alongside, so it stays as is.
+        let vars = vec![
+            (String::from("IC_GENERAL__TEAM_ID"), String::from("12")),
+            (
+                String::from("IC_IO__PUBLISHER__HOST"),
+                String::from("broker"),
+            ),
+        ];
+
+        let general = General::with_env("config", "general.toml", vars.clone()).unwrap();
+        assert_eq!(general.team_id, 12);
+
+        let io = Io::with_env("config", "io.toml", vars).unwrap();
+        assert_eq!(io.publisher.host, "broker");
+        assert_eq!(io.subscriber.host, "broker.0f.nl");
+    }
+
+    #[test]
+    fn test_flatten() {
+        let mut s = Config::new();
+        s.set("publisher.host", "localhost").unwrap();
+        s.set("publisher.port", 1883).unwrap();
+
+        let values = flatten(s.collect().unwrap());
+
+        assert_eq!(values["publisher.host"], "localhost");
+        assert_eq!(values["publisher.port"], "1883");
+    }
+}
@@ -12,7 +12,6 @@ pub struct Component {
 pub struct Group {
     pub kind: String,
     pub id: i32,
-    pub can_be_blocked: Option<bool>,
     pub components: Option<Vec<Component>>,
 }
 
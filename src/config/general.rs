@@ -3,6 +3,8 @@ use crate::config::config_file::ConfigFile;
 #[derive(Deserialize)]
 pub struct General {
     pub team_id: i32,
+    pub max_disconnect_time: i32,
+    pub takeover_delay: Option<i32>,
 }
 
 impl<'s> ConfigFile<'s> for General {
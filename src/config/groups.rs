@@ -5,6 +5,19 @@ pub struct Group {
     pub kind: String,
     pub min_go_time: i32,
     pub min_transition_time: i32,
+    pub max_go_time: Option<i32>,
+    pub gap_time: Option<i32>,
+    pub max_wait: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct Override {
+    pub group: String,
+    pub min_go_time: Option<i32>,
+    pub min_transition_time: Option<i32>,
+    pub max_go_time: Option<i32>,
+    pub gap_time: Option<i32>,
+    pub max_wait: Option<i32>,
 }
 
 #[derive(Deserialize)]
@@ -12,10 +25,18 @@ pub struct General {
     pub min_evac_time: i32,
 }
 
+#[derive(Deserialize)]
+pub struct Strategy {
+    pub name: String,
+    pub max_wait: Option<i32>,
+}
+
 #[derive(Deserialize)]
 pub struct Groups {
     pub groups: Vec<Group>,
     pub general: General,
+    pub strategy: Option<Strategy>,
+    pub overrides: Option<Vec<Override>>,
 }
 
 impl<'s> ConfigFile<'s> for Groups {
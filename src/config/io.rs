@@ -1,17 +1,41 @@
 use crate::config::config_file::ConfigFile;
 
-#[derive(Deserialize)]
+/// Certificates used when connecting over a TLS protocol. The broker certificate is always
+/// verified against the CA and the configured host name, the MQTT library can't skip either.
+#[derive(Deserialize, Clone)]
+pub struct Tls {
+    pub ca_file: String,
+    pub client_cert_file: Option<String>,
+    pub client_key_file: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
 pub struct MqConnection {
     pub client_id: String,
     pub host: String,
     pub protocol: String,
     pub qos: i32,
+    pub keep_alive: Option<i32>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub clean_session: Option<bool>,
+    pub reconnect: Option<String>,
+    pub reconnect_delay: Option<i32>,
+    pub max_in_flight: Option<i32>,
+    pub tls: Option<Tls>,
+}
+
+/// Where the metrics are served over HTTP.
+#[derive(Deserialize)]
+pub struct Metrics {
+    pub address: String,
 }
 
 #[derive(Deserialize)]
 pub struct Io {
     pub publisher: MqConnection,
     pub subscriber: MqConnection,
+    pub metrics: Option<Metrics>,
 }
 
 impl<'s> ConfigFile<'s> for Io {
@@ -0,0 +1,19 @@
+use crate::config::config_file::ConfigFile;
+
+#[derive(Deserialize)]
+pub struct Jam {
+    pub sensors: Vec<String>,
+    pub threshold: i32,
+    pub release: Option<i32>,
+    pub blocks: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Jams {
+    #[serde(default)]
+    pub jams: Vec<Jam>,
+}
+
+impl<'s> ConfigFile<'s> for Jams {
+    type Output = Jams;
+}
@@ -1,29 +1,39 @@
 use conf::ConfigError;
 
 use crate::config::blocks::Blocks;
+use crate::config::bridge_sequence::BridgeSequence;
 use crate::config::config_file::ConfigFile;
 use crate::config::definitions::Definitions;
 use crate::config::general::General;
 use crate::config::groups::Groups;
 use crate::config::io::Io;
+use crate::config::jams::Jams;
 use crate::config::protocols::Protocols;
+use crate::config::simulation::Simulation;
 
 pub mod blocks;
+pub mod bridge_sequence;
 mod config_file;
 pub mod definitions;
 pub mod general;
 pub mod groups;
 pub mod io;
+pub mod jams;
 pub mod protocols;
+pub mod simulation;
+pub mod validator;
 
 pub struct Config {
     pub traffic_lights_blocks: Blocks,
     pub traffic_lights: Definitions,
     pub bridge: Definitions,
+    pub bridge_sequence: BridgeSequence,
     pub general: General,
     pub groups: Groups,
+    pub jams: Jams,
     pub io: Io,
     pub protocols: Protocols,
+    pub simulation: Simulation,
 }
 
 impl Config {
@@ -32,10 +42,13 @@ impl Config {
             traffic_lights_blocks: Blocks::new(dir, "blocks.toml")?,
             traffic_lights: Definitions::new(dir, "traffic_lights.toml")?,
             bridge: Definitions::new(dir, "bridge.toml")?,
+            bridge_sequence: BridgeSequence::new(dir, "bridge_sequence.toml")?,
             general: General::new(dir, "general.toml")?,
             groups: Groups::new(dir, "groups.toml")?,
+            jams: Jams::new(dir, "jams.toml")?,
             io: Io::new(dir, "io.toml")?,
             protocols: Protocols::new(dir, "protocols.toml")?,
+            simulation: Simulation::new(dir, "simulation.toml")?,
         })
     }
 }
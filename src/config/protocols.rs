@@ -4,6 +4,7 @@ use crate::config::config_file::ConfigFile;
 pub struct Protocol {
     pub name: String,
     pub port: i32,
+    pub tls: Option<bool>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -0,0 +1,25 @@
+use crate::config::config_file::ConfigFile;
+
+#[derive(Deserialize, Clone)]
+pub struct General {
+    pub duration: i32,
+    pub seed: Option<u64>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Arrivals {
+    pub kind: String,
+    pub rate: f64,
+    pub departure_time: i32,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Simulation {
+    pub general: General,
+    #[serde(default)]
+    pub arrivals: Vec<Arrivals>,
+}
+
+impl<'s> ConfigFile<'s> for Simulation {
+    type Output = Simulation;
+}
@@ -0,0 +1,778 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::SocketAddr;
+
+use crate::config::blocks::Blocks;
+use crate::config::bridge_sequence::BridgeSequence;
+use crate::config::definitions::{Component, Definitions};
+use crate::config::groups::Groups;
+use crate::config::jams::Jams;
+use crate::config::simulation::Simulation;
+use crate::config::Config;
+use crate::core::bridge_runner;
+use crate::intersections::component::{ComponentId, ComponentKind, ComponentUid};
+use crate::intersections::deck::DeckState;
+use crate::intersections::gate::GateState;
+use crate::intersections::group::{GroupId, GroupKind};
+use crate::intersections::light::LightState;
+use crate::intersections::sensor::SensorState;
+
+/// A problem found in one of the configuration files.
+pub struct Problem {
+    pub file: &'static str,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.file, self.path, self.message)
+    }
+}
+
+/// The ids of the valid groups and components of a definitions file.
+#[derive(Default)]
+struct Ids {
+    groups: HashSet<GroupId>,
+    components: HashSet<ComponentUid>,
+}
+
+/// Checks the configuration as a whole and reports every problem found, so they can be fixed in
+/// one go instead of one failed startup at a time.
+pub fn validate(config: &Config) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    let traffic_lights =
+        validate_definitions("traffic_lights.toml", &config.traffic_lights, &mut problems);
+    let bridge = validate_definitions("bridge.toml", &config.bridge, &mut problems);
+    validate_blocks(
+        &config.traffic_lights_blocks,
+        &traffic_lights.groups,
+        &mut problems,
+    );
+    validate_timings(&config.groups, &traffic_lights.groups, &mut problems);
+    validate_jams(&config.jams, &traffic_lights, &mut problems);
+    validate_bridge_sequence(&config.bridge_sequence, &bridge, &mut problems);
+    validate_simulation(&config.simulation, &mut problems);
+
+    let general = &config.general;
+    validate_time(
+        "general.toml",
+        "general",
+        "max_disconnect_time",
+        Some(general.max_disconnect_time),
+        &mut problems,
+    );
+    validate_time(
+        "general.toml",
+        "general",
+        "takeover_delay",
+        general.takeover_delay,
+        &mut problems,
+    );
+
+    if let Some(metrics) = &config.io.metrics {
+        if let Err(e) = metrics.address.parse::<SocketAddr>() {
+            problems.push(problem("io.toml", "metrics.address", e));
+        }
+    }
+
+    problems
+}
+
+/// Validates a definitions file and returns the ids of its valid groups and components.
+fn validate_definitions(
+    file: &'static str,
+    defs: &Definitions,
+    problems: &mut Vec<Problem>,
+) -> Ids {
+    let mut ids = Ids::default();
+
+    for (index, group) in defs.groups.iter().enumerate() {
+        let path = format!("groups[{}] {}/{}", index, group.kind, group.id);
+
+        let kind = match GroupKind::try_from(&group.kind[..]) {
+            Ok(kind) => kind,
+            Err(e) => {
+                problems.push(problem(file, path, e));
+                continue;
+            }
+        };
+
+        let group_id = GroupId { kind, id: group.id };
+
+        if !ids.groups.insert(group_id) {
+            problems.push(problem(file, path.clone(), "duplicate group id"));
+        }
+
+        let mut component_ids = HashSet::new();
+
+        for (index, component) in group.components.iter().flatten().enumerate() {
+            let path = format!(
+                "{}/components[{}] {}/{}",
+                path, index, component.kind, component.id
+            );
+
+            match ComponentKind::try_from(&component.kind[..]) {
+                Ok(kind) => {
+                    if !component_ids.insert((kind, component.id)) {
+                        problems.push(problem(file, path.clone(), "duplicate component id"));
+                    }
+
+                    ids.components.insert(ComponentUid {
+                        group_id,
+                        component_id: ComponentId {
+                            kind,
+                            id: component.id,
+                        },
+                    });
+
+                    validate_component(file, &path, kind, component, problems);
+                }
+                Err(e) => problems.push(problem(file, path, e)),
+            }
+        }
+    }
+
+    ids
+}
+
+fn validate_component(
+    file: &'static str,
+    path: &str,
+    kind: ComponentKind,
+    component: &Component,
+    problems: &mut Vec<Problem>,
+) {
+    if component.distance.is_some() && kind != ComponentKind::Sensor {
+        problems.push(problem(file, path, "distance is only allowed on sensors"));
+    }
+
+    if let Some(state) = component.initial_state {
+        let result = match kind {
+            ComponentKind::Sensor => SensorState::try_from(state).map(|_| ()),
+            ComponentKind::Light => LightState::try_from(state).map(|_| ()),
+            ComponentKind::Gate => GateState::try_from(state).map(|_| ()),
+            ComponentKind::Deck => DeckState::try_from(state).map(|_| ()),
+        };
+
+        if let Err(e) = result {
+            problems.push(problem(file, path, format!("initial_state: {}", e)));
+        }
+    }
+}
+
+fn validate_blocks(blocks: &Blocks, groups: &HashSet<GroupId>, problems: &mut Vec<Problem>) {
+    let file = "blocks.toml";
+
+    let mut relations = HashSet::new();
+    let mut paths = vec![];
+
+    for (index, group) in blocks.groups.iter().enumerate() {
+        let path = format!("groups[{}] {}/{}", index, group.kind, group.id);
+
+        let id = match group_id(&group.kind, group.id) {
+            Some(id) if groups.contains(&id) => id,
+            _ => {
+                problems.push(problem(file, path, "unknown group"));
+                continue;
+            }
+        };
+
+        for (index, block) in group.blocks.iter().enumerate() {
+            let path = format!("{}/blocks[{}] {}/{}", path, index, block.kind, block.id);
+
+            validate_time(file, &path, "clearance", block.clearance, problems);
+
+            match group_id(&block.kind, block.id) {
+                Some(block) if groups.contains(&block) => {
+                    relations.insert((id, block));
+                    paths.push((id, block, path));
+                }
+                _ => problems.push(problem(file, path, "unknown group")),
+            }
+        }
+    }
+
+    for (id, block, path) in paths {
+        if !relations.contains(&(block, id)) {
+            problems.push(problem(
+                file,
+                path,
+                format!("{} blocks {}, but not the other way around", id, block),
+            ));
+        }
+    }
+}
+
+fn validate_timings(config: &Groups, groups: &HashSet<GroupId>, problems: &mut Vec<Problem>) {
+    let file = "groups.toml";
+
+    let mut kinds = HashSet::new();
+
+    for (index, group) in config.groups.iter().enumerate() {
+        let path = format!("groups[{}] {}", index, group.kind);
+
+        validate_time(
+            file,
+            &path,
+            "min_go_time",
+            Some(group.min_go_time),
+            problems,
+        );
+        validate_time(
+            file,
+            &path,
+            "min_transition_time",
+            Some(group.min_transition_time),
+            problems,
+        );
+        validate_time(file, &path, "max_go_time", group.max_go_time, problems);
+        validate_gap_time(file, &path, group.gap_time, problems);
+        validate_time(file, &path, "max_wait", group.max_wait, problems);
+
+        match GroupKind::try_from(&group.kind[..]) {
+            Ok(kind) => {
+                if !kinds.insert(kind) {
+                    problems.push(problem(file, path, "duplicate group kind"));
+                }
+            }
+            Err(e) => problems.push(problem(file, path, e)),
+        }
+    }
+
+    let mut overridden = HashSet::new();
+    let mut complete = HashSet::new();
+
+    for (index, group) in config.overrides.iter().flatten().enumerate() {
+        let path = format!("overrides[{}] {}", index, group.group);
+
+        validate_time(file, &path, "min_go_time", group.min_go_time, problems);
+        validate_time(
+            file,
+            &path,
+            "min_transition_time",
+            group.min_transition_time,
+            problems,
+        );
+        validate_time(file, &path, "max_go_time", group.max_go_time, problems);
+        validate_gap_time(file, &path, group.gap_time, problems);
+        validate_time(file, &path, "max_wait", group.max_wait, problems);
+
+        match GroupId::try_from(&group.group[..]) {
+            Ok(id) if !groups.contains(&id) => problems.push(problem(file, path, "unknown group")),
+            Ok(id) => {
+                if !overridden.insert(id) {
+                    problems.push(problem(file, path.clone(), "duplicate override"));
+                }
+
+                if group.min_go_time.is_some() && group.min_transition_time.is_some() {
+                    complete.insert(id);
+                }
+            }
+            Err(e) => problems.push(problem(file, path, e)),
+        }
+    }
+
+    validate_time(
+        file,
+        "general",
+        "min_evac_time",
+        Some(config.general.min_evac_time),
+        problems,
+    );
+
+    let mut missing: Vec<GroupKind> = groups
+        .iter()
+        .filter(|id| !kinds.contains(&id.kind) && !complete.contains(id))
+        .map(|id| id.kind)
+        .collect::<HashSet<GroupKind>>()
+        .into_iter()
+        .collect();
+    missing.sort_by_key(|kind| kind.to_string());
+
+    for kind in missing {
+        problems.push(problem(
+            file,
+            String::from("groups"),
+            format!("no timings for group kind {}, which is in use", kind),
+        ));
+    }
+}
+
+fn validate_jams(config: &Jams, ids: &Ids, problems: &mut Vec<Problem>) {
+    let file = "jams.toml";
+
+    for (index, jam) in config.jams.iter().enumerate() {
+        let path = format!("jams[{}]", index);
+
+        validate_time(file, &path, "threshold", Some(jam.threshold), problems);
+        validate_time(file, &path, "release", jam.release, problems);
+
+        for (index, sensor) in jam.sensors.iter().enumerate() {
+            let path = format!("{}/sensors[{}] {}", path, index, sensor);
+            validate_sensor(file, path, sensor, ids, problems);
+        }
+
+        for (index, group) in jam.blocks.iter().enumerate() {
+            let path = format!("{}/blocks[{}] {}", path, index, group);
+            validate_group(file, path, group, ids, problems);
+        }
+    }
+}
+
+fn validate_bridge_sequence(config: &BridgeSequence, ids: &Ids, problems: &mut Vec<Problem>) {
+    let file = "bridge_sequence.toml";
+
+    for (index, group) in config.general.triggers.iter().enumerate() {
+        let path = format!("general/triggers[{}] {}", index, group);
+        validate_group(file, path, group, ids, problems);
+    }
+
+    for (index, step) in config.steps.iter().enumerate() {
+        let path = format!("steps[{}] {}", index, step.action);
+
+        // The components are only looked up once the step parses like the bridge runner does it.
+        if let Err(errors) = bridge_runner::parse_step(index, step) {
+            for e in errors {
+                problems.push(problem(file, path.clone(), e));
+            }
+
+            continue;
+        }
+
+        for (index, component) in step.components.iter().flatten().enumerate() {
+            let path = format!("{}/components[{}] {}", path, index, component);
+
+            match ComponentUid::try_from(&component[..]) {
+                Ok(uid) if !ids.components.contains(&uid) => {
+                    problems.push(problem(file, path, "unknown component"))
+                }
+                Ok(_) => {}
+                Err(e) => problems.push(problem(file, path, e)),
+            }
+        }
+
+        if let Some(sensor) = &step.sensor {
+            let path = format!("{}/sensor {}", path, sensor);
+            validate_sensor(file, path, sensor, ids, problems);
+        }
+
+        for (index, group) in step.groups.iter().flatten().enumerate() {
+            let path = format!("{}/groups[{}] {}", path, index, group);
+            validate_group(file, path, group, ids, problems);
+        }
+    }
+}
+
+fn validate_sensor(
+    file: &'static str,
+    path: String,
+    sensor: &str,
+    ids: &Ids,
+    problems: &mut Vec<Problem>,
+) {
+    match ComponentUid::try_from(sensor) {
+        Ok(uid) if uid.component_id.kind != ComponentKind::Sensor => {
+            problems.push(problem(file, path, "not a sensor"))
+        }
+        Ok(uid) if !ids.components.contains(&uid) => {
+            problems.push(problem(file, path, "unknown sensor"))
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(problem(file, path, e)),
+    }
+}
+
+fn validate_group(
+    file: &'static str,
+    path: String,
+    group: &str,
+    ids: &Ids,
+    problems: &mut Vec<Problem>,
+) {
+    match GroupId::try_from(group) {
+        Ok(id) if !ids.groups.contains(&id) => problems.push(problem(file, path, "unknown group")),
+        Ok(_) => {}
+        Err(e) => problems.push(problem(file, path, e)),
+    }
+}
+
+/// Times are cast to unsigned durations, so a negative one would wrap around.
+fn validate_time(
+    file: &'static str,
+    path: &str,
+    name: &str,
+    time: Option<i32>,
+    problems: &mut Vec<Problem>,
+) {
+    match time {
+        Some(time) if time < 0 => {
+            problems.push(problem(file, path, format!("{} must be 0 or more", name)))
+        }
+        _ => {}
+    }
+}
+
+/// Green is extended for as long as traffic arrives within the gap, so it can't be 0.
+fn validate_gap_time(
+    file: &'static str,
+    path: &str,
+    gap_time: Option<i32>,
+    problems: &mut Vec<Problem>,
+) {
+    match gap_time {
+        Some(gap_time) if gap_time < 1 => {
+            problems.push(problem(file, path, "gap_time must be more than 0"))
+        }
+        _ => {}
+    }
+}
+
+fn validate_simulation(config: &Simulation, problems: &mut Vec<Problem>) {
+    let file = "simulation.toml";
+
+    if config.general.duration < 1 {
+        problems.push(problem(file, "general", "duration must be at least 1"));
+    }
+
+    let mut kinds = HashSet::new();
+
+    for (index, arrivals) in config.arrivals.iter().enumerate() {
+        let path = format!("arrivals[{}] {}", index, arrivals.kind);
+
+        match GroupKind::try_from(&arrivals.kind[..]) {
+            Ok(kind) => {
+                if !kinds.insert(kind) {
+                    problems.push(problem(file, path.clone(), "duplicate group kind"));
+                }
+            }
+            Err(e) => problems.push(problem(file, path.clone(), e)),
+        }
+
+        if !arrivals.rate.is_finite() || arrivals.rate < 0.0 {
+            problems.push(problem(file, path.clone(), "rate must be 0 or more"));
+        }
+
+        if arrivals.departure_time < 0 {
+            problems.push(problem(file, path, "departure_time must be 0 or more"));
+        }
+    }
+}
+
+fn group_id(kind: &str, id: i32) -> Option<GroupId> {
+    GroupKind::try_from(kind)
+        .ok()
+        .map(|kind| GroupId { kind, id })
+}
+
+fn problem(file: &'static str, path: impl Into<String>, message: impl fmt::Display) -> Problem {
+    Problem {
+        file,
+        path: path.into(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::blocks::{Block, Group as BlocksGroup};
+    use crate::config::bridge_sequence::{General as SequenceGeneral, Step};
+    use crate::config::definitions::Group as DefinitionsGroup;
+    use crate::config::groups::{General as GroupsGeneral, Group as TimingsGroup, Override};
+    use crate::config::jams::Jam;
+
+    fn definitions() -> Definitions {
+        let component = |kind: &str, distance| Component {
+            kind: String::from(kind),
+            id: 1,
+            distance,
+            initial_state: None,
+        };
+        let group = |id, components| DefinitionsGroup {
+            kind: String::from("motor_vehicle"),
+            id,
+            components: Some(components),
+        };
+
+        Definitions {
+            groups: vec![
+                group(
+                    1,
+                    vec![component("light", Some(10)), component("sensor", None)],
+                ),
+                group(
+                    2,
+                    vec![component("sensor", None), component("sensor", None)],
+                ),
+            ],
+        }
+    }
+
+    fn paths(problems: Vec<Problem>) -> Vec<String> {
+        problems.into_iter().map(|p| p.path).collect()
+    }
+
+    #[test]
+    fn test_invalid_components() {
+        let mut problems = vec![];
+        validate_definitions("traffic_lights.toml", &definitions(), &mut problems);
+
+        assert_eq!(
+            paths(problems),
+            vec![
+                "groups[0] motor_vehicle/1/components[0] light/1",
+                "groups[1] motor_vehicle/2/components[1] sensor/1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_blocks() {
+        let block = |id| Block {
+            kind: String::from("motor_vehicle"),
+            id,
+            clearance: None,
+        };
+        let blocks = Blocks {
+            groups: vec![BlocksGroup {
+                kind: String::from("motor_vehicle"),
+                id: 1,
+                blocks: vec![block(2), block(3)],
+            }],
+        };
+
+        let mut problems = vec![];
+        let ids = validate_definitions("traffic_lights.toml", &definitions(), &mut problems);
+
+        let mut problems = vec![];
+        validate_blocks(&blocks, &ids.groups, &mut problems);
+
+        assert_eq!(
+            paths(problems),
+            vec![
+                "groups[0] motor_vehicle/1/blocks[1] motor_vehicle/3",
+                "groups[0] motor_vehicle/1/blocks[0] motor_vehicle/2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negative_times() {
+        let groups = Groups {
+            groups: vec![TimingsGroup {
+                kind: String::from("motor_vehicle"),
+                min_go_time: 7_000,
+                min_transition_time: -1,
+                max_go_time: None,
+                gap_time: Some(0),
+                max_wait: None,
+            }],
+            general: GroupsGeneral { min_evac_time: -1 },
+            strategy: None,
+            overrides: Some(vec![Override {
+                group: String::from("motor_vehicle/1"),
+                min_go_time: None,
+                min_transition_time: None,
+                max_go_time: Some(-1),
+                gap_time: Some(-1),
+                max_wait: Some(0),
+            }]),
+        };
+        let blocks = Blocks {
+            groups: vec![BlocksGroup {
+                kind: String::from("motor_vehicle"),
+                id: 1,
+                blocks: vec![Block {
+                    kind: String::from("motor_vehicle"),
+                    id: 1,
+                    clearance: Some(-1),
+                }],
+            }],
+        };
+        let jams = Jams {
+            jams: vec![Jam {
+                sensors: vec![],
+                threshold: -1,
+                release: Some(-1),
+                blocks: vec![],
+            }],
+        };
+
+        let mut problems = vec![];
+        let ids = validate_definitions("traffic_lights.toml", &definitions(), &mut problems);
+
+        let mut problems = vec![];
+        validate_timings(&groups, &ids.groups, &mut problems);
+        validate_blocks(&blocks, &ids.groups, &mut problems);
+        validate_jams(&jams, &ids, &mut problems);
+
+        let messages: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "groups.toml: groups[0] motor_vehicle: min_transition_time must be 0 or more",
+                "groups.toml: groups[0] motor_vehicle: gap_time must be more than 0",
+                "groups.toml: overrides[0] motor_vehicle/1: max_go_time must be 0 or more",
+                "groups.toml: overrides[0] motor_vehicle/1: gap_time must be more than 0",
+                "groups.toml: general: min_evac_time must be 0 or more",
+                "blocks.toml: groups[0] motor_vehicle/1/blocks[0] motor_vehicle/1: \
+                 clearance must be 0 or more",
+                "jams.toml: jams[0]: threshold must be 0 or more",
+                "jams.toml: jams[0]: release must be 0 or more",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_jams() {
+        let strings = |values: &[&str]| values.iter().map(|v| String::from(*v)).collect();
+        let jams = Jams {
+            jams: vec![Jam {
+                sensors: strings(&[
+                    "motor_vehicle/1/sensor/1",
+                    "motor_vehicle/1/light/1",
+                    "motor_vehicle/3/sensor/1",
+                ]),
+                threshold: 3_000,
+                release: None,
+                blocks: strings(&["motor_vehicle/2", "motor_vehicle/4"]),
+            }],
+        };
+
+        let mut problems = vec![];
+        let ids = validate_definitions("traffic_lights.toml", &definitions(), &mut problems);
+
+        let mut problems = vec![];
+        validate_jams(&jams, &ids, &mut problems);
+
+        assert!(problems.iter().all(|p| p.file == "jams.toml"));
+        assert_eq!(
+            paths(problems),
+            vec![
+                "jams[0]/sensors[1] motor_vehicle/1/light/1",
+                "jams[0]/sensors[2] motor_vehicle/3/sensor/1",
+                "jams[0]/blocks[1] motor_vehicle/4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_bridge_sequence() {
+        let strings = |values: &[&str]| Some(values.iter().map(|v| String::from(*v)).collect());
+        let step = |action: &str| Step {
+            action: String::from(action),
+            components: None,
+            state: None,
+            duration: None,
+            sensor: None,
+            groups: None,
+        };
+        let sequence = BridgeSequence {
+            general: SequenceGeneral {
+                triggers: vec![String::from("motor_vehicle/1"), String::from("vessel/1")],
+            },
+            steps: vec![
+                Step {
+                    components: strings(&["motor_vehicle/1/light/1", "motor_vehicle/2/sensor/1"]),
+                    state: Some(1),
+                    ..step("set")
+                },
+                Step {
+                    sensor: Some(String::from("motor_vehicle/2/sensor/2")),
+                    state: Some(0),
+                    ..step("wait_until")
+                },
+                Step {
+                    groups: strings(&["motor_vehicle/2", "motor_vehicle/3"]),
+                    sensor: Some(String::from("motor_vehicle/1/sensor/1")),
+                    ..step("pass_vessels")
+                },
+            ],
+        };
+
+        let mut problems = vec![];
+        let ids = validate_definitions("bridge.toml", &definitions(), &mut problems);
+
+        let mut problems = vec![];
+        validate_bridge_sequence(&sequence, &ids, &mut problems);
+
+        assert!(problems.iter().all(|p| p.file == "bridge_sequence.toml"));
+        assert_eq!(
+            paths(problems),
+            vec![
+                "general/triggers[1] vessel/1",
+                "steps[0] set",
+                "steps[1] wait_until/sensor motor_vehicle/2/sensor/2",
+                "steps[2] pass_vessels/groups[1] motor_vehicle/3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_bridge_steps() {
+        let step = |action: &str| Step {
+            action: String::from(action),
+            components: None,
+            state: None,
+            duration: None,
+            sensor: None,
+            groups: None,
+        };
+        let sequence = BridgeSequence {
+            general: SequenceGeneral { triggers: vec![] },
+            steps: vec![
+                step("fly"),
+                Step {
+                    components: Some(vec![String::from("motor_vehicle/1/light/1")]),
+                    state: Some(7),
+                    ..step("set")
+                },
+                Step {
+                    duration: Some(-1),
+                    ..step("wait")
+                },
+                Step {
+                    sensor: Some(String::from("motor_vehicle/1/light/1")),
+                    state: Some(2),
+                    ..step("wait_until")
+                },
+                step("pass_vessels"),
+                Step {
+                    sensor: Some(String::from("motor_vehicle/1/sensor/1")),
+                    duration: Some(1_000),
+                    ..step("wait_for_passage")
+                },
+            ],
+        };
+
+        let mut problems = vec![];
+        let ids = validate_definitions("bridge.toml", &definitions(), &mut problems);
+
+        let mut problems = vec![];
+        validate_bridge_sequence(&sequence, &ids, &mut problems);
+
+        assert_eq!(
+            paths(problems),
+            vec![
+                "steps[0] fly",
+                "steps[1] set",
+                "steps[2] wait",
+                "steps[3] wait_until",
+                "steps[3] wait_until",
+                "steps[4] pass_vessels",
+                "steps[4] pass_vessels",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let config = Config::new("config").unwrap();
+        let problems: Vec<String> = validate(&config).iter().map(|p| p.to_string()).collect();
+
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+}
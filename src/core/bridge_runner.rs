@@ -1,20 +1,278 @@
+use std::convert::TryFrom;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::Acquire;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crossbeam_channel::{after, Receiver};
+use crossbeam_channel::{Receiver, Select};
+use failure::Fail;
 
-use crate::intersections::component::{Component, ComponentKind, ComponentUid as Uid};
+use crate::clock::ArcClock;
+use crate::config::bridge_sequence::{BridgeSequence, Step as ConfigStep};
+use crate::error::{Error, RwLockExt};
+use crate::intersections::actuator::ArcActuator;
+use crate::intersections::component::{Component, ComponentKind, ComponentUid};
 use crate::intersections::deck::DeckState;
 use crate::intersections::gate::GateState;
-use crate::intersections::group::{ArcGroup, GroupId, GroupKind};
+use crate::intersections::group::{ArcGroup, Group, GroupId};
 use crate::intersections::intersection::ArcIntersection;
 use crate::intersections::light::LightState;
-use crate::intersections::sensor::SensorState;
+use crate::intersections::sensor::{ArcSensor, SensorState};
+use crate::metrics::ArcMetrics;
+
+#[derive(Debug, Fail)]
+pub enum BridgeSequenceError {
+    #[fail(
+        display = "Unknown action \"{}\" in bridge sequence step {}",
+        action, step
+    )]
+    UnknownAction { step: usize, action: String },
+
+    #[fail(
+        display = "Bridge sequence step {} ({}) is missing the \"{}\" field",
+        step, action, field
+    )]
+    MissingField {
+        step: usize,
+        action: String,
+        field: &'static str,
+    },
+
+    #[fail(
+        display = "Bridge sequence step {} ({}) can't set {}, it is not an actuator",
+        step, action, uid
+    )]
+    NotAnActuator {
+        step: usize,
+        action: String,
+        uid: ComponentUid,
+    },
+
+    #[fail(
+        display = "Bridge sequence step {} ({}) needs a sensor, got {}",
+        step, action, uid
+    )]
+    NotASensor {
+        step: usize,
+        action: String,
+        uid: ComponentUid,
+    },
+
+    #[fail(
+        display = "Bridge sequence step {} ({}) has a negative duration: {}",
+        step, action, duration
+    )]
+    NegativeDuration {
+        step: usize,
+        action: String,
+        duration: i32,
+    },
+}
+
+/// A bridge sequence step with its fields parsed, before its components are looked up.
+pub enum StepDef {
+    Set(Vec<(ComponentUid, i32)>),
+    Wait(Duration),
+    WaitUntil(ComponentUid, SensorState),
+    WaitForPassage(ComponentUid, Option<Duration>),
+    PassVessels(Vec<GroupId>, ComponentUid),
+}
+
+/// Parses a configured step without an intersection, so the configuration can be checked up front.
+/// Returns every error found in the step.
+pub fn parse_step(index: usize, step: &ConfigStep) -> Result<StepDef, Vec<failure::Error>> {
+    let mut parser = StepParser {
+        index,
+        step,
+        errors: vec![],
+    };
+
+    match parser.parse() {
+        Some(def) if parser.errors.is_empty() => Ok(def),
+        _ => Err(parser.errors),
+    }
+}
+
+/// Parses the fields of a step, collecting the errors instead of stopping at the first.
+struct StepParser<'a> {
+    index: usize,
+    step: &'a ConfigStep,
+    errors: Vec<failure::Error>,
+}
+
+impl<'a> StepParser<'a> {
+    fn parse(&mut self) -> Option<StepDef> {
+        let step = self.step;
+
+        match &step.action[..] {
+            "set" => {
+                let state = self.field("state", step.state);
+                let components = self.field("components", step.components.as_ref());
+                let (state, components) = (state?, components?);
+
+                let targets: Vec<Option<(ComponentUid, i32)>> = components
+                    .iter()
+                    .map(|component| self.target(component, state))
+                    .collect();
+
+                Some(StepDef::Set(targets.into_iter().collect::<Option<_>>()?))
+            }
+            "wait" => {
+                let duration = self.field("duration", step.duration)?;
+
+                Some(StepDef::Wait(self.duration(duration)?))
+            }
+            "wait_until" => {
+                let sensor = self.sensor();
+                let state = self
+                    .field("state", step.state)
+                    .and_then(|state| self.check(SensorState::try_from(state)));
+
+                Some(StepDef::WaitUntil(sensor?, state?))
+            }
+            "wait_for_passage" => {
+                let sensor = self.sensor();
+                let duration = match step.duration {
+                    Some(duration) => self.duration(duration).map(Some),
+                    None => Some(None),
+                };
+
+                Some(StepDef::WaitForPassage(sensor?, duration?))
+            }
+            "pass_vessels" => {
+                let groups = self.groups();
+                let sensor = self.sensor();
+
+                Some(StepDef::PassVessels(groups?, sensor?))
+            }
+            _ => {
+                self.errors.push(
+                    BridgeSequenceError::UnknownAction {
+                        step: self.index + 1,
+                        action: step.action.clone(),
+                    }
+                    .into(),
+                );
+
+                None
+            }
+        }
+    }
+
+    fn target(&mut self, component: &str, state: i32) -> Option<(ComponentUid, i32)> {
+        let uid = self.check(ComponentUid::try_from(component))?;
+
+        let result = match uid.component_id.kind {
+            ComponentKind::Light => LightState::try_from(state).map(|_| ()),
+            ComponentKind::Gate => GateState::try_from(state).map(|_| ()),
+            ComponentKind::Deck => DeckState::try_from(state).map(|_| ()),
+            ComponentKind::Sensor => Err(BridgeSequenceError::NotAnActuator {
+                step: self.index + 1,
+                action: self.step.action.clone(),
+                uid,
+            }
+            .into()),
+        };
+
+        self.check(result).map(|_| (uid, state))
+    }
+
+    fn sensor(&mut self) -> Option<ComponentUid> {
+        let sensor = self.field("sensor", self.step.sensor.as_ref())?;
+        let uid = self.check(ComponentUid::try_from(&sensor[..]))?;
+
+        if uid.component_id.kind != ComponentKind::Sensor {
+            self.errors.push(
+                BridgeSequenceError::NotASensor {
+                    step: self.index + 1,
+                    action: self.step.action.clone(),
+                    uid,
+                }
+                .into(),
+            );
+
+            return None;
+        }
+
+        Some(uid)
+    }
+
+    fn groups(&mut self) -> Option<Vec<GroupId>> {
+        let groups = self.field("groups", self.step.groups.as_ref())?;
+
+        let ids: Vec<Option<GroupId>> = groups
+            .iter()
+            .map(|id| self.check(GroupId::try_from(&id[..])))
+            .collect();
+
+        ids.into_iter().collect()
+    }
+
+    fn duration(&mut self, duration: i32) -> Option<Duration> {
+        if duration < 0 {
+            self.errors.push(
+                BridgeSequenceError::NegativeDuration {
+                    step: self.index + 1,
+                    action: self.step.action.clone(),
+                    duration,
+                }
+                .into(),
+            );
+
+            return None;
+        }
+
+        Some(Duration::from_millis(duration as u64))
+    }
+
+    fn field<T>(&mut self, field: &'static str, value: Option<T>) -> Option<T> {
+        if value.is_none() {
+            self.errors.push(
+                BridgeSequenceError::MissingField {
+                    step: self.index + 1,
+                    action: self.step.action.clone(),
+                    field,
+                }
+                .into(),
+            );
+        }
+
+        value
+    }
+
+    fn check<T>(&mut self, result: Result<T, failure::Error>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.errors.push(e);
+                None
+            }
+        }
+    }
+}
+
+/// An actuator together with the state a `set` step puts it in.
+enum Target {
+    Light(ArcActuator<LightState>, LightState),
+    Gate(ArcActuator<GateState>, GateState),
+    Deck(ArcActuator<DeckState>, DeckState),
+}
+
+/// A bridge sequence step with all of its components resolved.
+enum Step {
+    Set(Vec<Target>),
+    Wait(Duration),
+    WaitUntil(ArcSensor, SensorState),
+    WaitForPassage(ArcSensor, Option<Duration>),
+    PassVessels(Vec<ArcGroup>, ArcSensor),
+}
 
 pub struct BridgeRunner {
     intersection: ArcIntersection,
+    clock: ArcClock,
+    sequence: BridgeSequence,
+    metrics: ArcMetrics,
+
     stop: Arc<AtomicBool>,
     stop_channel: Receiver<()>,
 }
@@ -22,91 +280,44 @@ pub struct BridgeRunner {
 impl BridgeRunner {
     pub fn new(
         intersection: ArcIntersection,
+        sequence: BridgeSequence,
+        metrics: ArcMetrics,
         stop: Arc<AtomicBool>,
         stop_channel: Receiver<()>,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, Error> {
+        let clock = Arc::clone(&intersection.read_checked()?.clock);
+
+        Ok(Self {
             intersection,
+            clock,
+            sequence,
+            metrics,
             stop,
             stop_channel,
-        }
+        })
     }
 
     pub fn run(&self) -> Result<(), failure::Error> {
         info!("Running bridge");
 
-        let above_deck_sensor = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_sensor(Uid::new(GroupKind::Bridge, 1, ComponentKind::Sensor, 1))
-            .unwrap();
+        let mut trigger_ids = vec![];
 
-        let below_deck_sensor = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_sensor(Uid::new(GroupKind::Vessel, 3, ComponentKind::Sensor, 1))
-            .unwrap();
-
-        let light = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_light(Uid::new(GroupKind::Bridge, 1, ComponentKind::Light, 1))
-            .unwrap();
-
-        let front_gate = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_gate(Uid::new(GroupKind::Bridge, 1, ComponentKind::Gate, 1))
-            .unwrap();
-
-        let back_gate = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_gate(Uid::new(GroupKind::Bridge, 1, ComponentKind::Gate, 2))
-            .unwrap();
-
-        let deck = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_deck(Uid::new(GroupKind::Bridge, 1, ComponentKind::Deck, 1))
-            .unwrap();
+        for id in &self.sequence.general.triggers {
+            trigger_ids.push(GroupId::try_from(&id[..])?);
+        }
 
-        let top_vessel = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_group(GroupId {
-                kind: GroupKind::Vessel,
-                id: 1,
-            })
-            .unwrap();
+        let triggers = self.find_groups(&trigger_ids)?;
+        let steps = self.build_steps()?;
 
-        let bottom_vessel = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_group(GroupId {
-                kind: GroupKind::Vessel,
-                id: 2,
-            })
-            .unwrap();
+        let mut trigger_channels: Vec<Receiver<ComponentUid>> = vec![];
 
-        let front_vessel_channel = top_vessel.read().unwrap().sensor_receiver.clone();
-        let bottom_vessel_channel = bottom_vessel.read().unwrap().sensor_receiver.clone();
+        for trigger in &triggers {
+            trigger_channels.push(trigger.read_checked()?.sensor_receiver.clone());
+        }
 
-        loop {
-            if !self.one_vessel_high() {
-                select! {
-                    recv(front_vessel_channel) -> _ => {},
-                    recv(bottom_vessel_channel) -> _ => {},
-                    recv(self.stop_channel) -> _ => {},
-                };
+        'cycle: loop {
+            if !Self::one_group_high(&triggers)? {
+                self.wait_for_update(&trigger_channels);
 
                 if self.stop.load(Acquire) {
                     break;
@@ -115,191 +326,369 @@ impl BridgeRunner {
                 continue;
             }
 
-            light
-                .write()
-                .unwrap()
-                .set_state(LightState::Transitioning)?;
+            info!("Starting a bridge cycle");
 
-            select! {
-                recv(after(Duration::from_secs(4))) -> _ => {},
-                recv(self.stop_channel) -> _ => {},
-            };
-            if self.stop.load(Acquire) {
-                break;
-            }
-
-            light.write().unwrap().set_state(LightState::Prohibit)?;
-
-            select! {
-                recv(after(Duration::from_secs(6))) -> _ => {},
-                recv(self.stop_channel) -> _ => {},
-            };
-            if self.stop.load(Acquire) {
-                break;
-            }
-
-            // Wait for all vehicles to leave the deck.
-            while above_deck_sensor.read().unwrap().state() == SensorState::High {
-                let channel = above_deck_sensor.read().unwrap().receiver.clone();
+            let started = self.clock.now();
 
-                select! {
-                    recv(channel) -> _ => {},
-                    recv(self.stop_channel) -> _ => {},
-                };
-                if self.stop.load(Acquire) {
-                    break;
+            for step in &steps {
+                if !self.run_step(step)? {
+                    break 'cycle;
                 }
             }
 
-            front_gate.write().unwrap().set_state(GateState::Close)?;
-            back_gate.write().unwrap().set_state(GateState::Close)?;
+            self.metrics.bridge_cycled(self.clock.since(started));
+        }
 
-            select! {
-                recv(after(Duration::from_secs(4))) -> _ => {},
-                recv(self.stop_channel) -> _ => {},
-            };
-            if self.stop.load(Acquire) {
-                break;
-            }
+        warn!("Stopping bridge runner");
 
-            deck.write().unwrap().set_state(DeckState::Open)?;
+        Ok(())
+    }
 
-            select! {
-                recv(after(Duration::from_secs(10))) -> _ => {},
-                recv(self.stop_channel) -> _ => {},
-            };
-            if self.stop.load(Acquire) {
-                break;
+    /// Runs a single step, returns `false` when the runner was stopped halfway.
+    fn run_step(&self, step: &Step) -> Result<bool, failure::Error> {
+        match step {
+            Step::Set(targets) => {
+                for target in targets {
+                    match target {
+                        Target::Light(light, state) => light.write_checked()?.set_state(*state)?,
+                        Target::Gate(gate, state) => gate.write_checked()?.set_state(*state)?,
+                        Target::Deck(deck, state) => deck.write_checked()?.set_state(*state)?,
+                    }
+                }
+
+                Ok(true)
             }
+            Step::Wait(duration) => Ok(self.sleep(*duration)),
+            Step::WaitUntil(sensor, state) => Ok(self.wait_for_state(sensor, *state)?),
+            Step::WaitForPassage(sensor, timeout) => Ok(self.wait_for_passage(sensor, *timeout)?),
+            Step::PassVessels(vessels, sensor) => self.pass_vessels(vessels, sensor),
+        }
+    }
 
-            while self.one_vessel_high() && !self.stop.load(Acquire) {
-                for vessel in self.main_vessels() {
-                    if !vessel.read().unwrap().one_sensor_high() {
+    fn pass_vessels(
+        &self,
+        vessels: &[ArcGroup],
+        sensor: &ArcSensor,
+    ) -> Result<bool, failure::Error> {
+        loop {
+            if Self::one_group_high(vessels)? {
+                for vessel in vessels {
+                    if !Group::one_sensor_high(vessel)? {
                         continue;
                     }
 
-                    for light in vessel.read().unwrap().lights.values() {
-                        light.write().unwrap().set_state(LightState::Proceed)?;
-                    }
-
-                    let channel = below_deck_sensor.read().unwrap().receiver.clone();
+                    let lights: Vec<_> = vessel.read_checked()?.lights.values().cloned().collect();
 
-                    while below_deck_sensor.read().unwrap().state() == SensorState::Low {
-                        select! {
-                            recv(channel) -> _ => {},
-                            recv(self.stop_channel) -> _ => {},
-                        };
-                        if self.stop.load(Acquire) {
-                            break;
-                        }
+                    for light in &lights {
+                        light.write_checked()?.set_state(LightState::Proceed)?;
                     }
 
-                    while below_deck_sensor.read().unwrap().state() == SensorState::High {
-                        select! {
-                            recv(channel) -> _ => {},
-                            recv(self.stop_channel) -> _ => {},
-                        };
-                        if self.stop.load(Acquire) {
-                            break;
-                        }
+                    let passed = self.wait_for_passage(sensor, None)?;
+
+                    for light in &lights {
+                        light.write_checked()?.set_state(LightState::Prohibit)?;
                     }
 
-                    for light in vessel.read().unwrap().lights.values() {
-                        light.write().unwrap().set_state(LightState::Prohibit)?;
+                    if !passed {
+                        return Ok(false);
                     }
                 }
+            } else if sensor.read_checked()?.state() == SensorState::High {
+                // A vessel is still below the deck.
+                if !self.wait_for_state(sensor, SensorState::Low)? {
+                    return Ok(false);
+                }
+            } else {
+                return Ok(true);
             }
+        }
+    }
 
-            deck.write().unwrap().set_state(DeckState::Close)?;
+    /// Waits for an update on one of the given channels or for the runner to be stopped.
+    fn wait_for_update(&self, channels: &[Receiver<ComponentUid>]) {
+        let mut select = Select::new();
 
-            select! {
-                recv(after(Duration::from_secs(10))) -> _ => {},
-                recv(self.stop_channel) -> _ => {},
-            };
-            if self.stop.load(Acquire) {
-                break;
-            }
+        for channel in channels {
+            select.recv(channel);
+        }
 
-            front_gate.write().unwrap().set_state(GateState::Open)?;
-            back_gate.write().unwrap().set_state(GateState::Open)?;
+        select.recv(&self.stop_channel);
 
-            select! {
-                recv(after(Duration::from_secs(4))) -> _ => {},
-                recv(self.stop_channel) -> _ => {},
-            };
-            if self.stop.load(Acquire) {
-                break;
+        match channels.get(select.ready()) {
+            Some(channel) => while channel.try_recv().is_ok() {},
+            None => {
+                let _ = self.stop_channel.try_recv();
             }
+        }
+    }
+
+    /// Waits for the given duration, returns `false` when the runner was stopped.
+    fn sleep(&self, duration: Duration) -> bool {
+        select! {
+            recv(self.clock.after(duration)) -> _ => {},
+            recv(self.stop_channel) -> _ => {},
+        };
 
-            light.write().unwrap().set_state(LightState::Proceed)?;
+        !self.stop.load(Acquire)
+    }
 
-            let deck_channel = above_deck_sensor.read().unwrap().receiver.clone();
-            let mut cars = false;
+    /// Waits until the sensor is in the given state, returns `false` when the runner was stopped.
+    fn wait_for_state(&self, sensor: &ArcSensor, state: SensorState) -> Result<bool, Error> {
+        let channel = sensor.read_checked()?.receiver.clone();
 
+        while sensor.read_checked()?.state() != state {
             select! {
-                recv(after(Duration::from_secs(30))) -> _ => {},
-                recv(deck_channel) -> _ => cars = true,
+                recv(channel) -> _ => {},
                 recv(self.stop_channel) -> _ => {},
             };
+
             if self.stop.load(Acquire) {
-                break;
+                return Ok(false);
             }
+        }
 
-            if cars {
-                while above_deck_sensor.read().unwrap().state() == SensorState::Low {
-                    select! {
-                        recv(deck_channel) -> _ => {},
-                        recv(self.stop_channel) -> _ => {},
-                    }
-                    if self.stop.load(Acquire) {
-                        break;
-                    }
+        Ok(true)
+    }
+
+    /// Waits for traffic to pass the sensor, i.e. for the sensor to go high and low again. When a
+    /// timeout is given and no traffic arrives within it, the step ends without waiting further.
+    fn wait_for_passage(
+        &self,
+        sensor: &ArcSensor,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        let channel = sensor.read_checked()?.receiver.clone();
+
+        // Drop stale updates, only changes from here on should count as arriving traffic.
+        while channel.try_recv().is_ok() {}
+
+        if sensor.read_checked()?.state() == SensorState::Low {
+            if let Some(timeout) = timeout {
+                let mut arrived = false;
+
+                select! {
+                    recv(self.clock.after(timeout)) -> _ => {},
+                    recv(channel) -> _ => arrived = true,
+                    recv(self.stop_channel) -> _ => {},
+                };
+
+                if self.stop.load(Acquire) {
+                    return Ok(false);
                 }
 
-                while above_deck_sensor.read().unwrap().state() == SensorState::High {
-                    select! {
-                        recv(deck_channel) -> _ => {},
-                        recv(self.stop_channel) -> _ => {},
-                    }
-                    if self.stop.load(Acquire) {
-                        break;
-                    }
+                if !arrived {
+                    return Ok(true);
                 }
             }
-        }
 
-        warn!("Stopping bridge runner");
+            if !self.wait_for_state(sensor, SensorState::High)? {
+                return Ok(false);
+            }
+        }
 
-        Ok(())
+        self.wait_for_state(sensor, SensorState::Low)
     }
 
-    fn one_vessel_high(&self) -> bool {
-        for group in self.intersection.read().unwrap().groups.values() {
-            if group.read().unwrap().id.kind != GroupKind::Vessel {
-                continue;
+    fn one_group_high(groups: &[ArcGroup]) -> Result<bool, Error> {
+        for group in groups {
+            if Group::one_sensor_high(group)? {
+                return Ok(true);
             }
+        }
 
-            if group.read().unwrap().one_sensor_high() {
-                return true;
-            }
+        Ok(false)
+    }
+
+    fn build_steps(&self) -> Result<Vec<Step>, failure::Error> {
+        let mut steps = vec![];
+
+        for (index, conf_step) in self.sequence.steps.iter().enumerate() {
+            // The validator reports every error, here the first one is enough to stop.
+            let def = parse_step(index, conf_step).map_err(|mut errors| errors.remove(0))?;
+
+            let step = match def {
+                StepDef::Set(components) => {
+                    let mut targets = vec![];
+
+                    for (uid, state) in components {
+                        targets.push(self.build_target(uid, state)?);
+                    }
+
+                    Step::Set(targets)
+                }
+                StepDef::Wait(duration) => Step::Wait(duration),
+                StepDef::WaitUntil(sensor, state) => {
+                    Step::WaitUntil(self.find_sensor(sensor)?, state)
+                }
+                StepDef::WaitForPassage(sensor, timeout) => {
+                    Step::WaitForPassage(self.find_sensor(sensor)?, timeout)
+                }
+                StepDef::PassVessels(groups, sensor) => {
+                    Step::PassVessels(self.find_groups(&groups)?, self.find_sensor(sensor)?)
+                }
+            };
+
+            steps.push(step);
         }
 
-        false
+        Ok(steps)
     }
 
-    fn main_vessels(&self) -> Vec<ArcGroup> {
-        let mut groups: Vec<ArcGroup> = vec![];
+    fn build_target(&self, uid: ComponentUid, state: i32) -> Result<Target, failure::Error> {
+        let intersection = self.intersection.read_checked()?;
+
+        let target = match uid.component_id.kind {
+            ComponentKind::Light => intersection
+                .find_light(uid)?
+                .map(|light| LightState::try_from(state).map(|s| Target::Light(light, s))),
+            ComponentKind::Gate => intersection
+                .find_gate(uid)?
+                .map(|gate| GateState::try_from(state).map(|s| Target::Gate(gate, s))),
+            ComponentKind::Deck => intersection
+                .find_deck(uid)?
+                .map(|deck| DeckState::try_from(state).map(|s| Target::Deck(deck, s))),
+            ComponentKind::Sensor => None,
+        };
+
+        match target {
+            Some(target) => target,
+            None => Err(Error::ComponentNotFound { uid }.into()),
+        }
+    }
 
-        for group in self.intersection.read().unwrap().groups.values() {
-            if group.read().unwrap().id.kind != GroupKind::Vessel {
-                continue;
-            }
+    fn find_sensor(&self, uid: ComponentUid) -> Result<ArcSensor, failure::Error> {
+        match self.intersection.read_checked()?.find_sensor(uid)? {
+            Some(sensor) => Ok(sensor),
+            None => Err(Error::ComponentNotFound { uid }.into()),
+        }
+    }
+
+    fn find_groups(&self, ids: &[GroupId]) -> Result<Vec<ArcGroup>, failure::Error> {
+        let mut groups = vec![];
 
-            if group.read().unwrap().id.id == 1 || group.read().unwrap().id.id == 2 {
-                groups.push(Arc::clone(&group));
+        for &id in ids {
+            match self.intersection.read_checked()?.find_group(id) {
+                Some(group) => groups.push(group),
+                None => return Err(Error::GroupNotFound { id }.into()),
             }
         }
 
-        groups
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering::Release;
+    use std::thread;
+    use std::time::Instant;
+
+    use crossbeam_channel::unbounded;
+
+    use crate::clock::manual::ManualClock;
+    use crate::config::Config;
+    use crate::intersections::group::GroupKind;
+    use crate::intersections::intersection_builder::IntersectionsBuilder;
+    use crate::metrics::Metrics;
+
+    #[test]
+    fn test_cycle_on_manual_clock() {
+        let config = Config::new("config").unwrap();
+        let clock = Arc::new(ManualClock::new());
+
+        let (sender, _receiver) = unbounded();
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_clock(Arc::clone(&clock) as ArcClock)
+            .with_defs(&config.bridge)
+            .finish()
+            .unwrap();
+
+        let uid = |kind, id, component_kind| ComponentUid::new(kind, id, component_kind, 1);
+        let sensor = |uid| {
+            intersection
+                .read()
+                .unwrap()
+                .find_sensor(uid)
+                .unwrap()
+                .unwrap()
+        };
+        let light = |uid| {
+            intersection
+                .read()
+                .unwrap()
+                .find_light(uid)
+                .unwrap()
+                .unwrap()
+        };
+
+        let vessel = sensor(uid(GroupKind::Vessel, 1, ComponentKind::Sensor));
+        let passage = sensor(uid(GroupKind::Vessel, 3, ComponentKind::Sensor));
+        let vessel_light = light(uid(GroupKind::Vessel, 1, ComponentKind::Light));
+        let bridge_light = light(uid(GroupKind::Bridge, 1, ComponentKind::Light));
+        let deck = intersection
+            .read()
+            .unwrap()
+            .find_deck(uid(GroupKind::Bridge, 1, ComponentKind::Deck))
+            .unwrap()
+            .unwrap();
+
+        let (stop_sender, stop_channel) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let runner = BridgeRunner::new(
+            Arc::clone(&intersection),
+            config.bridge_sequence,
+            Arc::new(Metrics::new()),
+            Arc::clone(&stop),
+            stop_channel,
+        )
+        .unwrap();
+        let handle = thread::spawn(move || runner.run());
+
+        let started = Instant::now();
+        let limit = Duration::from_secs(120);
+
+        vessel
+            .write()
+            .unwrap()
+            .set_state(SensorState::High)
+            .unwrap();
+
+        let mut cycle = clock.run_until(limit, || {
+            vessel_light.read().unwrap().state() == LightState::Proceed
+        });
+        assert!(deck.read().unwrap().state() == DeckState::Open);
+
+        // The vessel passes, the runner may only start watching for it after the first try.
+        vessel.write().unwrap().set_state(SensorState::Low).unwrap();
+        cycle += clock.run_until(limit, || {
+            passage
+                .write()
+                .unwrap()
+                .set_state(SensorState::High)
+                .unwrap();
+            thread::sleep(Duration::from_millis(10));
+            passage
+                .write()
+                .unwrap()
+                .set_state(SensorState::Low)
+                .unwrap();
+            thread::sleep(Duration::from_millis(10));
+
+            vessel_light.read().unwrap().state() == LightState::Prohibit
+        });
+
+        cycle += clock.run_until(limit, || {
+            bridge_light.read().unwrap().state() == LightState::Proceed
+        });
+        assert!(deck.read().unwrap().state() == DeckState::Close);
+
+        // Every wait of the sequence, the vessels passed in no time.
+        assert!(cycle >= Duration::from_secs(38));
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        stop.store(true, Release);
+        stop_sender.send(()).unwrap();
+        handle.join().unwrap().unwrap();
     }
 }
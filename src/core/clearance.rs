@@ -0,0 +1,220 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use failure::Fail;
+
+use crate::config::groups::Groups as ConfigGroups;
+use crate::error::{Error, RwLockExt};
+use crate::intersections::component::Component;
+use crate::intersections::group::{ArcGroup, Group};
+use crate::intersections::intersection::Intersection;
+use crate::intersections::light::LightState;
+use crate::intersections::strategies::conflicts;
+
+#[derive(Debug, Fail)]
+#[fail(display = "min_evac_time must be 0 or more, got {}", min_evac_time)]
+pub struct NegativeEvacTime {
+    min_evac_time: i32,
+}
+
+/// Clearance (intergreen) times between conflicting groups. A group may only proceed once every
+/// group it conflicts with has been prohibited for the clearance time from that group to it.
+pub struct Clearances {
+    default: Duration,
+}
+
+impl Clearances {
+    pub fn new(config: &ConfigGroups) -> Result<Self, NegativeEvacTime> {
+        let min_evac_time = config.general.min_evac_time;
+
+        match u64::try_from(min_evac_time) {
+            Ok(default) => Ok(Self {
+                default: Duration::from_millis(default),
+            }),
+            Err(_) => Err(NegativeEvacTime { min_evac_time }),
+        }
+    }
+
+    /// The clearance time needed after `from` stops before `to` may proceed.
+    pub fn clearance(&self, from: &Group, to: &Group) -> Duration {
+        match from.clearances.get(&to.id) {
+            Some(clearance) => *clearance,
+            None => self.default,
+        }
+    }
+
+    /// How long to wait before all of the given groups may proceed.
+    pub fn remaining(
+        &self,
+        intersection: &Intersection,
+        groups: &[ArcGroup],
+    ) -> Result<Duration, Error> {
+        let mut remaining = Duration::from_millis(0);
+
+        for group in groups {
+            for other in intersection.groups() {
+                if !conflicts(group, &other)? {
+                    continue;
+                }
+
+                let clearance = self.clearance(&**other.read_checked()?, &**group.read_checked()?);
+
+                let lights: Vec<_> = other.read_checked()?.lights.values().cloned().collect();
+
+                for light in lights {
+                    let light = light.read_checked()?;
+
+                    // The conflicting light hasn't even started clearing yet.
+                    if light.state() != LightState::Prohibit {
+                        remaining = remaining.max(clearance);
+                        continue;
+                    }
+
+                    let elapsed = intersection.clock.since(light.timestamp());
+
+                    if elapsed < clearance {
+                        remaining = remaining.max(clearance - elapsed);
+                    }
+                }
+            }
+        }
+
+        Ok(remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crossbeam_channel::{unbounded, Receiver};
+
+    use crate::clock::manual::ManualClock;
+    use crate::clock::ArcClock;
+    use crate::config::Config;
+    use crate::intersections::component::{ComponentKind, ComponentUid};
+    use crate::intersections::group::{GroupId, GroupKind};
+    use crate::intersections::intersection::{ArcIntersection, Notification};
+    use crate::intersections::intersection_builder::IntersectionsBuilder;
+
+    /// The receiver keeps the notification channel open, setting a light state fails otherwise.
+    fn setup() -> (
+        Arc<ManualClock>,
+        ArcIntersection,
+        Clearances,
+        Receiver<Notification>,
+    ) {
+        let config = Config::new("config").unwrap();
+        let clock = Arc::new(ManualClock::new());
+
+        let (sender, receiver) = unbounded();
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_clock(Arc::clone(&clock) as ArcClock)
+            .with_defs(&config.traffic_lights)
+            .with_blocks(&config.traffic_lights_blocks)
+            .finish()
+            .unwrap();
+
+        (
+            clock,
+            intersection,
+            Clearances::new(&config.groups).unwrap(),
+            receiver,
+        )
+    }
+
+    fn group(intersection: &ArcIntersection, kind: GroupKind) -> ArcGroup {
+        intersection
+            .read()
+            .unwrap()
+            .find_group(GroupId { kind, id: 1 })
+            .unwrap()
+    }
+
+    fn remaining(clearances: &Clearances, intersection: &ArcIntersection, group: &ArcGroup) -> u64 {
+        let remaining = clearances
+            .remaining(&intersection.read().unwrap(), &[Arc::clone(group)])
+            .unwrap();
+
+        remaining.as_millis() as u64
+    }
+
+    #[test]
+    fn test_default_clearance() {
+        let (clock, intersection, clearances, _receiver) = setup();
+        let foot = group(&intersection, GroupKind::Foot);
+
+        // Every light has just been prohibited, min_evac_time is 1 second.
+        assert_eq!(remaining(&clearances, &intersection, &foot), 1_000);
+
+        clock.advance(Duration::from_millis(400));
+        assert_eq!(remaining(&clearances, &intersection, &foot), 600);
+
+        clock.advance(Duration::from_millis(600));
+        assert_eq!(remaining(&clearances, &intersection, &foot), 0);
+    }
+
+    #[test]
+    fn test_pair_clearance() {
+        let (clock, intersection, clearances, _receiver) = setup();
+        let foot = group(&intersection, GroupKind::Foot);
+        let motor_vehicle = group(&intersection, GroupKind::MotorVehicle);
+
+        let foot_id = foot.read().unwrap().id;
+        motor_vehicle
+            .write()
+            .unwrap()
+            .clearances
+            .insert(foot_id, Duration::from_millis(3_000));
+
+        assert_eq!(remaining(&clearances, &intersection, &foot), 3_000);
+
+        // The other conflicting groups are clear by now, only the configured pair is left.
+        clock.advance(Duration::from_millis(2_000));
+        assert_eq!(remaining(&clearances, &intersection, &foot), 1_000);
+
+        clock.advance(Duration::from_millis(1_000));
+        assert_eq!(remaining(&clearances, &intersection, &foot), 0);
+    }
+
+    #[test]
+    fn test_wait_for_conflicting_light() {
+        let (clock, intersection, clearances, _receiver) = setup();
+        let foot = group(&intersection, GroupKind::Foot);
+
+        let uid = ComponentUid::new(GroupKind::MotorVehicle, 1, ComponentKind::Light, 1);
+        let light = intersection
+            .read()
+            .unwrap()
+            .find_light(uid)
+            .unwrap()
+            .unwrap();
+
+        for state in &[LightState::Proceed, LightState::Transitioning] {
+            light.write().unwrap().set_state(*state).unwrap();
+
+            // The clearance only starts counting once the light is prohibited.
+            clock.advance(Duration::from_millis(5_000));
+            assert_eq!(remaining(&clearances, &intersection, &foot), 1_000);
+        }
+
+        light
+            .write()
+            .unwrap()
+            .set_state(LightState::Prohibit)
+            .unwrap();
+        assert_eq!(remaining(&clearances, &intersection, &foot), 1_000);
+
+        clock.advance(Duration::from_millis(1_000));
+        assert_eq!(remaining(&clearances, &intersection, &foot), 0);
+    }
+
+    #[test]
+    fn test_negative_evac_time() {
+        let mut config = Config::new("config").unwrap();
+        config.groups.general.min_evac_time = -1;
+
+        assert!(Clearances::new(&config.groups).is_err());
+    }
+}
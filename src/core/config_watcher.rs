@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crossbeam_channel::Sender;
+
+/// Polls configuration files and reports when one of them changes.
+pub struct ConfigWatcher {
+    paths: Vec<PathBuf>,
+    sender: Sender<()>,
+}
+
+impl ConfigWatcher {
+    /// Watches the given files, both in the directory and in its local overlay.
+    pub fn new(dir: &str, files: &[&str], sender: Sender<()>) -> Self {
+        let mut paths = vec![];
+
+        for file in files {
+            paths.push([dir, file].iter().collect());
+            paths.push([dir, "local", file].iter().collect());
+        }
+
+        Self { paths, sender }
+    }
+
+    pub fn run(&self) {
+        let mut last = self.modified();
+
+        loop {
+            thread::sleep(Duration::from_secs(1));
+
+            let current = self.modified();
+
+            if current != last {
+                info!("Configuration changed on disk");
+                last = current;
+
+                if self.sender.send(()).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.paths
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
@@ -1,40 +1,190 @@
+use std::collections::BTreeSet;
 use std::convert::TryFrom;
+use std::fmt;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::process;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::Release;
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use chrono::Utc;
+use crossbeam_channel::{tick, unbounded, Receiver, Sender};
+use signal_hook::iterator::Signals;
+use signal_hook::{SIGINT, SIGTERM};
 
+use crate::config::validator;
 use crate::config::Config;
 use crate::core::bridge_runner::BridgeRunner;
+use crate::core::config_watcher::ConfigWatcher;
 use crate::core::message_publisher::{Message, MessagePublisher};
 use crate::core::message_subscriber::MessageSubscriber;
 use crate::core::score_poller::ScorePoller;
 use crate::core::state_publisher::StatePublisher;
 use crate::core::traffic_lights_runner::TrafficLightsRunner;
+use crate::error::{Error, RwLockExt};
 use crate::intersections::component::Component;
+use crate::intersections::group::Group;
 use crate::intersections::intersection::{ArcIntersection, Notification};
+use crate::intersections::intersection_builder::IntersectionsBuilder;
+use crate::intersections::light::LightState;
 use crate::intersections::sensor::SensorState;
 use crate::io::client::Client;
+use crate::io::recording::ArcRecorder;
+use crate::io::topics::command_topic::{Command, CommandTopic};
 use crate::io::topics::component_topic::ComponentTopic;
 use crate::io::topics::lifecycle_topic::{Device, Handler, LifeCycleTopic};
+use crate::io::topics::Topic;
+use crate::metrics::server::MetricsServer;
+use crate::metrics::{ArcMetrics, Metrics};
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid mode: {}", mode)]
+pub struct InvalidMode {
+    mode: String,
+}
+
+#[derive(Debug, Fail)]
+pub enum ReloadError {
+    #[fail(display = "Found {} problem(s) in the configuration", problems)]
+    Invalid { problems: usize },
+
+    #[fail(display = "Groups or components changed, that needs a restart")]
+    TopologyChanged,
+}
+
+/// Which intersections the controller runs.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    All,
+    TrafficLights,
+    Bridge,
+}
+
+impl Mode {
+    pub fn runs_traffic_lights(self) -> bool {
+        self != Mode::Bridge
+    }
+
+    pub fn runs_bridge(self) -> bool {
+        self != Mode::TrafficLights
+    }
+}
+
+impl TryFrom<&str> for Mode {
+    type Error = InvalidMode;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "all" => Ok(Mode::All),
+            "traffic_lights" => Ok(Mode::TrafficLights),
+            "bridge" => Ok(Mode::Bridge),
+            _ => Err(InvalidMode {
+                mode: String::from(value),
+            }),
+        }
+    }
+}
+
+/// How often failed runners are restarted before the controller gives up and degrades.
+const MAX_RUNNER_RESTARTS: u32 = 3;
+
+/// How long a starting instance listens for running ones, unless configured otherwise.
+const DEFAULT_TAKEOVER_DELAY: i32 = 2_000;
+
+/// Why the controller is in degraded mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DegradedReason {
+    Disconnected,
+    SafetyCheck,
+    Operator,
+    RunnerFailed,
+}
+
+impl fmt::Display for DegradedReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DegradedReason::Disconnected => write!(f, "the simulator disconnected for too long"),
+            DegradedReason::SafetyCheck => write!(f, "the safety check failed"),
+            DegradedReason::Operator => write!(f, "an operator requested it"),
+            DegradedReason::RunnerFailed => write!(f, "a runner kept failing"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Runner {
+    TrafficLights,
+    Bridge,
+}
+
+impl fmt::Display for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Runner::TrafficLights => write!(f, "traffic lights"),
+            Runner::Bridge => write!(f, "bridge"),
+        }
+    }
+}
+
+/// A runner thread that ended with an error or a panic instead of being stopped.
+struct RunnerFailure {
+    runner: Runner,
+    generation: u64,
+    error: failure::Error,
+}
 
 pub struct Controller {
+    mode: Mode,
+    config_dir: String,
+
+    /// Identifies this instance in its lifecycle messages, to tell it apart from other instances
+    /// for the same team. It starts with the creation time, so older instances sort first.
+    instance_id: String,
+    /// The other instances of the team that announced themselves and are still around.
+    instances: BTreeSet<String>,
+    /// Whether another instance drives the intersections. The one with the lowest id does.
+    standby: bool,
+    /// Until when a starting instance waits for running instances to answer its announcement.
+    listening_until: Option<Instant>,
+    takeover_delay: Duration,
+
+    team_id: i32,
+    /// Messages dropped because their topic belongs to another team.
+    foreign_messages: u64,
+
     traffic_lights: ArcIntersection,
     bridge: ArcIntersection,
 
+    publisher_sender: Sender<Message>,
     publisher_receiver: Receiver<Message>,
 
     subscriber_sender: Sender<(String, String)>,
     subscriber_receiver: Receiver<(String, String)>,
 
+    reload_sender: Sender<()>,
+    reload_receiver: Receiver<()>,
+    reconnected_sender: Sender<()>,
+    reconnected_receiver: Receiver<()>,
+    config_watcher_handle: Option<JoinHandle<()>>,
+
     message_publisher_handle: Option<JoinHandle<()>>,
+    stop_message_publisher: Sender<()>,
+    stop_message_publisher_receiver: Receiver<()>,
     message_subscriber_handle: Option<JoinHandle<()>>,
+    /// Records the messages passing the publisher and subscriber.
+    recorder: Option<ArcRecorder>,
+
+    metrics: ArcMetrics,
+    /// Where the metrics are served, if anywhere.
+    metrics_address: Option<String>,
 
     state_publisher_handle: Option<JoinHandle<()>>,
     state_publisher: Arc<StatePublisher>,
+    stop_state_publisher: Sender<()>,
 
     score_poller_handle: Option<JoinHandle<()>>,
     score_poller: Arc<ScorePoller>,
@@ -46,7 +196,25 @@ pub struct Controller {
     bridge_runner: Arc<BridgeRunner>,
 
     stop_runners: Arc<AtomicBool>,
-    stop_runners_sender: Sender<()>,
+    stop_runners_senders: Vec<Sender<()>>,
+
+    runner_failure_sender: Sender<RunnerFailure>,
+    runner_failure_receiver: Receiver<RunnerFailure>,
+    /// Changes whenever the runners are stopped, failures of runners stopped since are ignored.
+    runner_generation: u64,
+    /// Failed runners restarted since the simulator connected or degraded mode was left.
+    runner_restarts: u32,
+
+    shutdown_sender: Sender<()>,
+    shutdown_receiver: Receiver<()>,
+
+    max_disconnect_time: Duration,
+    simulator_connected: bool,
+    /// Whether the simulator state comes from its own lifecycle messages rather than from running
+    /// instances being around.
+    simulator_heard: bool,
+    disconnected_since: Option<Instant>,
+    degraded: Option<DegradedReason>,
 }
 
 impl Controller {
@@ -55,32 +223,75 @@ impl Controller {
         bridge: ArcIntersection,
         notification_receiver: Receiver<Notification>,
         config: Config,
-    ) -> Self {
+        config_dir: &str,
+        mode: Mode,
+    ) -> Result<Self, failure::Error> {
         let (publisher_sender, publisher_receiver) = unbounded();
         let (subscriber_sender, subscriber_receiver) = unbounded();
-        let (stop_runners_sender, stop_runners_receiver) = unbounded();
+        let (reload_sender, reload_receiver) = unbounded();
+        let (reconnected_sender, reconnected_receiver) = unbounded();
+        // Every runner gets its own stop channel, so a stop message can't be taken by the other.
+        let (stop_traffic_lights_sender, stop_traffic_lights_receiver) = unbounded();
+        let (stop_bridge_sender, stop_bridge_receiver) = unbounded();
+        let (stop_message_publisher, stop_message_publisher_receiver) = unbounded();
+        let (stop_state_publisher, stop_state_publisher_receiver) = unbounded();
+        let (runner_failure_sender, runner_failure_receiver) = unbounded();
+        let (shutdown_sender, shutdown_receiver) = unbounded();
 
         let stop_runners = Arc::new(AtomicBool::new(false));
+        let metrics = Arc::new(Metrics::new());
+
+        Ok(Self {
+            mode,
+            config_dir: String::from(config_dir),
+
+            instance_id: format!("{:013}-{}", Utc::now().timestamp_millis(), process::id()),
+            instances: BTreeSet::new(),
+            standby: false,
+            listening_until: None,
+            takeover_delay: Duration::from_millis(
+                config
+                    .general
+                    .takeover_delay
+                    .unwrap_or(DEFAULT_TAKEOVER_DELAY) as u64,
+            ),
+
+            team_id: config.general.team_id,
+            foreign_messages: 0,
 
-        Self {
             traffic_lights: Arc::clone(&traffic_lights),
             bridge: Arc::clone(&bridge),
 
+            publisher_sender: publisher_sender.clone(),
             publisher_receiver,
 
             subscriber_sender,
             subscriber_receiver,
 
+            reload_sender,
+            reload_receiver,
+            reconnected_sender,
+            reconnected_receiver,
+            config_watcher_handle: None,
+
             message_publisher_handle: None,
+            stop_message_publisher,
+            stop_message_publisher_receiver,
             message_subscriber_handle: None,
+            recorder: None,
+
+            metrics: Arc::clone(&metrics),
+            metrics_address: config.io.metrics.map(|metrics| metrics.address),
 
             state_publisher_handle: None,
             state_publisher: Arc::new(StatePublisher::new(
                 notification_receiver.clone(),
                 publisher_sender.clone(),
+                stop_state_publisher_receiver,
                 Arc::clone(&traffic_lights),
                 Arc::clone(&bridge),
             )),
+            stop_state_publisher,
 
             score_poller_handle: None,
             score_poller: Arc::new(ScorePoller::new(Arc::clone(&traffic_lights))),
@@ -89,20 +300,48 @@ impl Controller {
             traffic_lights_runner: Arc::new(TrafficLightsRunner::new(
                 Arc::clone(&traffic_lights),
                 config.groups,
+                config.jams,
+                Arc::clone(&metrics),
                 Arc::clone(&stop_runners),
-                stop_runners_receiver.clone(),
-            )),
+                stop_traffic_lights_receiver,
+            )?),
 
             bridge_runner_handle: None,
             bridge_runner: Arc::new(BridgeRunner::new(
                 Arc::clone(&bridge),
+                config.bridge_sequence,
+                Arc::clone(&metrics),
                 Arc::clone(&stop_runners),
-                stop_runners_receiver.clone(),
-            )),
+                stop_bridge_receiver,
+            )?),
 
             stop_runners: Arc::clone(&stop_runners),
-            stop_runners_sender,
-        }
+            stop_runners_senders: vec![stop_traffic_lights_sender, stop_bridge_sender],
+
+            runner_failure_sender,
+            runner_failure_receiver,
+            runner_generation: 0,
+            runner_restarts: 0,
+
+            shutdown_sender,
+            shutdown_receiver,
+
+            max_disconnect_time: Duration::from_millis(config.general.max_disconnect_time as u64),
+            simulator_connected: false,
+            simulator_heard: false,
+            disconnected_since: None,
+            degraded: None,
+        })
+    }
+
+    /// Identifies this instance among the instances of the same team, the oldest sorting first.
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Records every message received and published from now on.
+    pub fn set_recorder(&mut self, recorder: ArcRecorder) {
+        self.recorder = Some(recorder);
     }
 
     pub fn start(
@@ -110,7 +349,12 @@ impl Controller {
         mut publisher: Client,
         mut subscriber: Client,
     ) -> Result<(), failure::Error> {
-        debug!("Starting controller");
+        debug!("Starting controller instance {}", self.instance_id);
+
+        publisher.set_last_will(
+            Box::new(LifeCycleTopic::new(Device::Controller, Handler::Disconnect)),
+            self.instance_id.clone().into_bytes(),
+        )?;
 
         debug!("Starting publisher");
         publisher.start()?;
@@ -118,12 +362,12 @@ impl Controller {
         subscriber.start()?;
 
         debug!("Subscribing to sensor topics");
-        for sensor in self.traffic_lights.read().unwrap().sensors() {
-            subscriber.subscribe(Box::new(ComponentTopic::from(sensor.read().unwrap().uid())))?;
-        }
-
-        for sensor in self.bridge.read().unwrap().sensors() {
-            subscriber.subscribe(Box::new(ComponentTopic::from(sensor.read().unwrap().uid())))?;
+        for intersection in self.intersections() {
+            for sensor in intersection.read_checked()?.sensors()? {
+                subscriber.subscribe(Box::new(ComponentTopic::from(
+                    sensor.read_checked()?.uid()?,
+                )))?;
+            }
         }
 
         debug!("Subscribing to lifecycle topics");
@@ -137,18 +381,53 @@ impl Controller {
             Handler::Disconnect,
         )))?;
 
+        subscriber.subscribe(Box::new(LifeCycleTopic::new(
+            Device::Controller,
+            Handler::Connect,
+        )))?;
+
+        subscriber.subscribe(Box::new(LifeCycleTopic::new(
+            Device::Controller,
+            Handler::Disconnect,
+        )))?;
+
+        debug!("Subscribing to command topics");
+        subscriber.subscribe(Box::new(CommandTopic::new(Command::OutOfOrder)))?;
+        subscriber.subscribe(Box::new(CommandTopic::new(Command::Resume)))?;
+        subscriber.subscribe(Box::new(CommandTopic::new(Command::Reload)))?;
+
         // Publisher
         let publisher_receiver = self.publisher_receiver.clone();
+        let reconnected_sender = self.reconnected_sender.clone();
+        let stop_message_publisher = self.stop_message_publisher_receiver.clone();
+        let recorder = self.recorder.clone();
+        let metrics = Arc::clone(&self.metrics);
         self.message_publisher_handle = Some(thread::spawn(move || {
-            let publisher = MessagePublisher::new(publisher, publisher_receiver);
-            publisher.run().unwrap_or_else(|e| error!("{}", e));;
+            let publisher = MessagePublisher::new(
+                publisher,
+                publisher_receiver,
+                reconnected_sender,
+                stop_message_publisher,
+                recorder,
+                metrics,
+            );
+            publisher.run().unwrap_or_else(|e| error!("{}", e));
         }));
 
         // Subscriber
         let subscriber_sender = self.subscriber_sender.clone();
+        let reconnected_sender = self.reconnected_sender.clone();
+        let recorder = self.recorder.clone();
+        let metrics = Arc::clone(&self.metrics);
         self.message_subscriber_handle = Some(thread::spawn(move || {
-            let subscriber = MessageSubscriber::new(subscriber, subscriber_sender);
-            subscriber.run();
+            let subscriber = MessageSubscriber::new(
+                subscriber,
+                subscriber_sender,
+                reconnected_sender,
+                recorder,
+                metrics,
+            );
+            subscriber.run().unwrap_or_else(|e| error!("{}", e));
         }));
 
         let state_publisher = Arc::clone(&self.state_publisher);
@@ -156,22 +435,156 @@ impl Controller {
             state_publisher.run().unwrap_or_else(|e| error!("{}", e));
         }));
 
+        // Metrics server, it serves until the process exits.
+        if let Some(address) = &self.metrics_address {
+            let server = MetricsServer::bind(
+                address,
+                Arc::clone(&self.metrics),
+                self.intersections().into_iter().cloned().collect(),
+            )?;
+            info!("Serving metrics on http://{}/metrics", server.local_addr()?);
+            thread::spawn(move || server.run());
+        }
+
         // Score Poller
         let score_poller = Arc::clone(&self.score_poller);
         self.score_poller_handle = Some(thread::spawn(move || {
-            score_poller.run().unwrap_or_else(|e| error!("{}", e));;
+            score_poller.run().unwrap_or_else(|e| error!("{}", e));
+        }));
+
+        // Config Watcher
+        let config_watcher = ConfigWatcher::new(
+            &self.config_dir,
+            &["groups.toml", "blocks.toml"],
+            self.reload_sender.clone(),
+        );
+        self.config_watcher_handle = Some(thread::spawn(move || {
+            config_watcher.run();
         }));
 
+        // Signals
+        let shutdown_sender = self.shutdown_sender.clone();
+        let signals = Signals::new([SIGINT, SIGTERM])?;
+        thread::spawn(move || {
+            for (count, signal) in signals.forever().enumerate() {
+                // A second signal skips the graceful shutdown, in case it got stuck.
+                if count > 0 {
+                    error!("Received signal {} again, exiting right away", signal);
+                    process::exit(1);
+                }
+
+                info!("Received signal {}, shutting down", signal);
+                shutdown_sender.send(()).unwrap_or_default();
+            }
+        });
+
+        // Running instances answer the announcement, this one stands by until none of them turns
+        // out to be older.
+        self.standby = true;
+        self.state_publisher.pause()?;
+        self.listening_until = Some(Instant::now() + self.takeover_delay);
+        self.announce(Handler::Connect);
+        self.check_takeover()?;
+
         let receiver = self.subscriber_receiver.clone();
+        let reload_receiver = self.reload_receiver.clone();
+        let reconnected_receiver = self.reconnected_receiver.clone();
+        let runner_failure_receiver = self.runner_failure_receiver.clone();
+        let shutdown_receiver = self.shutdown_receiver.clone();
+        let health_ticker = tick(Duration::from_millis(100));
+
+        loop {
+            select! {
+                recv(receiver) -> message => match message {
+                    Ok(message) => self.handle_message(message),
+                    Err(_) => break,
+                },
+                recv(reload_receiver) -> _ => {
+                    self.reload_config()
+                        .unwrap_or_else(|e| error!("Rejected the configuration reload: {}", e));
+                },
+                recv(reconnected_receiver) -> _ => {
+                    self.handle_reconnect()
+                        .unwrap_or_else(|e| error!("Could not republish the states: {}", e));
+                },
+                recv(runner_failure_receiver) -> failure => if let Ok(failure) = failure {
+                    self.handle_runner_failure(failure)
+                        .unwrap_or_else(|e| error!("Could not handle the runner failure: {}", e));
+                },
+                recv(health_ticker) -> _ => {
+                    self.check_health()
+                        .unwrap_or_else(|e| error!("Could not check controller health: {}", e));
+                },
+                recv(shutdown_receiver) -> _ => break,
+            }
+        }
+
+        self.shutdown()
+    }
+
+    /// Shuts the controller down like a signal does, once sent to.
+    pub fn shutdown_sender(&self) -> Sender<()> {
+        self.shutdown_sender.clone()
+    }
 
-        for message in receiver {
-            if let Ok(topic) = LifeCycleTopic::try_from(&message.0[..]) {
-                self.handle_life_cycle_message(topic).unwrap_or_else(|_| {
-                    error!("Could not properly handle lifecycle message, skipping.")
-                });;
+    /// Stops the runners, leaves the intersections in a safe state and tells everyone this instance
+    /// is going away. Returns once all of that is published.
+    fn shutdown(&mut self) -> Result<(), failure::Error> {
+        info!("Shutting down controller instance {}", self.instance_id);
+
+        self.stop_runners()?;
+
+        if !self.standby {
+            for intersection in self.intersections() {
+                intersection.read_checked()?.secure()?;
             }
+        }
 
-            if let Ok(topic) = ComponentTopic::try_from(&message.0[..]) {
+        // The state publisher forwards the states queued so far before it stops, so they are
+        // published before the disconnect.
+        self.stop_state_publisher.send(())?;
+        if let Some(handle) = self.state_publisher_handle.take() {
+            handle
+                .join()
+                .unwrap_or_else(|_| error!("Could not join state publisher thread"));
+        }
+
+        self.announce(Handler::Disconnect);
+
+        self.stop_message_publisher.send(())?;
+        if let Some(handle) = self.message_publisher_handle.take() {
+            handle
+                .join()
+                .unwrap_or_else(|_| error!("Could not join message publisher thread"));
+        }
+
+        info!("Controller instance {} shut down", self.instance_id);
+
+        Ok(())
+    }
+
+    /// Publishes a lifecycle message of this controller instance.
+    fn announce(&self, handler: Handler) {
+        self.publisher_sender
+            .send(Message {
+                topic: Box::new(LifeCycleTopic::new(Device::Controller, handler)),
+                payload: self.instance_id.clone().into_bytes(),
+            })
+            .unwrap_or_else(|e| error!("{}", e));
+    }
+
+    fn handle_message(&mut self, message: (String, String)) {
+        if let Ok(topic) = LifeCycleTopic::try_from(&message.0[..]) {
+            if self.is_own_team(&topic, &message.0) {
+                self.handle_life_cycle_message(topic, &message.1)
+                    .unwrap_or_else(|_| {
+                        error!("Could not properly handle lifecycle message, skipping.")
+                    });
+            }
+        }
+
+        if let Ok(topic) = ComponentTopic::try_from(&message.0[..]) {
+            if self.is_own_team(&topic, &message.0) {
                 self.handle_component_message(topic, message.1)
                     .unwrap_or_else(|_| {
                         error!("Could not properly handle component message, skipping.")
@@ -179,37 +592,449 @@ impl Controller {
             }
         }
 
+        if let Ok(topic) = CommandTopic::try_from(&message.0[..]) {
+            if self.is_own_team(&topic, &message.0) {
+                self.handle_command_message(topic).unwrap_or_else(|_| {
+                    error!("Could not properly handle command message, skipping.")
+                });
+            }
+        }
+    }
+
+    /// Checks that a message is meant for this team, counting the ones that are not.
+    fn is_own_team(&mut self, topic: &dyn Topic, name: &str) -> bool {
+        if topic.team_id().ok() == Some(self.team_id) {
+            return true;
+        }
+
+        self.foreign_messages += 1;
+
+        debug!(
+            "Dropped message on topic \"{}\" of another team, {} dropped so far",
+            name, self.foreign_messages
+        );
+
+        false
+    }
+
+    /// Enters degraded mode when the safety check failed or the simulator is gone for too long.
+    fn check_health(&mut self) -> Result<(), failure::Error> {
+        self.check_takeover()?;
+
+        if self.degraded.is_some() || self.standby {
+            return Ok(());
+        }
+
+        let mut tripped = false;
+
+        for intersection in self.intersections() {
+            tripped |= intersection.read_checked()?.monitor.tripped()?;
+        }
+
+        if tripped {
+            self.enter_degraded(DegradedReason::SafetyCheck)?;
+        } else if let Some(since) = self.disconnected_since {
+            if since.elapsed() >= self.max_disconnect_time {
+                self.enter_degraded(DegradedReason::Disconnected)?;
+            }
+        }
+
         Ok(())
     }
 
-    fn handle_life_cycle_message(&mut self, topic: LifeCycleTopic) -> Result<(), failure::Error> {
+    fn handle_life_cycle_message(
+        &mut self,
+        topic: LifeCycleTopic,
+        payload: &str,
+    ) -> Result<(), failure::Error> {
         info!("Received a lifecycle topic");
 
+        if topic.device == Device::Controller {
+            return self.handle_controller_life_cycle(topic.handler, payload);
+        }
+
+        self.simulator_heard = true;
+
+        if self.standby {
+            self.simulator_connected = topic.handler == Handler::Connect;
+            self.disconnected_since = if self.simulator_connected {
+                None
+            } else {
+                Some(Instant::now())
+            };
+
+            return Ok(());
+        }
+
         if topic.device == Device::Simulator && topic.handler == Handler::Connect {
             info!("Received a connect");
 
-            self.stop_runners()?;
-            self.reset()?;
+            self.simulator_connected = true;
+            self.disconnected_since = None;
+
+            if self.degraded == Some(DegradedReason::Disconnected) {
+                self.leave_degraded()?;
+            } else if self.degraded.is_none() {
+                self.runner_restarts = 0;
+                self.restart_runners()?;
+            }
+        } else if topic.device == Device::Simulator && topic.handler == Handler::Disconnect {
+            warn!("Received a disconnect");
+
+            self.simulator_connected = false;
+            self.disconnected_since = Some(Instant::now());
+
+            if self.degraded.is_none() {
+                self.stop_runners()?;
+                self.reset()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The instance with the lowest id drives the intersections, the others stand by. Every
+    /// instance answers the announcement of a new one, so they all know each other and agree.
+    fn handle_controller_life_cycle(
+        &mut self,
+        handler: Handler,
+        instance_id: &str,
+    ) -> Result<(), failure::Error> {
+        if instance_id == self.instance_id {
+            return Ok(());
+        }
+
+        match handler {
+            Handler::Connect => {
+                if !self.instances.insert(String::from(instance_id)) {
+                    return Ok(());
+                }
+
+                if !self.simulator_heard {
+                    // The instances that were already running connected the simulator before
+                    // this one started, as far as its own lifecycle messages don't tell otherwise.
+                    self.simulator_connected = true;
+                }
+
+                if !self.standby && *instance_id < *self.instance_id {
+                    warn!(
+                        "Controller instance {} is older, handing the intersections over",
+                        instance_id
+                    );
+
+                    self.stand_by()?;
+                }
+
+                // Answered after standing by, so the secured states are out before the new instance
+                // learns of this one.
+                self.announce(Handler::Connect);
+            }
+            Handler::Disconnect => {
+                if self.instances.remove(instance_id) {
+                    self.check_takeover()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The id of the instance that drives the intersections, as far as this instance knows.
+    fn driving_instance(&self) -> &str {
+        match self.instances.iter().next() {
+            Some(oldest) if *oldest < self.instance_id => oldest,
+            _ => &self.instance_id,
+        }
+    }
+
+    /// Takes over the intersections once this instance is done listening for running instances
+    /// and none of the ones it knows of is older.
+    fn check_takeover(&mut self) -> Result<(), failure::Error> {
+        if let Some(until) = self.listening_until {
+            if Instant::now() < until {
+                return Ok(());
+            }
+        }
+
+        self.listening_until = None;
+
+        if !self.standby || self.driving_instance() != self.instance_id {
+            return Ok(());
+        }
+
+        info!(
+            "Controller instance {} drives the intersections",
+            self.instance_id
+        );
+
+        // The states of all actuators go out before the runners change any of them, replacing
+        // whatever the previous instance left on display.
+        self.standby = false;
+        self.state_publisher.resume()?;
+        self.reset()?;
+        self.publish_states()?;
+
+        if self.degraded.is_some() {
+            self.set_out_of_order()?;
+        } else if self.simulator_connected {
+            self.runner_restarts = 0;
+            self.start_runners();
+        }
+
+        Ok(())
+    }
+
+    /// Leaves the intersections to an older instance. The lights are secured and published right
+    /// away, so nothing of a phase nobody runs any longer stays on display.
+    fn stand_by(&mut self) -> Result<(), failure::Error> {
+        self.standby = true;
+        self.state_publisher.pause()?;
+        self.stop_runners()?;
+
+        for intersection in self.intersections() {
+            let intersection = intersection.read_checked()?;
+            intersection.secure()?;
+
+            for uid in intersection.actuators()? {
+                self.state_publisher.publish_state(uid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_command_message(&mut self, topic: CommandTopic) -> Result<(), failure::Error> {
+        info!("Received a {} command", topic.command);
+
+        if self.standby && topic.command != Command::Reload {
+            warn!("Standing by, ignoring the {} command", topic.command);
+            return Ok(());
+        }
+
+        match topic.command {
+            Command::OutOfOrder => self.enter_degraded(DegradedReason::Operator),
+            Command::Resume => self.leave_degraded(),
+            Command::Reload => self.reload_sender.send(()).map_err(|e| e.into()),
+        }
+    }
+
+    /// Reads the configuration again and hands the new timings and blocks to the traffic lights
+    /// runner. Everything else, like groups and components, is only read at startup.
+    fn reload_config(&mut self) -> Result<(), failure::Error> {
+        info!("Reloading the configuration");
+
+        let config = Config::new(&self.config_dir)?;
+        let problems = validator::validate(&config);
+
+        for problem in &problems {
+            error!("{}", problem);
+        }
+
+        if !problems.is_empty() {
+            return Err(ReloadError::Invalid {
+                problems: problems.len(),
+            }
+            .into());
+        }
+
+        if !IntersectionsBuilder::same_topology(&config.traffic_lights, &self.traffic_lights)?
+            || !IntersectionsBuilder::same_topology(&config.bridge, &self.bridge)?
+        {
+            return Err(ReloadError::TopologyChanged.into());
+        }
 
-            info!("Starting traffic light and bridge threads");
+        self.traffic_lights_runner
+            .reload(config.groups, config.traffic_lights_blocks)?;
+
+        info!("Configuration reloaded, applying it at the next phase");
+
+        Ok(())
+    }
+
+    /// Announces this instance again, as the broker may have published its last will, and
+    /// publishes the state of every actuator, so the simulator agrees with the controller.
+    fn handle_reconnect(&self) -> Result<(), failure::Error> {
+        self.announce(Handler::Connect);
+
+        if self.standby {
+            return Ok(());
+        }
+
+        self.publish_states()
+    }
+
+    fn publish_states(&self) -> Result<(), failure::Error> {
+        info!("Publishing all actuator states");
+
+        for intersection in self.intersections() {
+            let intersection = intersection.read_checked()?;
+
+            for uid in intersection.actuators()? {
+                intersection.send_state(uid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stops the runners and switches every light to out of order.
+    fn enter_degraded(&mut self, reason: DegradedReason) -> Result<(), failure::Error> {
+        if self.degraded.is_some() {
+            return Ok(());
+        }
+
+        error!("Entering degraded mode, {}", reason);
+
+        self.degraded = Some(reason);
+        self.stop_runners()?;
+
+        if self.standby {
+            return Ok(());
+        }
+
+        self.set_out_of_order()
+    }
+
+    fn set_out_of_order(&self) -> Result<(), failure::Error> {
+        for intersection in self.intersections() {
+            for group in intersection.read_checked()?.groups.values() {
+                let lights: Vec<_> = group.read_checked()?.lights.values().cloned().collect();
+
+                for light in lights {
+                    light.write_checked()?.set_state(LightState::OutOfOrder)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Leaves degraded mode with an all red restart, the runners are started again once the
+    /// simulator is connected.
+    fn leave_degraded(&mut self) -> Result<(), failure::Error> {
+        if self.degraded.is_none() {
+            return Ok(());
+        }
+
+        info!("Leaving degraded mode");
+
+        self.degraded = None;
+        self.runner_restarts = 0;
+
+        if self.standby {
+            return Ok(());
+        }
+
+        self.reset()?;
+
+        if self.simulator_connected {
+            self.start_runners();
+        } else {
+            self.disconnected_since = Some(Instant::now());
+        }
+
+        Ok(())
+    }
+
+    /// The intersections the controller runs.
+    fn intersections(&self) -> Vec<&ArcIntersection> {
+        let mut intersections = vec![];
+
+        if self.mode.runs_traffic_lights() {
+            intersections.push(&self.traffic_lights);
+        }
+
+        if self.mode.runs_bridge() {
+            intersections.push(&self.bridge);
+        }
+
+        intersections
+    }
+
+    fn start_runners(&mut self) {
+        if self.mode.runs_traffic_lights() {
+            info!("Starting traffic lights thread");
             let traffic_lights_runner = Arc::clone(&self.traffic_lights_runner);
-            self.traffic_lights_runner_handle = Some(thread::spawn(move || {
-                traffic_lights_runner
-                    .run()
-                    .unwrap_or_else(|e| error!("{}", e));
-            }));
+            self.traffic_lights_runner_handle = Some(Self::supervise(
+                Runner::TrafficLights,
+                self.runner_generation,
+                self.runner_failure_sender.clone(),
+                move || traffic_lights_runner.run(),
+            ));
+        }
 
+        if self.mode.runs_bridge() {
+            info!("Starting bridge thread");
             let bridge_runner = Arc::clone(&self.bridge_runner);
-            self.bridge_runner_handle = Some(thread::spawn(move || {
-                bridge_runner.run().unwrap_or_else(|e| error!("{}", e));
-            }));
-        } else if topic.device == Device::Simulator && topic.handler == Handler::Disconnect {
-            warn!("Received a disconnect");
+            self.bridge_runner_handle = Some(Self::supervise(
+                Runner::Bridge,
+                self.runner_generation,
+                self.runner_failure_sender.clone(),
+                move || bridge_runner.run(),
+            ));
+        }
+    }
+
+    /// Runs a runner on its own thread and reports it when it ends with an error or a panic
+    /// instead of being stopped.
+    fn supervise<F>(
+        runner: Runner,
+        generation: u64,
+        failure_sender: Sender<RunnerFailure>,
+        run: F,
+    ) -> JoinHandle<()>
+    where
+        F: FnOnce() -> Result<(), failure::Error> + Send + 'static,
+    {
+        thread::spawn(move || {
+            let error = match panic::catch_unwind(AssertUnwindSafe(run)) {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => e,
+                Err(payload) => Error::from_panic(payload).into(),
+            };
+
+            failure_sender
+                .send(RunnerFailure {
+                    runner,
+                    generation,
+                    error,
+                })
+                .unwrap_or_default();
+        })
+    }
 
-            self.stop_runners()?;
-            self.reset()?;
+    /// Restarts the runners with an all red reset when one of them failed, or enters degraded
+    /// mode once they failed too often, so the intersections never stay frozen.
+    fn handle_runner_failure(&mut self, failure: RunnerFailure) -> Result<(), failure::Error> {
+        if failure.generation != self.runner_generation {
+            return Ok(());
         }
 
+        error!("The {} runner failed: {}", failure.runner, failure.error);
+
+        if self.runner_restarts < MAX_RUNNER_RESTARTS {
+            self.runner_restarts += 1;
+
+            warn!(
+                "Restarting the runners, restart {} of {}",
+                self.runner_restarts, MAX_RUNNER_RESTARTS
+            );
+
+            match self.restart_runners() {
+                Ok(()) => return Ok(()),
+                Err(e) => error!("Could not restart the runners: {}", e),
+            }
+        }
+
+        self.enter_degraded(DegradedReason::RunnerFailed)
+    }
+
+    /// Stops the runners and starts them again after an all red reset.
+    fn restart_runners(&mut self) -> Result<(), failure::Error> {
+        self.stop_runners()?;
+        self.reset()?;
+        self.start_runners();
+
         Ok(())
     }
 
@@ -221,12 +1046,12 @@ impl Controller {
         let payload_int = payload.parse::<i32>()?;
         let state = SensorState::try_from(payload_int)?;
 
-        if let Some(sensor) = self.traffic_lights.read().unwrap().find_sensor(topic.uid) {
-            sensor.write().unwrap().set_state(state)?;
+        if let Some(sensor) = self.traffic_lights.read_checked()?.find_sensor(topic.uid)? {
+            sensor.write_checked()?.set_state(state)?;
         }
 
-        if let Some(sensor) = self.bridge.read().unwrap().find_sensor(topic.uid) {
-            sensor.write().unwrap().set_state(state)?;
+        if let Some(sensor) = self.bridge.read_checked()?.find_sensor(topic.uid)? {
+            sensor.write_checked()?.set_state(state)?;
         }
 
         Ok(())
@@ -234,35 +1059,38 @@ impl Controller {
 
     fn reset(&self) -> Result<(), failure::Error> {
         info!("Resetting all states and scores");
-        for group in self.traffic_lights.read().unwrap().groups.values() {
-            group.read().unwrap().reset_all()?;
-            group.write().unwrap().reset_score()?;
-        }
+        for intersection in self.intersections() {
+            intersection.read_checked()?.monitor.reset()?;
 
-        for group in self.bridge.read().unwrap().groups.values() {
-            group.read().unwrap().reset_all()?;
-            group.write().unwrap().reset_score()?;
+            for group in intersection.read_checked()?.groups.values() {
+                Group::reset_all(group)?;
+                group.write_checked()?.reset_score()?;
+            }
         }
 
         Ok(())
     }
 
     fn stop_runners(&mut self) -> Result<(), failure::Error> {
-        if self.traffic_lights_runner_handle.is_some() && self.bridge_runner_handle.is_some() {
+        if self.traffic_lights_runner_handle.is_some() || self.bridge_runner_handle.is_some() {
+            self.runner_generation += 1;
             self.stop_runners.store(true, Release);
-            self.stop_runners_sender.send(())?;
 
-            self.traffic_lights_runner_handle
-                .take()
-                .unwrap()
-                .join()
-                .unwrap_or_else(|_| error!("Could not join traffic lights thread"));
+            for sender in &self.stop_runners_senders {
+                sender.send(())?;
+            }
 
-            self.bridge_runner_handle
-                .take()
-                .unwrap()
-                .join()
-                .unwrap_or_else(|_| error!("Could not join traffic lights thread"));
+            if let Some(handle) = self.traffic_lights_runner_handle.take() {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| error!("Could not join traffic lights thread"));
+            }
+
+            if let Some(handle) = self.bridge_runner_handle.take() {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| error!("Could not join bridge thread"));
+            }
 
             self.stop_runners.store(false, Release);
         }
@@ -270,3 +1098,473 @@ impl Controller {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    use rumqtt::{MqttOptions, QoS};
+
+    use crate::intersections::component::{ComponentKind, ComponentUid};
+    use crate::intersections::group::{GroupId, GroupKind};
+    use crate::io::transport::in_process::InProcessBroker;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// A controller instance on its own thread, connected to an in-process broker.
+    struct Instance {
+        traffic_lights: ArcIntersection,
+        shutdown: Sender<()>,
+        handle: JoinHandle<Result<(), failure::Error>>,
+    }
+
+    impl Instance {
+        /// Starts an instance with the given id, returns once it announced itself.
+        fn start(broker: &InProcessBroker, instance_id: &str, takeover_delay: i32) -> Self {
+            let mut config = Config::new("config").unwrap();
+            config.general.takeover_delay = Some(takeover_delay);
+            let team_id = config.general.team_id;
+
+            let (notification_sender, notification_receiver) = unbounded();
+            let traffic_lights = IntersectionsBuilder::new(notification_sender.clone())
+                .with_defs(&config.traffic_lights)
+                .with_blocks(&config.traffic_lights_blocks)
+                .finish()
+                .unwrap();
+            let bridge = IntersectionsBuilder::new(notification_sender)
+                .with_defs(&config.bridge)
+                .finish()
+                .unwrap();
+
+            let mut controller = Controller::new(
+                Arc::clone(&traffic_lights),
+                bridge,
+                notification_receiver,
+                config,
+                "config",
+                Mode::TrafficLights,
+            )
+            .unwrap();
+            controller.instance_id = String::from(instance_id);
+
+            let client = |id: &str| {
+                Client::with_transport(
+                    MqttOptions::new(format!("{}-{}", instance_id, id), "localhost", 1883),
+                    QoS::AtLeastOnce,
+                    team_id,
+                    Box::new(broker.transport()),
+                )
+            };
+            let (publisher, subscriber) = (client("publisher"), client("subscriber"));
+
+            let messages = broker.tap().unwrap();
+            let shutdown = controller.shutdown_sender();
+            let handle = thread::spawn(move || controller.start(publisher, subscriber));
+
+            let announcement = (
+                topic(Box::new(LifeCycleTopic::new(
+                    Device::Controller,
+                    Handler::Connect,
+                ))),
+                instance_id.as_bytes().to_vec(),
+            );
+            while messages.recv_timeout(TIMEOUT).unwrap() != announcement {}
+
+            Self {
+                traffic_lights,
+                shutdown,
+                handle,
+            }
+        }
+
+        fn stop(self) {
+            self.shutdown.send(()).unwrap();
+            self.handle.join().unwrap().unwrap();
+        }
+
+        fn light_states(&self) -> Vec<LightState> {
+            let mut states = vec![];
+
+            for group in self.traffic_lights.read().unwrap().groups() {
+                for light in group.read().unwrap().lights.values() {
+                    states.push(light.read().unwrap().state());
+                }
+            }
+
+            states
+        }
+
+        fn all_prohibit(&self) -> bool {
+            self.light_states()
+                .iter()
+                .all(|state| *state == LightState::Prohibit)
+        }
+    }
+
+    fn topic(mut topic: Box<dyn Topic>) -> String {
+        topic.set_team_id(Config::new("config").unwrap().general.team_id);
+        topic.to_string()
+    }
+
+    fn connect_simulator(broker: &InProcessBroker) {
+        broker
+            .inject(
+                &topic(Box::new(LifeCycleTopic::new(
+                    Device::Simulator,
+                    Handler::Connect,
+                ))),
+                b"",
+            )
+            .unwrap();
+    }
+
+    /// Lets a car arrive until the driving instance gives it proceed, checking that the other
+    /// instances leave their lights alone meanwhile. The arrival is repeated, as taking over
+    /// resets the sensors.
+    fn assert_served_by(broker: &InProcessBroker, driving: &Instance, others: &[&Instance]) {
+        let sensor = ComponentUid::new(GroupKind::MotorVehicle, 1, ComponentKind::Sensor, 1);
+        let light = ComponentUid::new(GroupKind::MotorVehicle, 1, ComponentKind::Light, 1);
+        let proceeding = || {
+            let light = driving
+                .traffic_lights
+                .read()
+                .unwrap()
+                .find_light(light)
+                .unwrap()
+                .unwrap();
+            let state = light.read().unwrap().state();
+            state == LightState::Proceed
+        };
+
+        let deadline = Instant::now() + TIMEOUT;
+        let mut arrival = Instant::now();
+
+        loop {
+            for other in others {
+                assert!(other.all_prohibit(), "a standby instance drives the lights");
+            }
+
+            if proceeding() {
+                return;
+            }
+
+            assert!(Instant::now() < deadline, "the car never got proceed");
+
+            if Instant::now() >= arrival {
+                broker
+                    .inject(&topic(Box::new(ComponentTopic::from(sensor))), b"1")
+                    .unwrap();
+                arrival += Duration::from_millis(500);
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Checks that no two lights letting traffic in belong to groups that block each other.
+    fn assert_no_conflicts(
+        lights: &HashMap<ComponentUid, LightState>,
+        blocks: &HashMap<GroupId, Vec<GroupId>>,
+    ) {
+        let exclusive: Vec<GroupId> = lights
+            .iter()
+            .filter(|(_, state)| {
+                **state == LightState::Proceed || **state == LightState::Transitioning
+            })
+            .map(|(uid, _)| uid.group_id)
+            .collect();
+
+        for a in &exclusive {
+            for b in &exclusive {
+                assert!(!blocks[a].contains(b), "{} and {} are green together", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_car_gets_proceed_without_conflicts() {
+        let mut config = Config::new("config").unwrap();
+        config.general.takeover_delay = Some(0);
+        let team_id = config.general.team_id;
+
+        let (notification_sender, notification_receiver) = unbounded();
+        let traffic_lights = IntersectionsBuilder::new(notification_sender.clone())
+            .with_defs(&config.traffic_lights)
+            .with_blocks(&config.traffic_lights_blocks)
+            .finish()
+            .unwrap();
+        let bridge = IntersectionsBuilder::new(notification_sender)
+            .with_defs(&config.bridge)
+            .finish()
+            .unwrap();
+
+        let mut blocks = HashMap::new();
+        for group in traffic_lights.read().unwrap().groups() {
+            let group = group.read().unwrap();
+            let blocked = group.blocks.iter().map(|b| b.read().unwrap().id).collect();
+            blocks.insert(group.id, blocked);
+        }
+
+        let broker = InProcessBroker::new();
+        let messages = broker.tap().unwrap();
+        let client = |id: &str| {
+            Client::with_transport(
+                MqttOptions::new(id, "localhost", 1883),
+                QoS::AtLeastOnce,
+                team_id,
+                Box::new(broker.transport()),
+            )
+        };
+        let (publisher, subscriber) = (client("publisher"), client("subscriber"));
+
+        let mut controller = Controller::new(
+            traffic_lights,
+            bridge,
+            notification_receiver,
+            config,
+            "config",
+            Mode::TrafficLights,
+        )
+        .unwrap();
+        let shutdown = controller.shutdown_sender();
+        let handle = thread::spawn(move || controller.start(publisher, subscriber));
+
+        let topic = |mut topic: Box<dyn Topic>| {
+            topic.set_team_id(team_id);
+            topic.to_string()
+        };
+        let timeout = Duration::from_secs(10);
+
+        // The controller announces itself once it's subscribed.
+        let controller_connect = topic(Box::new(LifeCycleTopic::new(
+            Device::Controller,
+            Handler::Connect,
+        )));
+        while messages.recv_timeout(timeout).unwrap().0 != controller_connect {}
+
+        let sensor = ComponentUid::new(GroupKind::MotorVehicle, 1, ComponentKind::Sensor, 1);
+        let light = ComponentUid::new(GroupKind::MotorVehicle, 1, ComponentKind::Light, 1);
+
+        broker
+            .inject(
+                &topic(Box::new(LifeCycleTopic::new(
+                    Device::Simulator,
+                    Handler::Connect,
+                ))),
+                b"",
+            )
+            .unwrap();
+        broker
+            .inject(&topic(Box::new(ComponentTopic::from(sensor))), b"1")
+            .unwrap();
+
+        let mut lights = HashMap::new();
+        let mut record = |(name, payload): (String, Vec<u8>)| {
+            if let Ok(topic) = ComponentTopic::try_from(&name[..]) {
+                if topic.uid.component_id.kind == ComponentKind::Light {
+                    let state = String::from_utf8(payload).unwrap().parse::<i32>().unwrap();
+                    lights.insert(topic.uid, LightState::try_from(state).unwrap());
+                    assert_no_conflicts(&lights, &blocks);
+                }
+            }
+
+            lights.get(&light) == Some(&LightState::Proceed)
+        };
+
+        let deadline = Instant::now() + timeout;
+        while !record(messages.recv_timeout(deadline - Instant::now()).unwrap()) {}
+
+        shutdown.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+
+        // Everything published up to the shutdown is safe too, and the lights end up secured.
+        for message in messages.try_iter() {
+            record(message);
+        }
+        assert_eq!(lights[&light], LightState::Prohibit);
+    }
+
+    #[test]
+    fn test_supervise_reports_failures() {
+        let (sender, receiver) = unbounded();
+
+        Controller::supervise(Runner::Bridge, 1, sender.clone(), || Ok(()))
+            .join()
+            .unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        Controller::supervise(Runner::TrafficLights, 2, sender, || {
+            panic!("runner panicked")
+        })
+        .join()
+        .unwrap();
+
+        let failure = receiver.try_recv().unwrap();
+        assert_eq!(failure.runner, Runner::TrafficLights);
+        assert_eq!(failure.generation, 2);
+        assert_eq!(
+            failure.error.to_string(),
+            "Thread panicked: runner panicked"
+        );
+    }
+
+    #[test]
+    fn test_failing_runner_restarts_then_degrades() {
+        let mut config = Config::new("config").unwrap();
+        // The bridge runner fails as soon as it starts on a sequence it can't build.
+        config.bridge_sequence.steps[0].action = String::from("fly");
+
+        let (notification_sender, notification_receiver) = unbounded();
+        let traffic_lights = IntersectionsBuilder::new(notification_sender.clone())
+            .with_defs(&config.traffic_lights)
+            .finish()
+            .unwrap();
+        let bridge = IntersectionsBuilder::new(notification_sender)
+            .with_defs(&config.bridge)
+            .finish()
+            .unwrap();
+
+        let mut controller = Controller::new(
+            traffic_lights,
+            Arc::clone(&bridge),
+            notification_receiver,
+            config,
+            "config",
+            Mode::Bridge,
+        )
+        .unwrap();
+        controller.simulator_connected = true;
+        controller.start_runners();
+
+        let timeout = Duration::from_secs(10);
+
+        for restart in 1..=MAX_RUNNER_RESTARTS {
+            let failure = controller
+                .runner_failure_receiver
+                .recv_timeout(timeout)
+                .unwrap();
+            assert_eq!(failure.runner, Runner::Bridge);
+            // Only failures of the runners started last count.
+            assert_eq!(failure.generation, controller.runner_generation);
+
+            controller.handle_runner_failure(failure).unwrap();
+            assert_eq!(controller.runner_restarts, restart);
+            assert!(controller.bridge_runner_handle.is_some());
+            assert!(controller.degraded.is_none());
+        }
+
+        let failure = controller
+            .runner_failure_receiver
+            .recv_timeout(timeout)
+            .unwrap();
+        controller.handle_runner_failure(failure).unwrap();
+        assert_eq!(controller.degraded, Some(DegradedReason::RunnerFailed));
+        assert!(controller.bridge_runner_handle.is_none());
+
+        for group in bridge.read().unwrap().groups() {
+            for light in group.read().unwrap().lights.values() {
+                assert_eq!(light.read().unwrap().state(), LightState::OutOfOrder);
+            }
+        }
+    }
+
+    #[test]
+    fn test_oldest_instance_drives_the_lights() {
+        let broker = InProcessBroker::new();
+        let a = Instance::start(&broker, "a", 200);
+        let b = Instance::start(&broker, "b", 200);
+        let c = Instance::start(&broker, "c", 200);
+
+        connect_simulator(&broker);
+        assert_served_by(&broker, &a, &[&b, &c]);
+
+        // Every remaining instance agrees on the oldest one taking over.
+        a.stop();
+        assert_served_by(&broker, &b, &[&c]);
+
+        b.stop();
+        assert_served_by(&broker, &c, &[]);
+        c.stop();
+    }
+
+    #[test]
+    fn test_takeover_after_the_simulator_connected() {
+        let broker = InProcessBroker::new();
+        let a = Instance::start(&broker, "a", 0);
+
+        connect_simulator(&broker);
+        assert_served_by(&broker, &a, &[]);
+
+        // The second instance never sees the simulator connect, yet takes over from the first.
+        let b = Instance::start(&broker, "b", 200);
+        thread::sleep(Duration::from_millis(400));
+        assert!(b.all_prohibit());
+
+        a.stop();
+        assert_served_by(&broker, &b, &[]);
+        b.stop();
+    }
+
+    #[test]
+    fn test_hand_over_to_an_older_instance() {
+        let broker = InProcessBroker::new();
+        let b = Instance::start(&broker, "b", 0);
+
+        connect_simulator(&broker);
+        assert_served_by(&broker, &b, &[]);
+
+        let messages = broker.tap().unwrap();
+        let a = Instance::start(&broker, "a", 500);
+        let lights = a.light_states().len();
+
+        let light_state = |(name, payload): &(String, Vec<u8>)| {
+            let topic = ComponentTopic::try_from(&name[..]).ok()?;
+            let state = String::from_utf8_lossy(payload).parse::<i32>().ok()?;
+            Some((topic.uid, LightState::try_from(state).ok()?))
+        };
+
+        // The younger instance secures its lights and publishes them before it answers.
+        let answer = (
+            topic(Box::new(LifeCycleTopic::new(
+                Device::Controller,
+                Handler::Connect,
+            ))),
+            b"b".to_vec(),
+        );
+        let mut secured = HashMap::new();
+
+        loop {
+            let message = messages.recv_timeout(TIMEOUT).unwrap();
+
+            if message == answer {
+                break;
+            }
+
+            if let Some((uid, state)) = light_state(&message) {
+                secured.insert(uid, state);
+            }
+        }
+
+        assert_eq!(secured.len(), lights);
+        assert!(secured.values().all(|state| *state == LightState::Prohibit));
+        assert!(b.all_prohibit());
+
+        // The older instance publishes the state of every light before it changes any of them.
+        let mut published = HashSet::new();
+
+        while published.len() < lights {
+            let message = messages.recv_timeout(TIMEOUT).unwrap();
+
+            if let Some((uid, state)) = light_state(&message) {
+                assert_eq!(state, LightState::Prohibit);
+                published.insert(uid);
+            }
+        }
+
+        assert_served_by(&broker, &a, &[&b]);
+
+        a.stop();
+        b.stop();
+    }
+}
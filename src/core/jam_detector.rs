@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use crate::config::jams::{Jam as ConfigJam, Jams as ConfigJams};
+use crate::error::{Error, RwLockExt};
+use crate::intersections::component::{Component, ComponentUid};
+use crate::intersections::group::{ArcGroup, GroupId};
+use crate::intersections::intersection::ArcIntersection;
+use crate::intersections::sensor::{ArcSensor, SensorState};
+use crate::metrics::ArcMetrics;
+
+struct JamRule {
+    sensors: Vec<ArcSensor>,
+    threshold: Duration,
+    release: Duration,
+    blocks: Vec<ArcGroup>,
+
+    active: bool,
+}
+
+impl JamRule {
+    fn jammed(&self) -> Result<bool, Error> {
+        for sensor in &self.sensors {
+            if sensor
+                .read_checked()?
+                .triggered_for(self.threshold, SensorState::High)
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn cleared(&self) -> Result<bool, Error> {
+        for sensor in &self.sensors {
+            if !sensor
+                .read_checked()?
+                .triggered_for(self.release, SensorState::Low)
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Blocks groups while one of the configured jam rules detects a traffic jam.
+pub struct JamDetector {
+    rules: Vec<JamRule>,
+    metrics: ArcMetrics,
+}
+
+impl JamDetector {
+    pub fn new(
+        intersection: &ArcIntersection,
+        config: &ConfigJams,
+        metrics: ArcMetrics,
+    ) -> Result<Self, Error> {
+        let mut rules = vec![];
+
+        for jam in &config.jams {
+            if let Some(rule) = Self::build_rule(intersection, jam)? {
+                rules.push(rule);
+            }
+        }
+
+        Ok(Self { rules, metrics })
+    }
+
+    /// Re-evaluates every rule and (un)blocks their groups accordingly.
+    pub fn update(&mut self) -> Result<(), Error> {
+        for rule in &mut self.rules {
+            if !rule.active && rule.jammed()? {
+                warn!("A wild traffic jam appeared, blocking other traffic.");
+                rule.active = true;
+
+                for group in &rule.blocks {
+                    self.metrics.jam_blocked(group.read_checked()?.id);
+                }
+            } else if rule.active && rule.cleared()? {
+                info!("Traffic jam cleared, unblocking traffic.");
+                rule.active = false;
+            }
+        }
+
+        let mut blocked = HashSet::new();
+
+        for rule in self.rules.iter().filter(|rule| rule.active) {
+            for group in &rule.blocks {
+                blocked.insert(group.read_checked()?.id);
+            }
+        }
+
+        for rule in &self.rules {
+            for group in &rule.blocks {
+                let mut group = group.write_checked()?;
+                group.block = blocked.contains(&group.id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn build_rule(
+        intersection: &ArcIntersection,
+        jam: &ConfigJam,
+    ) -> Result<Option<JamRule>, Error> {
+        let intersection = intersection.read_checked()?;
+
+        let mut sensors = vec![];
+
+        for sensor in &jam.sensors {
+            let found = match ComponentUid::try_from(&sensor[..]) {
+                Ok(uid) => intersection.find_sensor(uid)?,
+                Err(_) => None,
+            };
+
+            match found {
+                Some(sensor) => sensors.push(sensor),
+                None => warn!("Jam sensor {} was not found, ignoring it", sensor),
+            }
+        }
+
+        if sensors.is_empty() {
+            warn!("Jam rule has no known sensors, ignoring it");
+            return Ok(None);
+        }
+
+        let mut blocks = vec![];
+
+        for group in &jam.blocks {
+            match GroupId::try_from(&group[..])
+                .ok()
+                .and_then(|id| intersection.find_group(id))
+            {
+                Some(group) => blocks.push(group),
+                None => warn!("Jam group {} was not found, ignoring it", group),
+            }
+        }
+
+        Ok(Some(JamRule {
+            sensors,
+            threshold: Duration::from_millis(jam.threshold as u64),
+            release: Duration::from_millis(jam.release.unwrap_or(0) as u64),
+            blocks,
+            active: false,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crossbeam_channel::{unbounded, Receiver};
+
+    use crate::clock::manual::ManualClock;
+    use crate::clock::ArcClock;
+    use crate::config::Config;
+    use crate::intersections::component::ComponentKind;
+    use crate::intersections::group::GroupKind;
+    use crate::intersections::intersection::Notification;
+    use crate::intersections::intersection_builder::IntersectionsBuilder;
+    use crate::metrics::Metrics;
+
+    /// The receiver keeps the notification channel open, setting a sensor state fails otherwise.
+    fn setup() -> (Arc<ManualClock>, ArcIntersection, Receiver<Notification>) {
+        let config = Config::new("config").unwrap();
+        let clock = Arc::new(ManualClock::new());
+
+        let (sender, receiver) = unbounded();
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_clock(Arc::clone(&clock) as ArcClock)
+            .with_defs(&config.traffic_lights)
+            .finish()
+            .unwrap();
+
+        (clock, intersection, receiver)
+    }
+
+    fn jam(sensors: &[&str], blocks: &[&str]) -> ConfigJam {
+        ConfigJam {
+            sensors: sensors.iter().map(|s| String::from(*s)).collect(),
+            threshold: 3_000,
+            release: Some(2_000),
+            blocks: blocks.iter().map(|b| String::from(*b)).collect(),
+        }
+    }
+
+    fn sensor(intersection: &ArcIntersection, group_id: i32) -> ArcSensor {
+        let uid = ComponentUid::new(GroupKind::MotorVehicle, group_id, ComponentKind::Sensor, 1);
+
+        intersection
+            .read()
+            .unwrap()
+            .find_sensor(uid)
+            .unwrap()
+            .unwrap()
+    }
+
+    fn blocked(intersection: &ArcIntersection, group_id: i32) -> bool {
+        let id = GroupId {
+            kind: GroupKind::MotorVehicle,
+            id: group_id,
+        };
+
+        let group = intersection.read().unwrap().find_group(id).unwrap();
+        let blocked = group.read().unwrap().block;
+        blocked
+    }
+
+    #[test]
+    fn test_jam_hysteresis() {
+        let (clock, intersection, _receiver) = setup();
+        let config = ConfigJams {
+            jams: vec![jam(&["motor_vehicle/14/sensor/1"], &["motor_vehicle/3"])],
+        };
+        let mut detector =
+            JamDetector::new(&intersection, &config, Arc::new(Metrics::new())).unwrap();
+        let sensor = sensor(&intersection, 14);
+
+        sensor
+            .write()
+            .unwrap()
+            .set_state(SensorState::High)
+            .unwrap();
+
+        clock.advance(Duration::from_millis(2_900));
+        detector.update().unwrap();
+        assert!(!blocked(&intersection, 3));
+
+        clock.advance(Duration::from_millis(100));
+        detector.update().unwrap();
+        assert!(blocked(&intersection, 3));
+
+        // Low for less than the release time, the jam is still there.
+        sensor.write().unwrap().set_state(SensorState::Low).unwrap();
+
+        clock.advance(Duration::from_millis(1_900));
+        detector.update().unwrap();
+        assert!(blocked(&intersection, 3));
+
+        clock.advance(Duration::from_millis(100));
+        detector.update().unwrap();
+        assert!(!blocked(&intersection, 3));
+    }
+
+    #[test]
+    fn test_unknown_sensors_and_groups() {
+        let (clock, intersection, _receiver) = setup();
+        let config = ConfigJams {
+            jams: vec![
+                jam(
+                    &["motor_vehicle/99/sensor/1", "no sensor"],
+                    &["motor_vehicle/3"],
+                ),
+                jam(
+                    &["motor_vehicle/99/sensor/1", "motor_vehicle/14/sensor/1"],
+                    &["motor_vehicle/99", "motor_vehicle/7"],
+                ),
+            ],
+        };
+
+        // A rule without a single known sensor is left out, unknown sensors and groups of the
+        // other rule are skipped.
+        let mut detector =
+            JamDetector::new(&intersection, &config, Arc::new(Metrics::new())).unwrap();
+        assert_eq!(detector.rules.len(), 1);
+        assert_eq!(detector.rules[0].sensors.len(), 1);
+        assert_eq!(detector.rules[0].blocks.len(), 1);
+
+        sensor(&intersection, 14)
+            .write()
+            .unwrap()
+            .set_state(SensorState::High)
+            .unwrap();
+
+        clock.advance(Duration::from_millis(3_000));
+        detector.update().unwrap();
+        assert!(blocked(&intersection, 7));
+        assert!(!blocked(&intersection, 3));
+    }
+}
@@ -1,7 +1,19 @@
-use crossbeam_channel::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, Sender};
+use rumqtt::{Notification, QoS};
 
 use crate::io::client::Client;
+use crate::io::recording::{ArcRecorder, Direction};
 use crate::io::topics::Topic;
+use crate::metrics::ArcMetrics;
+
+/// How long stopping waits for the broker to acknowledge the messages published so far.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// QoS 0 messages are never acknowledged, they get this long to leave instead.
+const FLUSH_GRACE: Duration = Duration::from_millis(500);
 
 pub struct Message {
     pub topic: Box<dyn Topic>,
@@ -11,21 +23,138 @@ pub struct Message {
 pub struct MessagePublisher {
     publisher: Client,
     receiver: Receiver<Message>,
+    reconnected_sender: Sender<()>,
+    stop_channel: Receiver<()>,
+    recorder: Option<ArcRecorder>,
+    metrics: ArcMetrics,
+
+    /// Messages published on the current connection that the broker didn't acknowledge yet.
+    unacknowledged: usize,
 }
 
 impl MessagePublisher {
-    pub fn new(publisher: Client, receiver: Receiver<Message>) -> Self {
+    pub fn new(
+        publisher: Client,
+        receiver: Receiver<Message>,
+        reconnected_sender: Sender<()>,
+        stop_channel: Receiver<()>,
+        recorder: Option<ArcRecorder>,
+        metrics: ArcMetrics,
+    ) -> Self {
         Self {
             publisher,
             receiver,
+            reconnected_sender,
+            stop_channel,
+            recorder,
+            metrics,
+            unacknowledged: 0,
         }
     }
 
+    /// Publishes messages until stopped, then flushes the messages queued by then.
     pub fn run(mut self) -> Result<(), failure::Error> {
-        for message in &self.receiver {
-            self.publisher.publish(message.topic, message.payload)?;
+        let receiver = self.receiver.clone();
+        let stop_channel = self.stop_channel.clone();
+        let mut notifications = self.publisher.listen()?;
+
+        loop {
+            select! {
+                recv(receiver) -> message => match message {
+                    Ok(message) => self.publish(message),
+                    Err(_) => break,
+                },
+                recv(notifications) -> notification => match notification {
+                    Ok(notification) => self.acknowledge(&notification),
+                    // The channel closes when the connection is lost.
+                    Err(_) => {
+                        warn!(
+                            target: "mqtt",
+                            "MQTT client \"{}\" lost its connection",
+                            self.publisher.options.client_id(),
+                        );
+
+                        if !self.publisher.reconnect() {
+                            break;
+                        }
+
+                        self.unacknowledged = 0;
+                        notifications = self.publisher.listen()?;
+                        self.reconnected_sender.send(())?;
+                    }
+                },
+                recv(stop_channel) -> _ => {
+                    self.flush(&notifications);
+                    break;
+                },
+            }
         }
 
         Ok(())
     }
+
+    fn publish(&mut self, mut message: Message) {
+        message.topic.set_team_id(self.publisher.team_id());
+        let topic = message.topic.to_string();
+
+        self.metrics.published(&topic);
+        self.metrics.publisher_queue(self.receiver.len());
+
+        if let Some(recorder) = &self.recorder {
+            recorder
+                .record(Direction::Outbound, &topic, &message.payload)
+                .unwrap_or_else(|e| error!(target: "mqtt", "Could not record message: {}", e));
+        }
+
+        // Messages published while the connection is down are lost, the states are published
+        // again after reconnecting.
+        match self.publisher.publish(message.topic, message.payload) {
+            Ok(()) if self.publisher.qos() != QoS::AtMostOnce => self.unacknowledged += 1,
+            Ok(()) => {}
+            Err(e) => error!(target: "mqtt", "Could not publish: {}", e),
+        }
+    }
+
+    fn acknowledge(&mut self, notification: &Notification) {
+        let acknowledged = matches!(
+            (self.publisher.qos(), notification),
+            (QoS::AtLeastOnce, Notification::PubAck(_))
+                | (QoS::ExactlyOnce, Notification::PubComp(_))
+        );
+
+        if acknowledged {
+            self.unacknowledged = self.unacknowledged.saturating_sub(1);
+        }
+    }
+
+    /// Publishes the queued messages and waits until the broker acknowledged them.
+    fn flush(&mut self, notifications: &Receiver<Notification>) {
+        for message in self.receiver.try_iter().collect::<Vec<Message>>() {
+            self.publish(message);
+        }
+
+        if self.publisher.qos() == QoS::AtMostOnce {
+            thread::sleep(FLUSH_GRACE);
+            return;
+        }
+
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+
+        while self.unacknowledged > 0 {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            match notifications.recv_timeout(timeout) {
+                Ok(notification) => self.acknowledge(&notification),
+                Err(_) => {
+                    warn!(
+                        target: "mqtt",
+                        "MQTT client \"{}\" stopped with {} unacknowledged message(s)",
+                        self.publisher.options.client_id(),
+                        self.unacknowledged
+                    );
+                    break;
+                }
+            }
+        }
+    }
 }
@@ -1,47 +1,79 @@
 use crossbeam_channel::Sender;
-use rumqtt::Notification;
+use rumqtt::{Notification, Publish};
 
 use crate::io::client::Client;
+use crate::io::recording::{ArcRecorder, Direction};
+use crate::metrics::ArcMetrics;
 
 pub struct MessageSubscriber {
     subscriber: Client,
     sender: Sender<(String, String)>,
+    reconnected_sender: Sender<()>,
+    recorder: Option<ArcRecorder>,
+    metrics: ArcMetrics,
 }
 
 impl MessageSubscriber {
-    pub fn new(subscriber: Client, sender: Sender<(String, String)>) -> Self {
-        Self { subscriber, sender }
+    pub fn new(
+        subscriber: Client,
+        sender: Sender<(String, String)>,
+        reconnected_sender: Sender<()>,
+        recorder: Option<ArcRecorder>,
+        metrics: ArcMetrics,
+    ) -> Self {
+        Self {
+            subscriber,
+            sender,
+            reconnected_sender,
+            recorder,
+            metrics,
+        }
     }
 
-    pub fn run(&self) {
-        let receiver = self.subscriber.listen().unwrap();
-
-        for message in receiver {
-            let message = match message {
-                Notification::Publish(msg) => msg,
-                _ => continue,
-            };
+    pub fn run(mut self) -> Result<(), failure::Error> {
+        loop {
+            let receiver = self.subscriber.listen()?;
 
-            let topic = message.topic_name;
-            let payload = String::from_utf8_lossy(&message.payload);
+            for message in receiver {
+                if let Notification::Publish(message) = message {
+                    self.forward(message);
+                }
+            }
 
-            info!(
+            warn!(
                 target: "mqtt",
-                "MQTT Client \"{}\" received a message with topic \"{}\" and payload \"{}\".",
+                "MQTT client \"{}\" lost its connection",
                 self.subscriber.options.client_id(),
-                topic,
-                payload,
             );
 
-            self.sender
-                .send((topic.clone(), String::from(payload.clone())))
-                .unwrap_or_else(|_| {
-                    error!(
-                        target: "mqtt",
-                        "Could not send message on topic \"{}\" with payload \"{}\".",
-                        topic, payload,
-                    )
-                });
+            if !self.subscriber.reconnect() || self.reconnected_sender.send(()).is_err() {
+                break;
+            }
         }
+
+        Ok(())
+    }
+
+    fn forward(&self, message: Publish) {
+        let topic = message.topic_name;
+        let payload = String::from_utf8_lossy(&message.payload);
+
+        self.metrics.received(&topic);
+
+        if let Some(recorder) = &self.recorder {
+            recorder
+                .record(Direction::Inbound, &topic, &message.payload)
+                .unwrap_or_else(|e| error!(target: "mqtt", "Could not record message: {}", e));
+        }
+
+        self.sender
+            .send((topic.clone(), String::from(payload.clone())))
+            .unwrap_or_else(|_| {
+                error!(
+                    target: "mqtt",
+                    "Could not send message on topic \"{}\" with payload \"{}\".",
+                    topic, payload,
+                )
+            });
     }
 }
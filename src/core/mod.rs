@@ -1,5 +1,8 @@
 pub mod bridge_runner;
+pub mod clearance;
+pub mod config_watcher;
 pub mod controller;
+pub mod jam_detector;
 pub mod message_publisher;
 pub mod message_subscriber;
 pub mod score_poller;
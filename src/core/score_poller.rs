@@ -1,6 +1,7 @@
-use std::thread;
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::error::RwLockExt;
 use crate::intersections::component::Component;
 use crate::intersections::intersection::ArcIntersection;
 use crate::intersections::sensor::SensorState;
@@ -15,12 +16,16 @@ impl ScorePoller {
     }
 
     pub fn run(&self) -> Result<(), failure::Error> {
+        let clock = Arc::clone(&self.traffic_lights.read_checked()?.clock);
+
         loop {
-            for group in self.traffic_lights.read().unwrap().groups() {
-                let mut score = group.read().unwrap().score;
+            for group in self.traffic_lights.read_checked()?.groups() {
+                let mut score = group.read_checked()?.score;
+
+                let sensors = group.read_checked()?.sensors();
 
-                for sensor in group.read().unwrap().sensors.values() {
-                    let sensor = sensor.read().unwrap();
+                for sensor in sensors {
+                    let sensor = sensor.read_checked()?;
 
                     if sensor.state() != SensorState::High {
                         continue;
@@ -35,10 +40,10 @@ impl ScorePoller {
                     }
                 }
 
-                group.write().unwrap().set_score(score)?;
+                group.write_checked()?.set_score(score)?;
             }
 
-            thread::sleep(Duration::from_millis(100));
+            clock.sleep(Duration::from_millis(100));
         }
     }
 }
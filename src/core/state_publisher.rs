@@ -1,20 +1,19 @@
+use std::sync::Mutex;
+
 use crossbeam_channel::{Receiver, Sender};
-use failure::Fail;
 
 use crate::core::message_publisher::Message;
+use crate::error::{Error, MutexExt, RwLockExt};
 use crate::intersections::component::{Component, ComponentKind, ComponentUid};
 use crate::intersections::intersection::{ArcIntersection, Notification};
 use crate::io::topics::component_topic::ComponentTopic;
 
-#[derive(Debug, Fail)]
-#[fail(display = "Component with id {} was not found", id)]
-pub struct ComponentNotFound {
-    id: ComponentUid,
-}
-
 pub struct StatePublisher {
     notification_receiver: Receiver<Notification>,
     sender: Sender<Message>,
+    stop_channel: Receiver<()>,
+    /// Whether state updates are published, held while one is, so none is on its way once paused.
+    publishing: Mutex<bool>,
 
     traffic_light: ArcIntersection,
     bridge: ArcIntersection,
@@ -24,30 +23,76 @@ impl StatePublisher {
     pub fn new(
         notification_receiver: Receiver<Notification>,
         sender: Sender<Message>,
+        stop_channel: Receiver<()>,
         traffic_light: ArcIntersection,
         bridge: ArcIntersection,
     ) -> Self {
         Self {
             notification_receiver,
             sender,
+            stop_channel,
+            publishing: Mutex::new(true),
             traffic_light,
             bridge,
         }
     }
 
+    /// Publishes state updates until stopped, the updates queued by then are still published.
     pub fn run(&self) -> Result<(), failure::Error> {
-        for notification in &self.notification_receiver {
-            if let Notification::StateUpdated(id) = notification {
-                if id.component_id.kind == ComponentKind::Sensor {
-                    continue;
-                }
-
-                self.sender
-                    .send(Message {
-                        topic: Box::new(ComponentTopic::from(id)),
-                        payload: self.get_payload(id)?.to_string().into_bytes(),
-                    })
-                    .unwrap_or_else(|e| error!("{}", e));
+        loop {
+            select! {
+                recv(self.notification_receiver) -> notification => match notification {
+                    Ok(notification) => self.publish(notification)?,
+                    Err(_) => break,
+                },
+                recv(self.stop_channel) -> _ => {
+                    for notification in self.notification_receiver.try_iter() {
+                        self.publish(notification)?;
+                    }
+
+                    break;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drops state updates until resumed, returns once none is being published any longer.
+    pub fn pause(&self) -> Result<(), Error> {
+        *self.publishing.lock_checked()? = false;
+
+        Ok(())
+    }
+
+    pub fn resume(&self) -> Result<(), Error> {
+        *self.publishing.lock_checked()? = true;
+
+        Ok(())
+    }
+
+    /// Publishes the current state of a component right away, paused or not.
+    pub fn publish_state(&self, id: ComponentUid) -> Result<(), failure::Error> {
+        self.sender
+            .send(Message {
+                topic: Box::new(ComponentTopic::from(id)),
+                payload: self.get_payload(id)?.to_string().into_bytes(),
+            })
+            .unwrap_or_else(|e| error!("{}", e));
+
+        Ok(())
+    }
+
+    fn publish(&self, notification: Notification) -> Result<(), failure::Error> {
+        if let Notification::StateUpdated(id) = notification {
+            if id.component_id.kind == ComponentKind::Sensor {
+                return Ok(());
+            }
+
+            let publishing = self.publishing.lock_checked()?;
+
+            if *publishing {
+                self.publish_state(id)?;
             }
         }
 
@@ -55,38 +100,38 @@ impl StatePublisher {
     }
 
     fn get_payload(&self, id: ComponentUid) -> Result<i32, failure::Error> {
-        if let Some(sensor) = self.traffic_light.read().unwrap().find_sensor(id) {
-            return Ok(sensor.read().unwrap().state() as i32);
+        if let Some(sensor) = self.traffic_light.read_checked()?.find_sensor(id)? {
+            return Ok(sensor.read_checked()?.state() as i32);
         }
 
-        if let Some(sensor) = self.bridge.read().unwrap().find_sensor(id) {
-            return Ok(sensor.read().unwrap().state() as i32);
+        if let Some(sensor) = self.bridge.read_checked()?.find_sensor(id)? {
+            return Ok(sensor.read_checked()?.state() as i32);
         }
 
-        if let Some(light) = self.traffic_light.read().unwrap().find_light(id) {
-            return Ok(light.read().unwrap().state() as i32);
+        if let Some(light) = self.traffic_light.read_checked()?.find_light(id)? {
+            return Ok(light.read_checked()?.state() as i32);
         }
 
-        if let Some(light) = self.bridge.read().unwrap().find_light(id) {
-            return Ok(light.read().unwrap().state() as i32);
+        if let Some(light) = self.bridge.read_checked()?.find_light(id)? {
+            return Ok(light.read_checked()?.state() as i32);
         }
 
-        if let Some(gate) = self.traffic_light.read().unwrap().find_gate(id) {
-            return Ok(gate.read().unwrap().state() as i32);
+        if let Some(gate) = self.traffic_light.read_checked()?.find_gate(id)? {
+            return Ok(gate.read_checked()?.state() as i32);
         }
 
-        if let Some(gate) = self.bridge.read().unwrap().find_gate(id) {
-            return Ok(gate.read().unwrap().state() as i32);
+        if let Some(gate) = self.bridge.read_checked()?.find_gate(id)? {
+            return Ok(gate.read_checked()?.state() as i32);
         }
 
-        if let Some(deck) = self.traffic_light.read().unwrap().find_deck(id) {
-            return Ok(deck.read().unwrap().state() as i32);
+        if let Some(deck) = self.traffic_light.read_checked()?.find_deck(id)? {
+            return Ok(deck.read_checked()?.state() as i32);
         }
 
-        if let Some(deck) = self.bridge.read().unwrap().find_deck(id) {
-            return Ok(deck.read().unwrap().state() as i32);
+        if let Some(deck) = self.bridge.read_checked()?.find_deck(id)? {
+            return Ok(deck.read_checked()?.state() as i32);
         }
 
-        Err(ComponentNotFound { id }.into())
+        Err(Error::ComponentNotFound { uid: id }.into())
     }
 }
@@ -1,22 +1,57 @@
-use std::collections::HashMap;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::Acquire;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::time::Duration;
 
-use crossbeam_channel::{after, Receiver};
+use crossbeam_channel::{unbounded, Receiver};
 
-use crate::config::groups::{Group as ConfigGroup, Groups as ConfigGroups};
-use crate::intersections::component::{Component, ComponentKind, ComponentUid};
-use crate::intersections::group::{ArcGroup, GroupKind};
+use crate::clock::ArcClock;
+
+use crate::config::blocks::Blocks;
+use crate::config::groups::Groups as ConfigGroups;
+use crate::config::jams::Jams as ConfigJams;
+use crate::core::clearance::Clearances;
+use crate::core::jam_detector::JamDetector;
+use crate::error::{Error, MutexExt, RwLockExt};
+use crate::intersections::component::Component;
+use crate::intersections::group::{ArcGroup, Group};
 use crate::intersections::intersection::ArcIntersection;
+use crate::intersections::intersection_builder::IntersectionsBuilder;
 use crate::intersections::light::LightState;
 use crate::intersections::sensor::SensorState;
+use crate::intersections::strategies;
+use crate::intersections::strategies::starvation::StarvationProtection;
+use crate::intersections::timings::{Timing, Timings};
+use crate::metrics::ArcMetrics;
+
+/// The times of a group's phase.
+#[derive(Clone, Copy)]
+struct PhaseTimes {
+    /// Minimum time to proceed.
+    proceed: Duration,
+    /// Maximum time to proceed when the phase is extended.
+    max_proceed: Duration,
+    /// Time the sensors get to detect traffic before extending the phase again.
+    gap: Duration,
+    transitioning: Duration,
+}
+
+/// Reloaded configuration, waiting for the next phase boundary.
+struct Reload {
+    groups_config: ConfigGroups,
+    blocks: Blocks,
+    timings: Timings,
+}
 
 pub struct TrafficLightsRunner {
     intersection: ArcIntersection,
-    groups_config: ConfigGroups,
+    clock: ArcClock,
+    groups_config: RwLock<ConfigGroups>,
+    jams_config: ConfigJams,
+    timings: RwLock<Arc<Timings>>,
+    reload: Mutex<Option<Reload>>,
+    metrics: ArcMetrics,
 
     stop: Arc<AtomicBool>,
     stop_channel: Receiver<()>,
@@ -26,151 +61,117 @@ impl TrafficLightsRunner {
     pub fn new(
         intersection: ArcIntersection,
         groups_config: ConfigGroups,
+        jams_config: ConfigJams,
+        metrics: ArcMetrics,
         stop: Arc<AtomicBool>,
         stop_channel: Receiver<()>,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, failure::Error> {
+        let timings = Timings::new(&**intersection.read_checked()?, &groups_config)?;
+        let clock = Arc::clone(&intersection.read_checked()?.clock);
+
+        Ok(Self {
             intersection,
-            groups_config,
+            clock,
+            groups_config: RwLock::new(groups_config),
+            jams_config,
+            timings: RwLock::new(Arc::new(timings)),
+            reload: Mutex::new(None),
+            metrics,
             stop,
             stop_channel,
-        }
+        })
     }
 
     pub fn run(&self) -> Result<(), failure::Error> {
         info!("Running traffic lights");
 
-        let state_receiver = self.intersection.read().unwrap().state_receiver.clone();
-        let jam_sensor = self
-            .intersection
-            .read()
-            .unwrap()
-            .find_sensor(ComponentUid::new(
-                GroupKind::MotorVehicle,
-                14,
-                ComponentKind::Sensor,
-                1,
-            ))
-            .unwrap();
+        let state_receiver = self.intersection.read_checked()?.state_receiver.clone();
+        let mut jam_detector = JamDetector::new(
+            &self.intersection,
+            &self.jams_config,
+            Arc::clone(&self.metrics),
+        )?;
+        let mut strategy = self.build_strategy()?;
+        let mut clearances = Clearances::new(&*self.groups_config.read_checked()?)?;
 
         loop {
             select! {
                 recv(self.stop_channel) -> _ => {},
                 recv(state_receiver) -> _ => {},
-                recv(after(Duration::from_millis(100))) -> _ => {},
+                recv(self.clock.after(Duration::from_millis(100))) -> _ => {},
             }
 
             if self.stop.load(Acquire) {
                 break;
             }
 
-            if jam_sensor
-                .read()
-                .unwrap()
-                .triggered_for(Duration::from_secs(3), SensorState::High)
-            {
-                warn!("A wild traffic jam appeared, blocking other traffic.");
+            // Stay all red until the intersection is reset.
+            if self.intersection.read_checked()?.monitor.tripped()? {
+                continue;
+            }
 
-                for group in self.intersection.read().unwrap().blockable_groups() {
-                    group.write().unwrap().block = true;
-                }
-            } else {
-                for group in self.intersection.read().unwrap().blockable_groups() {
-                    group.write().unwrap().block = false;
-                }
+            if self.apply_reload()? {
+                strategy = self.build_strategy()?;
+                clearances = Clearances::new(&*self.groups_config.read_checked()?)?;
             }
 
-            let runnables = self.intersection.read().unwrap().get_runnables().unwrap();
+            jam_detector.update()?;
+
+            let runnables = self
+                .intersection
+                .read_checked()?
+                .get_runnables(&mut strategy)?;
 
             if runnables.is_empty() {
                 continue;
             }
 
+            if !self.wait_for_clearance(&clearances, &runnables)? {
+                break;
+            }
+
             info!("Starting a traffic lights phase");
+            self.metrics.phase_started();
 
-            let by_kind = self.runnables_by_group_kind(runnables);
-            let all_times = self.get_times(by_kind.keys().cloned().collect());
+            for group in &runnables {
+                if let Some(wait) = self.waited(group)? {
+                    self.metrics.waited(group.read_checked()?.id, wait);
+                }
+            }
 
+            // Nothing is sent on the phase channel, dropping its sender wakes every group at once,
+            // the finished channel closes once every group is done.
+            let (phase_stop, phase_stop_channel) = unbounded::<()>();
+            let (running, finished) = unbounded::<()>();
             let mut handles = vec![];
 
-            for (kind, runnables) in by_kind {
-                let times = all_times[&kind].clone();
-
+            for (group, times) in self.get_times(runnables)? {
+                let clock = Arc::clone(&self.clock);
                 let stop = Arc::clone(&self.stop);
-                let stop_channel = self.stop_channel.clone();
+                let stop_channel = phase_stop_channel.clone();
+                let running = running.clone();
 
                 handles.push(thread::spawn(move || {
-                    info!("Phase {} for kind {}", LightState::Proceed, kind);
-
-                    for group in runnables.clone() {
-                        for light in group.read().unwrap().lights.values() {
-                            light
-                                .write()
-                                .unwrap()
-                                .set_state(LightState::Proceed)
-                                .unwrap_or_else(|e| error!("{}", e));
-                        }
-                    }
-                    select! {
-                        recv(after(times[&LightState::Proceed])) -> _ => {},
-                        recv(stop_channel) -> _ => {},
-                    };
-
-                    if stop.load(Acquire) {
-                        return;
-                    }
-
-                    info!("Phase {} for kind {}", LightState::Transitioning, kind);
-
-                    for group in runnables.clone() {
-                        for light in group.read().unwrap().lights.values() {
-                            light
-                                .write()
-                                .unwrap()
-                                .set_state(LightState::Transitioning)
-                                .unwrap_or_else(|e| error!("{}", e));
-                        }
-                    }
-
-                    select! {
-                        recv(after(times[&LightState::Transitioning])) -> _ => {},
-                        recv(stop_channel) -> _ => {},
-                    };
-
-                    if stop.load(Acquire) {
-                        return;
-                    }
-
-                    for group in runnables.clone() {
-                        for light in group.read().unwrap().lights.values() {
-                            light
-                                .write()
-                                .unwrap()
-                                .set_state(LightState::Prohibit)
-                                .unwrap_or_else(|e| error!("{}", e));
-                        }
-
-                        group
-                            .write()
-                            .unwrap()
-                            .reset_score()
-                            .unwrap_or_else(|e| error!("{}", e));
-                    }
+                    let _running = running;
+                    Self::run_group(group, times, clock, stop, stop_channel)
                 }));
             }
 
-            for handle in handles {
-                handle.join().expect("Could not join sub threads");
-            }
+            drop(running);
 
-            if self.stop.load(Acquire) {
-                break;
+            // A stop message reaches a single receiver, so the groups hear of it through the
+            // phase channel closing.
+            select! {
+                recv(finished) -> _ => {},
+                recv(self.stop_channel) -> _ => drop(phase_stop),
             }
 
-            select! {
-                recv(after(Duration::from_secs(1))) -> _ => {},
-                recv(self.stop_channel) -> _ => {},
-            };
+            // Join every group before bailing out, so no group is left running on its own.
+            let results: Vec<_> = handles.into_iter().map(|handle| handle.join()).collect();
+
+            for result in results {
+                result.map_err(Error::from_panic)??;
+            }
 
             if self.stop.load(Acquire) {
                 break;
@@ -182,86 +183,393 @@ impl TrafficLightsRunner {
         Ok(())
     }
 
-    fn runnables_by_group_kind(
+    /// Prepares new timings and block relations, they're applied at the next phase boundary so
+    /// no running phase is cut short.
+    pub fn reload(
         &self,
-        runnables: Vec<ArcGroup>,
-    ) -> HashMap<GroupKind, Vec<ArcGroup>> {
-        let mut map: HashMap<GroupKind, Vec<ArcGroup>> = HashMap::new();
+        groups_config: ConfigGroups,
+        blocks: Blocks,
+    ) -> Result<(), failure::Error> {
+        let timings = Timings::new(&**self.intersection.read_checked()?, &groups_config)?;
+        strategies::build(&groups_config.strategy)?;
+
+        *self.reload.lock_checked()? = Some(Reload {
+            groups_config,
+            blocks,
+            timings,
+        });
+
+        Ok(())
+    }
+
+    /// Applies a pending reload, returns whether there was one.
+    fn apply_reload(&self) -> Result<bool, failure::Error> {
+        let reload = match self.reload.lock_checked()?.take() {
+            Some(reload) => reload,
+            None => return Ok(false),
+        };
+
+        IntersectionsBuilder::rebuild_blocks(&reload.blocks, &self.intersection)?;
+
+        *self.timings.write_checked()? = Arc::new(reload.timings);
+        *self.groups_config.write_checked()? = reload.groups_config;
+
+        info!("Applied the reloaded timings and blocks");
+
+        Ok(true)
+    }
+
+    /// How long a group with traffic has been waiting, from the oldest sensor still detecting it
+    /// or since its score became positive, but no longer than since its lights last changed. The
+    /// group is unlocked before its sensors and lights are read.
+    fn waited(&self, group: &ArcGroup) -> Result<Option<Duration>, Error> {
+        let (score, mut since, sensors, lights) = {
+            let group = group.read_checked()?;
+
+            (
+                group.score,
+                group.waiting_since,
+                group.sensors(),
+                group.lights.values().cloned().collect::<Vec<_>>(),
+            )
+        };
+
+        if score <= 0 {
+            return Ok(None);
+        }
 
-        for runnable in runnables {
-            let kind = runnable.read().unwrap().id.kind;
+        for sensor in sensors {
+            let sensor = sensor.read_checked()?;
 
-            if let Some(runnables) = map.get_mut(&kind) {
-                runnables.push(Arc::clone(&runnable));
-            } else {
-                map.insert(kind, vec![Arc::clone(&runnable)]);
+            if sensor.state() == SensorState::High {
+                let timestamp = sensor.timestamp();
+                since = Some(since.map_or(timestamp, |since| since.min(timestamp)));
             }
         }
 
-        map
+        // A sensor that stayed high through the last phase was served by it.
+        for light in lights {
+            let changed = light.read_checked()?.timestamp();
+            since = since.map(|since| since.max(changed));
+        }
+
+        Ok(since.map(|since| self.clock.since(since)))
     }
 
-    fn get_times(
-        &self,
-        kinds: Vec<GroupKind>,
-    ) -> HashMap<GroupKind, HashMap<LightState, Duration>> {
-        let largest_total_time = self.largest_total_time(kinds.clone());
+    fn build_strategy(&self) -> Result<StarvationProtection, failure::Error> {
+        Ok(StarvationProtection::new(
+            strategies::build(&self.groups_config.read_checked()?.strategy)?,
+            Arc::clone(&*self.timings.read_checked()?),
+        ))
+    }
 
-        let mut map: HashMap<GroupKind, HashMap<LightState, Duration>> = HashMap::new();
+    /// Runs a single group through its phase. The group stays green for its minimum time, after
+    /// that it is extended step by step for as long as its sensors keep detecting traffic, up to
+    /// its maximum time.
+    fn run_group(
+        group: ArcGroup,
+        times: PhaseTimes,
+        clock: ArcClock,
+        stop: Arc<AtomicBool>,
+        stop_channel: Receiver<()>,
+    ) -> Result<(), failure::Error> {
+        let id = group.read_checked()?.id;
 
-        for kind in kinds {
-            let config = self.group_config(kind);
+        info!("Phase {} for group {}", LightState::Proceed, id);
+        Self::set_lights(&group, LightState::Proceed)?;
 
-            if config.is_none() {
-                continue;
+        let started = clock.now();
+
+        select! {
+            recv(clock.after(times.proceed)) -> _ => {},
+            recv(stop_channel) -> _ => {},
+        };
+
+        if stop.load(Acquire) {
+            return Ok(());
+        }
+
+        loop {
+            let elapsed = clock.since(started);
+
+            if elapsed >= times.max_proceed {
+                break;
             }
 
-            let config = config.unwrap();
-            let total_time = self.total_time(kind);
+            let step = times.gap.min(times.max_proceed - elapsed);
 
-            let mut state_map: HashMap<LightState, Duration> = HashMap::new();
+            match Self::wait_for_demand(&group, step, &clock, &stop, &stop_channel)? {
+                Some(true) => debug!("Extending phase for group {}", id),
+                Some(false) => break,
+                None => return Ok(()),
+            }
+        }
 
-            state_map.insert(
-                LightState::Proceed,
-                Duration::from_millis(config.min_go_time as u64)
-                    + (largest_total_time - total_time),
-            );
-            state_map.insert(
-                LightState::Transitioning,
-                Duration::from_millis(config.min_transition_time as u64),
-            );
+        info!("Phase {} for group {}", LightState::Transitioning, id);
+        Self::set_lights(&group, LightState::Transitioning)?;
 
-            map.insert(kind, state_map);
+        select! {
+            recv(clock.after(times.transitioning)) -> _ => {},
+            recv(stop_channel) -> _ => {},
+        };
+
+        if stop.load(Acquire) {
+            return Ok(());
         }
 
-        map
+        Self::set_lights(&group, LightState::Prohibit)?;
+
+        group
+            .write_checked()?
+            .reset_score()
+            .unwrap_or_else(|e| error!("{}", e));
+
+        Ok(())
     }
 
-    fn largest_total_time(&self, kinds: Vec<GroupKind>) -> Duration {
-        let mut largest: Duration = Duration::from_millis(0);
+    /// Waits for the given time and tells whether one of the group's sensors detected traffic in
+    /// the meantime, `None` when the runner was stopped.
+    fn wait_for_demand(
+        group: &ArcGroup,
+        time: Duration,
+        clock: &ArcClock,
+        stop: &Arc<AtomicBool>,
+        stop_channel: &Receiver<()>,
+    ) -> Result<Option<bool>, Error> {
+        let receiver = group.read_checked()?.sensor_receiver.clone();
+        let deadline = clock.after(time);
+        let mut demand = false;
+
+        // Only traffic arriving from here on counts.
+        while receiver.try_recv().is_ok() {}
 
-        for kind in kinds {
-            if self.total_time(kind) > largest {
-                largest = self.total_time(kind);
+        loop {
+            select! {
+                recv(receiver) -> _ => demand |= Group::one_sensor_high(group)?,
+                recv(deadline) -> _ => break,
+                recv(stop_channel) -> _ => {},
+            };
+
+            if stop.load(Acquire) {
+                return Ok(None);
             }
         }
 
-        largest
+        Ok(Some(demand || Group::one_sensor_high(group)?))
+    }
+
+    fn set_lights(group: &ArcGroup, state: LightState) -> Result<(), Error> {
+        let lights: Vec<_> = group.read_checked()?.lights.values().cloned().collect();
+
+        for light in lights {
+            light
+                .write_checked()?
+                .set_state(state)
+                .unwrap_or_else(|e| error!("{}", e));
+        }
+
+        Ok(())
     }
 
-    fn total_time(&self, kind: GroupKind) -> Duration {
-        let config = self.group_config(kind).unwrap();
+    /// Waits until every group conflicting with the given groups has been cleared, returns
+    /// `false` when the runner was stopped.
+    fn wait_for_clearance(
+        &self,
+        clearances: &Clearances,
+        groups: &[ArcGroup],
+    ) -> Result<bool, Error> {
+        loop {
+            let remaining = clearances.remaining(&**self.intersection.read_checked()?, groups)?;
+
+            if remaining == Duration::from_millis(0) {
+                return Ok(true);
+            }
 
-        Duration::from_millis(config.min_go_time as u64 + config.min_transition_time as u64)
+            select! {
+                recv(self.clock.after(remaining)) -> _ => {},
+                recv(self.stop_channel) -> _ => {},
+            };
+
+            if self.stop.load(Acquire) {
+                return Ok(false);
+            }
+        }
     }
 
-    fn group_config(&self, kind: GroupKind) -> Option<&ConfigGroup> {
-        for group in &self.groups_config.groups {
-            if group.kind == kind.to_string() {
-                return Some(group);
+    /// Gets the phase times of the given groups. Every group gets extra proceed time so all groups
+    /// reach prohibit at the same time when none of them is extended.
+    fn get_times(&self, groups: Vec<ArcGroup>) -> Result<Vec<(ArcGroup, PhaseTimes)>, Error> {
+        let all_timings = Arc::clone(&*self.timings.read_checked()?);
+
+        let mut timings: Vec<(ArcGroup, Timing)> = vec![];
+
+        for group in groups {
+            let id = group.read_checked()?.id;
+
+            match all_timings.get(id) {
+                Some(timing) => timings.push((group, *timing)),
+                None => error!("Group {} has no timings, skipping it", id),
             }
         }
 
-        None
+        let largest_total_time = timings
+            .iter()
+            .map(|(_, timing)| Self::total_time(timing))
+            .max()
+            .unwrap_or_else(|| Duration::from_millis(0));
+
+        Ok(timings
+            .into_iter()
+            .map(|(group, timing)| {
+                let padding = largest_total_time - Self::total_time(&timing);
+
+                let times = PhaseTimes {
+                    proceed: timing.min_go + padding,
+                    max_proceed: timing.max_go + padding,
+                    gap: timing.gap,
+                    transitioning: timing.min_transition,
+                };
+
+                (group, times)
+            })
+            .collect())
+    }
+
+    fn total_time(timing: &Timing) -> Duration {
+        timing.min_go + timing.min_transition
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering::Release;
+    use std::time::Instant;
+
+    use crossbeam_channel::unbounded;
+
+    use crate::clock::manual::ManualClock;
+    use crate::config::Config;
+    use crate::intersections::component::{ComponentKind, ComponentUid};
+    use crate::intersections::group::GroupKind;
+    use crate::metrics::Metrics;
+
+    #[test]
+    fn test_phase_on_manual_clock() {
+        let config = Config::new("config").unwrap();
+        let clock = Arc::new(ManualClock::new());
+
+        let (sender, _receiver) = unbounded();
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_clock(Arc::clone(&clock) as ArcClock)
+            .with_defs(&config.traffic_lights)
+            .with_blocks(&config.traffic_lights_blocks)
+            .finish()
+            .unwrap();
+
+        let uid = ComponentUid::new(GroupKind::MotorVehicle, 1, ComponentKind::Light, 1);
+        let light = intersection
+            .read()
+            .unwrap()
+            .find_light(uid)
+            .unwrap()
+            .unwrap();
+        let state = || light.read().unwrap().state();
+
+        let (stop_sender, stop_channel) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let runner = TrafficLightsRunner::new(
+            Arc::clone(&intersection),
+            config.groups,
+            config.jams,
+            Arc::new(Metrics::new()),
+            Arc::clone(&stop),
+            stop_channel,
+        )
+        .unwrap();
+        let handle = thread::spawn(move || runner.run());
+
+        let started = Instant::now();
+        let limit = Duration::from_secs(60);
+
+        light
+            .read()
+            .unwrap()
+            .group()
+            .write()
+            .unwrap()
+            .set_score(1)
+            .unwrap();
+
+        clock.run_until(limit, || state() == LightState::Proceed);
+        let proceed = clock.run_until(limit, || state() == LightState::Transitioning);
+        let transitioning = clock.run_until(limit, || state() == LightState::Prohibit);
+
+        // Minimum green plus one gap without traffic, then the minimum transition time.
+        assert!(proceed >= Duration::from_secs(9));
+        assert!(transitioning >= Duration::from_secs(4));
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        stop.store(true, Release);
+        stop_sender.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_stop_reaches_every_group() {
+        let config = Config::new("config").unwrap();
+        let clock = Arc::new(ManualClock::new());
+
+        let (sender, _receiver) = unbounded();
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_clock(Arc::clone(&clock) as ArcClock)
+            .with_defs(&config.traffic_lights)
+            .with_blocks(&config.traffic_lights_blocks)
+            .finish()
+            .unwrap();
+        let groups = intersection.read().unwrap().groups();
+
+        let (stop_sender, stop_channel) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let runner = TrafficLightsRunner::new(
+            Arc::clone(&intersection),
+            config.groups,
+            config.jams,
+            Arc::new(Metrics::new()),
+            Arc::clone(&stop),
+            stop_channel,
+        )
+        .unwrap();
+        let handle = thread::spawn(move || runner.run());
+
+        for group in &groups {
+            group.write().unwrap().set_score(1).unwrap();
+        }
+
+        let proceeding = || {
+            groups
+                .iter()
+                .filter(|group| {
+                    let lights: Vec<_> = group.read().unwrap().lights.values().cloned().collect();
+                    lights
+                        .iter()
+                        .any(|light| light.read().unwrap().state() == LightState::Proceed)
+                })
+                .count()
+        };
+
+        clock.run_until(Duration::from_secs(60), || proceeding() > 1);
+
+        // Without advancing the clock, every group has to notice the stop by itself.
+        stop.store(true, Release);
+        stop_sender.send(()).unwrap();
+
+        let started = Instant::now();
+        while !handle.is_finished() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(handle.is_finished());
+        handle.join().unwrap().unwrap();
     }
 }
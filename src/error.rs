@@ -0,0 +1,90 @@
+use std::any::Any;
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::intersections::component::ComponentUid;
+use crate::intersections::group::GroupId;
+
+/// Errors of the runtime path, i.e. of everything that runs after the intersections are built.
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "A lock was poisoned by a thread that panicked while holding it")]
+    Poisoned,
+
+    #[fail(display = "Component {} was not found", uid)]
+    ComponentNotFound { uid: ComponentUid },
+
+    #[fail(display = "Group {} was not found", id)]
+    GroupNotFound { id: GroupId },
+
+    #[fail(display = "Thread panicked: {}", message)]
+    Panicked { message: String },
+}
+
+impl Error {
+    /// Wraps the payload of a caught panic, as returned when joining a panicked thread.
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => String::from(*message),
+                Err(_) => String::from("unknown cause"),
+            },
+        };
+
+        Error::Panicked { message }
+    }
+}
+
+/// Access to a `RwLock` that reports poisoning as an error instead of panicking.
+pub trait RwLockExt<T: ?Sized> {
+    fn read_checked(&self) -> Result<RwLockReadGuard<'_, T>, Error>;
+    fn write_checked(&self) -> Result<RwLockWriteGuard<'_, T>, Error>;
+}
+
+impl<T: ?Sized> RwLockExt<T> for RwLock<T> {
+    fn read_checked(&self) -> Result<RwLockReadGuard<'_, T>, Error> {
+        self.read().map_err(|_| Error::Poisoned)
+    }
+
+    fn write_checked(&self) -> Result<RwLockWriteGuard<'_, T>, Error> {
+        self.write().map_err(|_| Error::Poisoned)
+    }
+}
+
+/// Access to a `Mutex` that reports poisoning as an error instead of panicking.
+pub trait MutexExt<T: ?Sized> {
+    fn lock_checked(&self) -> Result<MutexGuard<'_, T>, Error>;
+}
+
+impl<T: ?Sized> MutexExt<T> for Mutex<T> {
+    fn lock_checked(&self) -> Result<MutexGuard<'_, T>, Error> {
+        self.lock().map_err(|_| Error::Poisoned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_poisoned_lock() {
+        let lock = Arc::new(RwLock::new(0));
+
+        let poisoner = Arc::clone(&lock);
+        let result = thread::spawn(move || {
+            let _guard = poisoner.write().unwrap();
+            panic!("poisoning the lock");
+        })
+        .join();
+
+        match Error::from_panic(result.unwrap_err()) {
+            Error::Panicked { message } => assert_eq!(message, "poisoning the lock"),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        assert!(matches!(lock.read_checked(), Err(Error::Poisoned)));
+        assert!(matches!(lock.write_checked(), Err(Error::Poisoned)));
+    }
+}
@@ -3,6 +3,7 @@ use std::sync::{Arc, RwLock};
 use chrono::{DateTime, Utc};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 
+use crate::clock::ArcClock;
 use crate::intersections::component::{Component, ComponentId, ComponentState, ComponentUid};
 use crate::intersections::group::ArcGroup;
 
@@ -15,6 +16,7 @@ where
     group: ArcGroup,
 
     id: ComponentId,
+    clock: ArcClock,
 
     state: S,
     initial_state: S,
@@ -28,15 +30,16 @@ impl<S> Actuator<S>
 where
     S: ComponentState + Send,
 {
-    pub fn new(group: ArcGroup, id: ComponentId, initial_state: S) -> Self {
+    pub fn new(group: ArcGroup, id: ComponentId, clock: ArcClock, initial_state: S) -> Self {
         let (sender, receiver) = unbounded();
 
         Self {
             group,
             id,
+            timestamp: clock.now(),
+            clock,
             state: initial_state,
             initial_state,
-            timestamp: Utc::now(),
             sender,
             receiver,
         }
@@ -69,13 +72,17 @@ where
 
     fn set_state_internal(&mut self, state: S) {
         self.state = state;
-        self.timestamp = Utc::now();
+        self.timestamp = self.clock.now();
     }
 
     fn timestamp(&self) -> DateTime<Utc> {
         self.timestamp
     }
 
+    fn clock(&self) -> ArcClock {
+        Arc::clone(&self.clock)
+    }
+
     fn id(&self) -> ComponentId {
         self.id
     }
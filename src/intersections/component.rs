@@ -8,6 +8,8 @@ use colored::{Color, Colorize};
 use crossbeam_channel::{Receiver, Sender};
 use failure;
 
+use crate::clock::ArcClock;
+use crate::error::{Error, RwLockExt};
 use crate::intersections::group::{ArcGroup, GroupId, GroupKind};
 
 #[derive(Debug, Fail)]
@@ -81,6 +83,12 @@ impl Debug for ComponentId {
     }
 }
 
+#[derive(Debug, Fail)]
+#[fail(display = "Could not build component uid; invalid format: {}", uid)]
+pub struct ComponentUidBuildError {
+    uid: String,
+}
+
 #[derive(Copy, Clone, Hash, Eq, PartialEq)]
 pub struct ComponentUid {
     pub group_id: GroupId,
@@ -113,6 +121,28 @@ impl Display for ComponentUid {
     }
 }
 
+impl TryFrom<&str> for ComponentUid {
+    type Error = failure::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let parts: Vec<&str> = value.split('/').collect();
+
+        if parts.len() != 4 {
+            return Err(ComponentUidBuildError {
+                uid: String::from(value),
+            }
+            .into());
+        }
+
+        Ok(Self::new(
+            GroupKind::try_from(parts[0])?,
+            parts[1].parse::<i32>()?,
+            ComponentKind::try_from(parts[2])?,
+            parts[3].parse::<i32>()?,
+        ))
+    }
+}
+
 impl Debug for ComponentUid {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{:?}{:?}", self.group_id, self.component_id)
@@ -122,6 +152,10 @@ impl Debug for ComponentUid {
 pub trait ComponentState:
     Clone + Copy + Default + Display + PartialEq + Into<i32> + TryFrom<i32>
 {
+    /// Checks whether a component may be set to this state, called before every transition.
+    fn guard(self, _uid: ComponentUid, _group: &ArcGroup) -> Result<(), failure::Error> {
+        Ok(())
+    }
 }
 
 pub trait Component<S>: Send
@@ -137,26 +171,31 @@ where
     fn initial_state(&self) -> S;
     fn set_state_internal(&mut self, state: S);
     fn timestamp(&self) -> DateTime<Utc>;
+    fn clock(&self) -> ArcClock;
 
     fn id(&self) -> ComponentId;
 
     fn set_state(&mut self, state: S) -> Result<(), failure::Error> {
-        debug!("Setting state on {:?} to {}", self.uid(), state);
+        let uid = self.uid()?;
+
+        debug!("Setting state on {:?} to {}", uid, state);
+
+        state.guard(uid, &self.group())?;
 
         self.set_state_internal(state);
-        self.sender().send(self.uid())?;
-        self.group().read().unwrap().send_actuator(self.uid())?;
+        self.sender().send(uid)?;
+        self.group().read_checked()?.send_actuator(uid)?;
 
         Ok(())
     }
 
-    fn uid(&self) -> ComponentUid {
-        let group_id = self.group().read().unwrap().id;
+    fn uid(&self) -> Result<ComponentUid, Error> {
+        let group_id = self.group().read_checked()?.id;
 
-        ComponentUid {
+        Ok(ComponentUid {
             group_id,
             component_id: self.id(),
-        }
+        })
     }
 
     fn reset(&mut self) -> Result<(), failure::Error> {
@@ -165,7 +204,35 @@ where
         Ok(())
     }
 
+    /// Whether the component has been in the given state for at least the given duration. A
+    /// timestamp in the future, i.e. the clock went backwards, counts as no time at all.
     fn triggered_for(&self, duration: Duration, state: S) -> bool {
-        self.state() == state && (Utc::now() - self.timestamp()).to_std().unwrap() >= duration
+        self.state() == state && self.clock().since(self.timestamp()) >= duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_uid() {
+        let uid = ComponentUid::try_from("bridge/1/gate/2");
+
+        assert!(uid.is_ok());
+
+        let uid = uid.unwrap();
+
+        assert_eq!(uid.group_id.kind, GroupKind::Bridge);
+        assert_eq!(uid.group_id.id, 1);
+        assert_eq!(uid.component_id.kind, ComponentKind::Gate);
+        assert_eq!(uid.component_id.id, 2);
+    }
+
+    #[test]
+    fn test_invalid_uid() {
+        assert!(ComponentUid::try_from("bridge/1/gate").is_err());
+        assert!(ComponentUid::try_from("4/bridge/1/gate/2").is_err());
+        assert!(ComponentUid::try_from("bridge/one/gate/2").is_err());
     }
 }
@@ -0,0 +1,212 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use failure::Fail;
+
+use crate::error::{Error, MutexExt, RwLockExt};
+use crate::intersections::component::ComponentUid;
+use crate::intersections::group::{ArcGroup, GroupId};
+use crate::intersections::light::LightState;
+
+#[derive(Debug, Fail)]
+pub enum ConflictError {
+    #[fail(
+        display = "Refused to set {} to {}, it conflicts with {} which is {}",
+        uid, state, conflicting, conflicting_state
+    )]
+    Conflict {
+        uid: ComponentUid,
+        state: LightState,
+        conflicting: ComponentUid,
+        conflicting_state: LightState,
+    },
+
+    #[fail(
+        display = "Refused to set {} to {}, the intersection is in fail-safe mode",
+        uid, state
+    )]
+    FailSafe {
+        uid: ComponentUid,
+        state: LightState,
+    },
+}
+
+struct MonitorState {
+    conflicts: HashSet<(GroupId, GroupId)>,
+    lights: HashMap<ComponentUid, LightState>,
+    tripped: bool,
+}
+
+/// Checks every light transition against the block graph, independent of the scheduling logic.
+/// A light may only show proceed or transitioning while no light of a conflicting group does.
+/// Once an unsafe transition has been refused the monitor trips, and only prohibit and out of
+/// order are allowed until it is reset.
+pub struct ConflictMonitor {
+    state: Mutex<MonitorState>,
+}
+
+impl ConflictMonitor {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MonitorState {
+                conflicts: HashSet::new(),
+                lights: HashMap::new(),
+                tripped: false,
+            }),
+        }
+    }
+
+    /// Marks two groups as conflicting, in both directions.
+    pub fn add_conflict(&self, a: GroupId, b: GroupId) -> Result<(), Error> {
+        let mut state = self.state.lock_checked()?;
+
+        state.conflicts.insert((a, b));
+        state.conflicts.insert((b, a));
+
+        Ok(())
+    }
+
+    /// Forgets every conflict, used before the block relations are rebuilt.
+    pub fn clear_conflicts(&self) -> Result<(), Error> {
+        self.state.lock_checked()?.conflicts.clear();
+
+        Ok(())
+    }
+
+    /// Registers the state of a light without checking it, used for initial states.
+    pub fn register(&self, uid: ComponentUid, light_state: LightState) -> Result<(), Error> {
+        self.state.lock_checked()?.lights.insert(uid, light_state);
+
+        Ok(())
+    }
+
+    /// Checks the transition of a light and records it when it is safe, trips the monitor when it
+    /// isn't.
+    pub fn transition(
+        &self,
+        uid: ComponentUid,
+        light_state: LightState,
+    ) -> Result<(), failure::Error> {
+        let mut state = self.state.lock_checked()?;
+
+        let exclusive = Self::exclusive(light_state);
+
+        if state.tripped && exclusive {
+            return Err(ConflictError::FailSafe {
+                uid,
+                state: light_state,
+            }
+            .into());
+        }
+
+        if exclusive {
+            let conflict = state.lights.iter().find(|(other, other_state)| {
+                Self::exclusive(**other_state)
+                    && state.conflicts.contains(&(uid.group_id, other.group_id))
+            });
+
+            if let Some((conflicting, conflicting_state)) = conflict {
+                let error = ConflictError::Conflict {
+                    uid,
+                    state: light_state,
+                    conflicting: *conflicting,
+                    conflicting_state: *conflicting_state,
+                };
+
+                state.tripped = true;
+
+                return Err(error.into());
+            }
+        }
+
+        state.lights.insert(uid, light_state);
+
+        Ok(())
+    }
+
+    pub fn tripped(&self) -> Result<bool, Error> {
+        Ok(self.state.lock_checked()?.tripped)
+    }
+
+    /// Leaves fail-safe mode.
+    pub fn reset(&self) -> Result<(), Error> {
+        self.state.lock_checked()?.tripped = false;
+
+        Ok(())
+    }
+
+    /// Whether a light state lets traffic into the conflict area.
+    fn exclusive(state: LightState) -> bool {
+        state == LightState::Proceed || state == LightState::Transitioning
+    }
+}
+
+/// Guards a light transition in the group's intersection. When the transition is refused, all
+/// lights of the intersection are set to prohibit. That happens on a separate thread, since the
+/// caller still holds the lock of the light that is being set.
+pub fn guard_light(
+    uid: ComponentUid,
+    group: &ArcGroup,
+    state: LightState,
+) -> Result<(), failure::Error> {
+    let intersection = Arc::clone(&group.read_checked()?.intersection);
+    let result = intersection.read_checked()?.monitor.transition(uid, state);
+
+    if let Err(e) = &result {
+        if let Some(ConflictError::Conflict { .. }) = e.downcast_ref() {
+            error!("Conflicting lights detected, forcing all lights to prohibit");
+
+            thread::spawn(move || match intersection.read_checked() {
+                Ok(intersection) => intersection.fail_safe().unwrap_or_else(|e| error!("{}", e)),
+                Err(e) => error!("{}", e),
+            });
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intersections::component::ComponentKind;
+    use crate::intersections::group::GroupKind;
+
+    fn light(group: i32) -> ComponentUid {
+        ComponentUid::new(GroupKind::MotorVehicle, group, ComponentKind::Light, 1)
+    }
+
+    #[test]
+    fn test_refuses_conflicting_proceed() {
+        let monitor = ConflictMonitor::new();
+        monitor
+            .add_conflict(light(1).group_id, light(2).group_id)
+            .unwrap();
+
+        assert!(monitor.transition(light(1), LightState::Proceed).is_ok());
+        assert!(monitor.transition(light(3), LightState::Proceed).is_ok());
+        assert!(monitor.transition(light(2), LightState::Proceed).is_err());
+        assert!(monitor.tripped().unwrap());
+    }
+
+    #[test]
+    fn test_fail_safe_only_allows_prohibit() {
+        let monitor = ConflictMonitor::new();
+        monitor
+            .add_conflict(light(1).group_id, light(2).group_id)
+            .unwrap();
+
+        monitor
+            .transition(light(1), LightState::Transitioning)
+            .unwrap();
+        assert!(monitor.transition(light(2), LightState::Proceed).is_err());
+
+        assert!(monitor.transition(light(1), LightState::Prohibit).is_ok());
+        assert!(monitor.transition(light(3), LightState::Proceed).is_err());
+
+        monitor.reset().unwrap();
+
+        assert!(monitor.transition(light(2), LightState::Proceed).is_ok());
+    }
+}
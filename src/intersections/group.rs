@@ -2,9 +2,12 @@ use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 
+use crate::error::{Error, RwLockExt};
 use crate::intersections::actuator::ArcActuator;
 use crate::intersections::component::{Component, ComponentId, ComponentKind, ComponentUid};
 use crate::intersections::deck::DeckState;
@@ -72,6 +75,12 @@ impl TryFrom<&str> for GroupKind {
     }
 }
 
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid group id: {}", group_id)]
+pub struct InvalidGroupId {
+    group_id: String,
+}
+
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub struct GroupId {
     pub kind: GroupKind,
@@ -84,6 +93,26 @@ impl fmt::Display for GroupId {
     }
 }
 
+impl TryFrom<&str> for GroupId {
+    type Error = failure::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let parts: Vec<&str> = value.split('/').collect();
+
+        if parts.len() != 2 {
+            return Err(InvalidGroupId {
+                group_id: String::from(value),
+            }
+            .into());
+        }
+
+        Ok(Self {
+            kind: GroupKind::try_from(parts[0])?,
+            id: parts[1].parse::<i32>()?,
+        })
+    }
+}
+
 impl fmt::Debug for GroupId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}{}", self.kind, self.id)
@@ -95,7 +124,6 @@ pub struct Group {
 
     pub id: GroupId,
 
-    pub can_be_blocked: bool,
     pub block: bool,
 
     pub sensors: HashMap<ComponentId, ArcSensor>,
@@ -104,10 +132,15 @@ pub struct Group {
     pub decks: HashMap<ComponentId, ArcActuator<DeckState>>,
 
     pub score: i32,
+    /// Since when the group has been waiting to be served, i.e. since its score became positive.
+    pub waiting_since: Option<DateTime<Utc>>,
 
     pub blocks: Vec<ArcGroup>,
     pub concurrences: Vec<ArcGroup>,
 
+    /// Clearance times that override the default for groups this group blocks.
+    pub clearances: HashMap<GroupId, Duration>,
+
     pub sensor_receiver: Receiver<ComponentUid>,
     pub light_receiver: Receiver<ComponentUid>,
     pub gate_receiver: Receiver<ComponentUid>,
@@ -122,7 +155,7 @@ pub struct Group {
 }
 
 impl Group {
-    pub fn new(intersection: ArcIntersection, id: GroupId, can_be_blocked: bool) -> Self {
+    pub fn new(intersection: ArcIntersection, id: GroupId) -> Self {
         let (sensor_sender, sensor_receiver) = unbounded();
         let (light_sender, light_receiver) = unbounded();
         let (gate_sender, gate_receiver) = unbounded();
@@ -134,7 +167,6 @@ impl Group {
             intersection,
             id,
 
-            can_be_blocked,
             block: false,
 
             sensors: HashMap::new(),
@@ -143,8 +175,10 @@ impl Group {
             decks: HashMap::new(),
 
             score: 0,
+            waiting_since: None,
             blocks: vec![],
             concurrences: vec![],
+            clearances: HashMap::new(),
 
             sensor_receiver,
             light_receiver,
@@ -189,8 +223,17 @@ impl Group {
     }
 
     pub fn set_score(&mut self, score: i32) -> Result<(), failure::Error> {
+        let intersection = self.intersection.read_checked()?;
+
         self.score = score;
-        self.intersection.read().unwrap().send_score(self.id)?;
+
+        if score <= 0 {
+            self.waiting_since = None;
+        } else if self.waiting_since.is_none() {
+            self.waiting_since = Some(intersection.clock.now());
+        }
+
+        intersection.send_score(self.id)?;
 
         Ok(())
     }
@@ -201,48 +244,66 @@ impl Group {
         Ok(())
     }
 
-    pub fn one_sensor_high(&self) -> bool {
-        for sensor in &self.sensors() {
-            if sensor.read().unwrap().state() == SensorState::High {
-                return true;
+    /// Whether one of the group's sensors is high, the group is only locked to collect them.
+    pub fn one_sensor_high(group: &ArcGroup) -> Result<bool, Error> {
+        let sensors = group.read_checked()?.sensors();
+
+        for sensor in &sensors {
+            if sensor.read_checked()?.state() == SensorState::High {
+                return Ok(true);
             }
         }
 
-        false
+        Ok(false)
     }
 
-    pub fn blocks_group(&self, group: ArcGroup) -> bool {
+    pub fn blocks_group(&self, group: ArcGroup) -> Result<bool, Error> {
+        let id = group.read_checked()?.id;
+
         for g in &self.blocks {
-            if g.read().unwrap().id == group.read().unwrap().id {
-                return true;
+            if g.read_checked()?.id == id {
+                return Ok(true);
             }
         }
 
-        false
+        Ok(false)
     }
 
-    pub fn reset_all(&self) -> Result<(), failure::Error> {
-        for s in self.sensors.values() {
-            s.write().unwrap().reset()?;
+    /// Resets every component of the group. The components lock their group when they change, so
+    /// the group is only locked to collect them; locking it again while a writer waits deadlocks.
+    pub fn reset_all(group: &ArcGroup) -> Result<(), failure::Error> {
+        let (sensors, lights, decks, gates) = {
+            let group = group.read_checked()?;
+
+            (
+                group.sensors(),
+                group.lights.values().cloned().collect::<Vec<_>>(),
+                group.decks.values().cloned().collect::<Vec<_>>(),
+                group.gates.values().cloned().collect::<Vec<_>>(),
+            )
+        };
+
+        for s in sensors {
+            s.write_checked()?.reset()?;
         }
 
-        for l in self.lights.values() {
-            l.write().unwrap().reset()?;
+        for l in lights {
+            l.write_checked()?.reset()?;
         }
 
-        for d in self.decks.values() {
-            d.write().unwrap().reset()?;
+        for d in decks {
+            d.write_checked()?.reset()?;
         }
 
-        for g in self.gates.values() {
-            g.write().unwrap().reset()?;
+        for g in gates {
+            g.write_checked()?.reset()?;
         }
 
         Ok(())
     }
 
     pub fn send(&self, uid: ComponentUid) -> Result<(), failure::Error> {
-        self.intersection.read().unwrap().send_state(uid)?;
+        self.intersection.read_checked()?.send_state(uid)?;
 
         match uid.component_id.kind {
             ComponentKind::Sensor => self.sensor_sender.send(uid),
@@ -3,13 +3,17 @@ use std::sync::{Arc, RwLock};
 
 use crossbeam_channel::{unbounded, Receiver, Sender};
 
+use crate::clock::ArcClock;
+use crate::error::{Error, RwLockExt};
 use crate::intersections::actuator::ArcActuator;
-use crate::intersections::component::ComponentUid;
+use crate::intersections::component::{Component, ComponentUid};
+use crate::intersections::conflict_monitor::ConflictMonitor;
 use crate::intersections::deck::DeckState;
 use crate::intersections::gate::GateState;
 use crate::intersections::group::{ArcGroup, GroupId};
 use crate::intersections::light::LightState;
 use crate::intersections::sensor::ArcSensor;
+use crate::intersections::strategies::PhaseStrategy;
 
 pub type ArcIntersection = Arc<RwLock<Box<Intersection>>>;
 
@@ -21,6 +25,8 @@ pub enum Notification {
 pub struct Intersection {
     pub alias: Option<String>,
     pub groups: HashMap<GroupId, ArcGroup>,
+    pub monitor: ConflictMonitor,
+    pub clock: ArcClock,
 
     pub state_receiver: Receiver<ComponentUid>,
     pub score_receiver: Receiver<GroupId>,
@@ -31,13 +37,19 @@ pub struct Intersection {
 }
 
 impl Intersection {
-    pub fn new(alias: Option<String>, notification_sender: Sender<Notification>) -> Self {
+    pub fn new(
+        alias: Option<String>,
+        notification_sender: Sender<Notification>,
+        clock: ArcClock,
+    ) -> Self {
         let (state_sender, state_receiver) = unbounded();
         let (score_sender, score_receiver) = unbounded();
 
         Self {
             alias,
             groups: HashMap::new(),
+            monitor: ConflictMonitor::new(),
+            clock,
 
             state_receiver,
             score_receiver,
@@ -52,30 +64,53 @@ impl Intersection {
         self.groups.values().map(|g| Arc::clone(&g)).collect()
     }
 
-    pub fn unblocked_groups(&self) -> Vec<ArcGroup> {
-        self.groups()
-            .iter()
-            .filter(|g| !g.read().unwrap().block)
-            .map(|g| Arc::clone(&g))
-            .collect()
-    }
+    pub fn unblocked_groups(&self) -> Result<Vec<ArcGroup>, Error> {
+        let mut groups = vec![];
+
+        for group in self.groups.values() {
+            if !group.read_checked()?.block {
+                groups.push(Arc::clone(&group));
+            }
+        }
 
-    pub fn blockable_groups(&self) -> Vec<ArcGroup> {
-        self.groups()
-            .iter()
-            .filter(|g| g.read().unwrap().can_be_blocked)
-            .map(|g| Arc::clone(&g))
-            .collect()
+        Ok(groups)
     }
 
-    pub fn sensors(&self) -> Vec<ArcSensor> {
+    pub fn sensors(&self) -> Result<Vec<ArcSensor>, Error> {
         let mut lights: Vec<ArcSensor> = vec![];
 
         for group in self.groups.values() {
-            lights.extend(group.read().unwrap().sensors());
+            lights.extend(group.read_checked()?.sensors());
+        }
+
+        Ok(lights)
+    }
+
+    /// The ids of every light, gate and deck.
+    pub fn actuators(&self) -> Result<Vec<ComponentUid>, Error> {
+        let mut actuators = vec![];
+
+        for group in self.groups.values() {
+            let group = group.read_checked()?;
+            let uid = |component_id| ComponentUid {
+                group_id: group.id,
+                component_id,
+            };
+
+            for light in group.lights.values() {
+                actuators.push(uid(light.read_checked()?.id()));
+            }
+
+            for gate in group.gates.values() {
+                actuators.push(uid(gate.read_checked()?.id()));
+            }
+
+            for deck in group.decks.values() {
+                actuators.push(uid(deck.read_checked()?.id()));
+            }
         }
 
-        lights
+        Ok(actuators)
     }
 
     pub fn find_group(&self, id: GroupId) -> Option<ArcGroup> {
@@ -86,76 +121,77 @@ impl Intersection {
         None
     }
 
-    pub fn find_sensor(&self, id: ComponentUid) -> Option<ArcSensor> {
-        let group = self.find_group(id.group_id)?;
-        let sensor = group.read().unwrap().find_sensor(id.component_id)?;
-        Some(Arc::clone(&sensor))
+    pub fn find_sensor(&self, id: ComponentUid) -> Result<Option<ArcSensor>, Error> {
+        match self.find_group(id.group_id) {
+            Some(group) => Ok(group.read_checked()?.find_sensor(id.component_id)),
+            None => Ok(None),
+        }
     }
 
-    pub fn find_light(&self, id: ComponentUid) -> Option<ArcActuator<LightState>> {
-        let group = self.find_group(id.group_id)?;
-        let light = group.read().unwrap().find_light(id.component_id)?;
-        Some(Arc::clone(&light))
+    pub fn find_light(&self, id: ComponentUid) -> Result<Option<ArcActuator<LightState>>, Error> {
+        match self.find_group(id.group_id) {
+            Some(group) => Ok(group.read_checked()?.find_light(id.component_id)),
+            None => Ok(None),
+        }
     }
 
-    pub fn find_gate(&self, id: ComponentUid) -> Option<ArcActuator<GateState>> {
-        let group = self.find_group(id.group_id)?;
-        let gate = group.read().unwrap().find_gate(id.component_id)?;
-        Some(Arc::clone(&gate))
+    pub fn find_gate(&self, id: ComponentUid) -> Result<Option<ArcActuator<GateState>>, Error> {
+        match self.find_group(id.group_id) {
+            Some(group) => Ok(group.read_checked()?.find_gate(id.component_id)),
+            None => Ok(None),
+        }
     }
 
-    pub fn find_deck(&self, id: ComponentUid) -> Option<ArcActuator<DeckState>> {
-        let group = self.find_group(id.group_id)?;
-        let deck = group.read().unwrap().find_deck(id.component_id)?;
-        Some(Arc::clone(&deck))
+    pub fn find_deck(&self, id: ComponentUid) -> Result<Option<ArcActuator<DeckState>>, Error> {
+        match self.find_group(id.group_id) {
+            Some(group) => Ok(group.read_checked()?.find_deck(id.component_id)),
+            None => Ok(None),
+        }
     }
 
-    fn highest_scoring_group(groups: &[ArcGroup]) -> ArcGroup {
-        let mut score = -1;
-        let mut highest = Arc::clone(groups.first().unwrap());
+    pub fn get_runnables(
+        &self,
+        strategy: &mut dyn PhaseStrategy,
+    ) -> Result<Vec<ArcGroup>, failure::Error> {
+        strategy.select(self)
+    }
 
-        for group in groups {
-            if group.read().unwrap().score > score {
-                score = group.read().unwrap().score;
-                highest = Arc::clone(group);
+    /// Sets every light to prohibit.
+    pub fn fail_safe(&self) -> Result<(), failure::Error> {
+        for group in self.groups.values() {
+            // Collected first, the group must not stay locked while they change and lock it.
+            let lights: Vec<_> = group.read_checked()?.lights.values().cloned().collect();
+
+            for light in lights {
+                light.write_checked()?.set_state(LightState::Prohibit)?;
             }
         }
 
-        highest
+        Ok(())
     }
 
-    pub fn get_runnables(&self) -> Result<Vec<ArcGroup>, failure::Error> {
-        let mut groups: Vec<ArcGroup> = vec![];
+    /// Sets every light to prohibit and closes the gates before the decks, a configuration that
+    /// is safe to leave behind without a controller.
+    pub fn secure(&self) -> Result<(), failure::Error> {
+        self.fail_safe()?;
 
-        let highest_scoring = Self::highest_scoring_group(&self.unblocked_groups());
-
-        if highest_scoring.read().unwrap().score == 0 {
-            return Ok(groups);
-        }
+        for group in self.groups.values() {
+            let gates: Vec<_> = group.read_checked()?.gates.values().cloned().collect();
 
-        for group in &highest_scoring.read().unwrap().concurrences {
-            if group.read().unwrap().score <= 0 || group.read().unwrap().block {
-                continue;
+            for gate in gates {
+                gate.write_checked()?.set_state(GateState::Close)?;
             }
+        }
 
-            let mut can_fit = true;
-
-            for block in &group.read().unwrap().blocks {
-                for existing_group in &groups {
-                    if existing_group.read().unwrap().id == block.read().unwrap().id {
-                        can_fit = false;
-                    }
-                }
-            }
+        for group in self.groups.values() {
+            let decks: Vec<_> = group.read_checked()?.decks.values().cloned().collect();
 
-            if !can_fit {
-                continue;
+            for deck in decks {
+                deck.write_checked()?.set_state(DeckState::Close)?;
             }
-
-            groups.push(Arc::clone(&group));
         }
 
-        Ok(groups)
+        Ok(())
     }
 
     pub fn send_state(&self, id: ComponentUid) -> Result<(), failure::Error> {
@@ -1,13 +1,17 @@
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use crossbeam_channel::Sender;
 use failure::Fail;
 
+use crate::clock::{ArcClock, SystemClock};
 use crate::config::blocks::Blocks;
 use crate::config::definitions::{Component as ConfigComponent, Definitions, Group as ConfigGroup};
+use crate::error::{Error, RwLockExt};
 use crate::intersections::actuator::{Actuator, ArcActuator};
-use crate::intersections::component::{ComponentId, ComponentKind};
+use crate::intersections::component::{Component, ComponentId, ComponentKind};
 use crate::intersections::deck::DeckState;
 use crate::intersections::gate::GateState;
 use crate::intersections::group::{Group, GroupId, GroupKind};
@@ -20,10 +24,29 @@ use crate::intersections::sensor::{Sensor, SensorState};
 #[fail(display = "Intersection build error")]
 pub struct IntersectionBuildError;
 
+#[derive(Debug, Fail)]
+#[fail(display = "Group {} blocks unknown group {}", group, block)]
+pub struct UnknownBlockedGroup {
+    group: GroupId,
+    block: GroupId,
+}
+
+#[derive(Debug, Fail)]
+#[fail(
+    display = "Group {} has a negative clearance to group {}: {}",
+    group, block, clearance
+)]
+pub struct NegativeClearance {
+    group: GroupId,
+    block: GroupId,
+    clearance: i32,
+}
+
 pub struct IntersectionsBuilder<'a> {
     defs: Option<&'a Definitions>,
     blocks: Option<&'a Blocks>,
     notification_sender: Sender<Notification>,
+    clock: ArcClock,
 }
 
 impl<'a> IntersectionsBuilder<'a> {
@@ -32,6 +55,7 @@ impl<'a> IntersectionsBuilder<'a> {
             defs: None,
             blocks: None,
             notification_sender,
+            clock: Arc::new(SystemClock),
         }
     }
 
@@ -45,6 +69,12 @@ impl<'a> IntersectionsBuilder<'a> {
         self
     }
 
+    /// Sets the clock of the intersection and its components, the system clock by default.
+    pub fn with_clock(mut self, clock: ArcClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn finish(&self) -> Result<ArcIntersection, failure::Error> {
         let defs = match self.defs {
             Some(defs) => defs,
@@ -56,11 +86,91 @@ impl<'a> IntersectionsBuilder<'a> {
         let intersection = Arc::new(RwLock::new(Box::new(Intersection::new(
             None,
             self.notification_sender.clone(),
+            Arc::clone(&self.clock),
         ))));
 
         self.build_groups(&defs.groups, Arc::clone(&intersection))?;
 
-        Ok(self.fill_concurrences(self.fill_blocks(intersection)?)?)
+        if let Some(blocks) = self.blocks {
+            Self::fill_blocks(blocks, &intersection)?;
+        }
+
+        Self::fill_concurrences(&intersection)?;
+
+        Ok(intersection)
+    }
+
+    /// Replaces the block relations of an intersection that was built before.
+    pub fn rebuild_blocks(
+        blocks: &Blocks,
+        intersection: &ArcIntersection,
+    ) -> Result<(), failure::Error> {
+        intersection.read_checked()?.monitor.clear_conflicts()?;
+
+        for group in intersection.read_checked()?.groups() {
+            let mut group = group.write_checked()?;
+
+            group.blocks.clear();
+            group.concurrences.clear();
+            group.clearances.clear();
+        }
+
+        Self::fill_blocks(blocks, intersection)?;
+        Self::fill_concurrences(intersection)?;
+
+        Ok(())
+    }
+
+    /// Whether the definitions describe exactly the groups and components of the intersection.
+    pub fn same_topology(
+        defs: &Definitions,
+        intersection: &ArcIntersection,
+    ) -> Result<bool, Error> {
+        let mut defined = HashSet::new();
+
+        for group in &defs.groups {
+            let kind = match GroupKind::try_from(&group.kind[..]) {
+                Ok(kind) => kind,
+                Err(_) => return Ok(false),
+            };
+
+            defined.insert((GroupId { kind, id: group.id }, None));
+
+            for component in group.components.iter().flatten() {
+                let component_kind = match ComponentKind::try_from(&component.kind[..]) {
+                    Ok(component_kind) => component_kind,
+                    Err(_) => return Ok(false),
+                };
+
+                defined.insert((
+                    GroupId { kind, id: group.id },
+                    Some(ComponentId {
+                        kind: component_kind,
+                        id: component.id,
+                    }),
+                ));
+            }
+        }
+
+        let mut built = HashSet::new();
+
+        for group in intersection.read_checked()?.groups() {
+            let group = group.read_checked()?;
+
+            built.insert((group.id, None));
+
+            for id in group
+                .lights
+                .keys()
+                .chain(group.sensors.keys())
+                .chain(group.gates.keys())
+                .chain(group.decks.keys())
+            {
+                built.insert((group.id, Some(*id)));
+            }
+        }
+
+        Ok(defined == built)
     }
 
     pub fn build_groups(
@@ -77,10 +187,6 @@ impl<'a> IntersectionsBuilder<'a> {
             let group = Arc::new(RwLock::new(Box::new(Group::new(
                 Arc::clone(&intersection),
                 id,
-                match conf_group.can_be_blocked {
-                    Some(can_be_blocked) => can_be_blocked,
-                    None => false,
-                },
             ))));
 
             if let Some(conf_cmpts) = &conf_group.components {
@@ -88,8 +194,7 @@ impl<'a> IntersectionsBuilder<'a> {
             }
 
             intersection
-                .write()
-                .unwrap()
+                .write_checked()?
                 .groups
                 .insert(id, Arc::clone(&group));
         }
@@ -113,6 +218,7 @@ impl<'a> IntersectionsBuilder<'a> {
                     let component = Arc::new(RwLock::new(Box::new(Sensor::new(
                         Arc::clone(&group),
                         id,
+                        Arc::clone(&self.clock),
                         match conf_compt.initial_state {
                             Some(state) => SensorState::try_from(state)?,
                             None => SensorState::default(),
@@ -123,46 +229,59 @@ impl<'a> IntersectionsBuilder<'a> {
                         },
                     ))));
 
-                    group.write().unwrap().sensors.insert(id, component);
+                    group.write_checked()?.sensors.insert(id, component);
                 }
                 ComponentKind::Light => {
                     let component: ArcActuator<LightState> =
                         Arc::new(RwLock::new(Box::new(Actuator::new(
                             Arc::clone(&group),
                             id,
+                            Arc::clone(&self.clock),
                             match conf_compt.initial_state {
                                 Some(state) => LightState::try_from(state)?,
                                 None => LightState::default(),
                             },
                         ))));
 
-                    group.write().unwrap().lights.insert(id, component);
+                    let uid = component.read_checked()?.uid()?;
+                    let state = component.read_checked()?.state();
+
+                    group
+                        .read_checked()?
+                        .intersection
+                        .read_checked()?
+                        .monitor
+                        .register(uid, state)?;
+
+                    group.write_checked()?.lights.insert(id, component);
                 }
                 ComponentKind::Gate => {
                     let component: ArcActuator<GateState> =
                         Arc::new(RwLock::new(Box::new(Actuator::new(
                             Arc::clone(&group),
                             id,
+                            Arc::clone(&self.clock),
                             match conf_compt.initial_state {
                                 Some(state) => GateState::try_from(state)?,
                                 None => GateState::default(),
                             },
                         ))));
 
-                    group.write().unwrap().gates.insert(id, component);
+                    group.write_checked()?.gates.insert(id, component);
                 }
                 ComponentKind::Deck => {
                     let component: ArcActuator<DeckState> =
                         Arc::new(RwLock::new(Box::new(Actuator::new(
                             Arc::clone(&group),
                             id,
+                            Arc::clone(&self.clock),
                             match conf_compt.initial_state {
                                 Some(state) => DeckState::try_from(state)?,
                                 None => DeckState::default(),
                             },
                         ))));
 
-                    group.write().unwrap().decks.insert(id, component);
+                    group.write_checked()?.decks.insert(id, component);
                 }
             };
         }
@@ -170,65 +289,189 @@ impl<'a> IntersectionsBuilder<'a> {
         Ok(())
     }
 
-    fn fill_blocks(
-        &self,
-        intersection: ArcIntersection,
-    ) -> Result<ArcIntersection, failure::Error> {
-        if self.blocks.is_none() {
-            return Ok(intersection);
-        }
-
-        for blocked_group in &self.blocks.unwrap().groups {
-            let actual_group = intersection.read().unwrap().find_group(GroupId {
+    fn fill_blocks(blocks: &Blocks, intersection: &ArcIntersection) -> Result<(), failure::Error> {
+        for blocked_group in &blocks.groups {
+            let actual_group = match intersection.read_checked()?.find_group(GroupId {
                 id: blocked_group.id,
                 kind: GroupKind::try_from(&blocked_group.kind[..])?,
-            });
-
-            if actual_group.is_none() {
-                continue;
-            }
-
-            let actual_group = actual_group.unwrap();
+            }) {
+                Some(group) => group,
+                None => continue,
+            };
 
             for block in &blocked_group.blocks {
-                let found_group = intersection
-                    .read()
-                    .unwrap()
-                    .find_group(GroupId {
-                        kind: GroupKind::try_from(&block.kind[..])?,
-                        id: block.id,
-                    })
-                    .unwrap();
+                let block_id = GroupId {
+                    kind: GroupKind::try_from(&block.kind[..])?,
+                    id: block.id,
+                };
+
+                let found_group = match intersection.read_checked()?.find_group(block_id) {
+                    Some(group) => group,
+                    None => {
+                        return Err(UnknownBlockedGroup {
+                            group: actual_group.read_checked()?.id,
+                            block: block_id,
+                        }
+                        .into());
+                    }
+                };
+
+                let clearance = match block.clearance {
+                    Some(clearance) => match u64::try_from(clearance) {
+                        Ok(clearance) => Some(Duration::from_millis(clearance)),
+                        Err(_) => {
+                            return Err(NegativeClearance {
+                                group: actual_group.read_checked()?.id,
+                                block: block_id,
+                                clearance,
+                            }
+                            .into());
+                        }
+                    },
+                    None => None,
+                };
 
                 actual_group
-                    .write()
-                    .unwrap()
+                    .write_checked()?
                     .push_block(Arc::clone(&found_group));
+
+                intersection.read_checked()?.monitor.add_conflict(
+                    actual_group.read_checked()?.id,
+                    found_group.read_checked()?.id,
+                )?;
+
+                if let Some(clearance) = clearance {
+                    let found_id = found_group.read_checked()?.id;
+
+                    actual_group
+                        .write_checked()?
+                        .clearances
+                        .insert(found_id, clearance);
+                }
             }
         }
 
-        Ok(intersection)
+        Ok(())
     }
 
-    fn fill_concurrences(
-        &self,
-        intersection: ArcIntersection,
-    ) -> Result<ArcIntersection, failure::Error> {
-        for outer_group in intersection.read().unwrap().groups() {
-            for inner_group in intersection.read().unwrap().groups() {
+    fn fill_concurrences(intersection: &ArcIntersection) -> Result<(), Error> {
+        for outer_group in intersection.read_checked()?.groups() {
+            for inner_group in intersection.read_checked()?.groups() {
                 if !outer_group
-                    .read()
-                    .unwrap()
-                    .blocks_group(Arc::clone(&inner_group))
+                    .read_checked()?
+                    .blocks_group(Arc::clone(&inner_group))?
                 {
                     outer_group
-                        .write()
-                        .unwrap()
+                        .write_checked()?
                         .push_concurrent(Arc::clone(&inner_group));
                 }
             }
         }
 
-        Ok(intersection)
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::blocks::{Block, Group as BlocksGroup};
+    use crate::intersections::group::ArcGroup;
+    use crate::intersections::strategies::conflicts;
+    use crossbeam_channel::unbounded;
+
+    fn defs() -> Definitions {
+        let group = |id| ConfigGroup {
+            kind: String::from("cycle"),
+            id,
+            components: Some(vec![ConfigComponent {
+                kind: String::from("light"),
+                id: 1,
+                distance: None,
+                initial_state: None,
+            }]),
+        };
+
+        Definitions {
+            groups: vec![group(1), group(2), group(3)],
+        }
+    }
+
+    /// Lets cycle group 1 and the given group block each other.
+    fn blocks(id: i32) -> Blocks {
+        let group = |id, block| BlocksGroup {
+            kind: String::from("cycle"),
+            id,
+            blocks: vec![Block {
+                kind: String::from("cycle"),
+                id: block,
+                clearance: None,
+            }],
+        };
+
+        Blocks {
+            groups: vec![group(1, id), group(id, 1)],
+        }
+    }
+
+    fn cycle(intersection: &ArcIntersection, id: i32) -> ArcGroup {
+        intersection
+            .read()
+            .unwrap()
+            .find_group(GroupId {
+                kind: GroupKind::Cycle,
+                id,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rebuild_blocks() {
+        let (sender, _receiver) = unbounded();
+        let defs = defs();
+        let blocks = blocks(2);
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_defs(&defs)
+            .with_blocks(&blocks)
+            .finish()
+            .unwrap();
+
+        assert!(conflicts(&cycle(&intersection, 1), &cycle(&intersection, 2)).unwrap());
+
+        IntersectionsBuilder::rebuild_blocks(&self::blocks(3), &intersection).unwrap();
+
+        assert!(!conflicts(&cycle(&intersection, 1), &cycle(&intersection, 2)).unwrap());
+        assert!(conflicts(&cycle(&intersection, 1), &cycle(&intersection, 3)).unwrap());
+    }
+
+    #[test]
+    fn test_same_topology() {
+        let (sender, _receiver) = unbounded();
+        let defs = defs();
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_defs(&defs)
+            .finish()
+            .unwrap();
+
+        assert!(IntersectionsBuilder::same_topology(&defs, &intersection).unwrap());
+
+        let mut changed = self::defs();
+        changed.groups[0].components = None;
+        assert!(!IntersectionsBuilder::same_topology(&changed, &intersection).unwrap());
+    }
+
+    #[test]
+    fn test_negative_clearance() {
+        let (sender, _receiver) = unbounded();
+        let defs = defs();
+        let mut blocks = blocks(2);
+        blocks.groups[1].blocks[0].clearance = Some(-1);
+
+        let result = IntersectionsBuilder::new(sender)
+            .with_defs(&defs)
+            .with_blocks(&blocks)
+            .finish();
+
+        assert!(result.is_err());
     }
 }
@@ -5,7 +5,9 @@ use std::fmt::{Display, Formatter};
 use failure;
 use failure::Fail;
 
-use crate::intersections::component::ComponentState;
+use crate::intersections::component::{ComponentState, ComponentUid};
+use crate::intersections::conflict_monitor;
+use crate::intersections::group::ArcGroup;
 use colored::{Color, Colorize};
 
 #[derive(Debug, Fail)]
@@ -14,7 +16,7 @@ pub enum LightStateError {
     CouldNotConvert { value: i32 },
 }
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum LightState {
     Prohibit,
     Transitioning,
@@ -22,7 +24,11 @@ pub enum LightState {
     OutOfOrder,
 }
 
-impl ComponentState for LightState {}
+impl ComponentState for LightState {
+    fn guard(self, uid: ComponentUid, group: &ArcGroup) -> Result<(), failure::Error> {
+        conflict_monitor::guard_light(uid, group, self)
+    }
+}
 
 impl Default for LightState {
     fn default() -> Self {
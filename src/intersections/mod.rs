@@ -1,5 +1,6 @@
 pub mod actuator;
 pub mod component;
+pub mod conflict_monitor;
 pub mod deck;
 pub mod gate;
 pub mod group;
@@ -7,3 +8,5 @@ pub mod intersection;
 pub mod intersection_builder;
 pub mod light;
 pub mod sensor;
+pub mod strategies;
+pub mod timings;
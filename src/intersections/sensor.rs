@@ -7,6 +7,7 @@ use chrono::{DateTime, Utc};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use failure::Fail;
 
+use crate::clock::ArcClock;
 use crate::intersections::component::{Component, ComponentId, ComponentState, ComponentUid};
 use crate::intersections::group::ArcGroup;
 use colored::{Color, Colorize};
@@ -70,6 +71,7 @@ pub struct Sensor {
     group: ArcGroup,
 
     id: ComponentId,
+    clock: ArcClock,
 
     state: SensorState,
     initial_state: SensorState,
@@ -85,6 +87,7 @@ impl Sensor {
     pub fn new(
         group: ArcGroup,
         id: ComponentId,
+        clock: ArcClock,
         initial_state: SensorState,
         distance: i32,
     ) -> Self {
@@ -93,9 +96,10 @@ impl Sensor {
         Self {
             group,
             id,
+            timestamp: clock.now(),
+            clock,
             state: initial_state,
             initial_state,
-            timestamp: Utc::now(),
             sender,
             receiver,
             distance,
@@ -126,13 +130,17 @@ impl Component<SensorState> for Sensor {
 
     fn set_state_internal(&mut self, state: SensorState) {
         self.state = state;
-        self.timestamp = Utc::now();
+        self.timestamp = self.clock.now();
     }
 
     fn timestamp(&self) -> DateTime<Utc> {
         self.timestamp
     }
 
+    fn clock(&self) -> ArcClock {
+        Arc::clone(&self.clock)
+    }
+
     fn id(&self) -> ComponentId {
         self.id
     }
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use crate::error::{Error, RwLockExt};
+use crate::intersections::group::{ArcGroup, GroupId};
+use crate::intersections::intersection::Intersection;
+use crate::intersections::strategies::{fits, PhaseStrategy};
+
+/// Runs through a fixed cycle of phases regardless of demand. The phases are derived from the
+/// blocks, every phase takes the first group that hasn't had a phase yet and adds every other
+/// group that fits.
+pub struct FixedCycleStrategy {
+    phases: Vec<Vec<ArcGroup>>,
+    next: usize,
+}
+
+impl FixedCycleStrategy {
+    pub fn new() -> Self {
+        Self {
+            phases: vec![],
+            next: 0,
+        }
+    }
+
+    fn build_phases(intersection: &Intersection) -> Result<Vec<Vec<ArcGroup>>, Error> {
+        let mut lit: Vec<(GroupId, ArcGroup)> = vec![];
+
+        for group in intersection.groups() {
+            let id = {
+                let group = group.read_checked()?;
+
+                if group.lights.is_empty() {
+                    continue;
+                }
+
+                group.id
+            };
+
+            lit.push((id, group));
+        }
+
+        lit.sort_by_key(|(id, _)| (id.kind.to_string(), id.id));
+
+        let groups: Vec<ArcGroup> = lit.into_iter().map(|(_, group)| group).collect();
+
+        let mut phases: Vec<Vec<ArcGroup>> = vec![];
+        let mut served = vec![false; groups.len()];
+
+        while let Some(first) = served.iter().position(|s| !s) {
+            let mut phase = vec![Arc::clone(&groups[first])];
+            served[first] = true;
+
+            for (index, group) in groups.iter().enumerate() {
+                if fits(&phase, group)? {
+                    phase.push(Arc::clone(group));
+                    served[index] = true;
+                }
+            }
+
+            phases.push(phase);
+        }
+
+        Ok(phases)
+    }
+}
+
+impl PhaseStrategy for FixedCycleStrategy {
+    fn select(&mut self, intersection: &Intersection) -> Result<Vec<ArcGroup>, failure::Error> {
+        if self.phases.is_empty() {
+            self.phases = Self::build_phases(intersection)?;
+
+            if self.phases.is_empty() {
+                return Ok(vec![]);
+            }
+        }
+
+        let phase = &self.phases[self.next];
+        self.next = (self.next + 1) % self.phases.len();
+
+        let mut groups = vec![];
+
+        for group in phase {
+            if !group.read_checked()?.block {
+                groups.push(Arc::clone(group));
+            }
+        }
+
+        Ok(groups)
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use crate::error::{Error, RwLockExt};
+use crate::intersections::group::ArcGroup;
+use crate::intersections::intersection::Intersection;
+use crate::intersections::strategies::PhaseStrategy;
+
+/// Picks the highest scoring group and adds every concurrent group with a score that fits.
+pub struct GreedyStrategy;
+
+impl GreedyStrategy {
+    /// The group with the highest score, `None` when there are no groups at all.
+    fn highest_scoring_group(groups: &[ArcGroup]) -> Result<Option<ArcGroup>, Error> {
+        let mut score = -1;
+        let mut highest = None;
+
+        for group in groups {
+            let group_score = group.read_checked()?.score;
+
+            if group_score > score {
+                score = group_score;
+                highest = Some(Arc::clone(group));
+            }
+        }
+
+        Ok(highest)
+    }
+}
+
+impl PhaseStrategy for GreedyStrategy {
+    fn select(&mut self, intersection: &Intersection) -> Result<Vec<ArcGroup>, failure::Error> {
+        let mut groups: Vec<ArcGroup> = vec![];
+
+        let highest_scoring = match Self::highest_scoring_group(&intersection.unblocked_groups()?)?
+        {
+            Some(group) => group,
+            None => return Ok(groups),
+        };
+
+        let highest_scoring = highest_scoring.read_checked()?;
+
+        if highest_scoring.score == 0 {
+            return Ok(groups);
+        }
+
+        for group in &highest_scoring.concurrences {
+            let concurrent = group.read_checked()?;
+
+            if concurrent.score <= 0 || concurrent.block {
+                continue;
+            }
+
+            let mut can_fit = true;
+
+            for block in &concurrent.blocks {
+                let block_id = block.read_checked()?.id;
+
+                for existing_group in &groups {
+                    if existing_group.read_checked()?.id == block_id {
+                        can_fit = false;
+                    }
+                }
+            }
+
+            if !can_fit {
+                continue;
+            }
+
+            groups.push(Arc::clone(group));
+        }
+
+        Ok(groups)
+    }
+}
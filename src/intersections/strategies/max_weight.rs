@@ -0,0 +1,89 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+use crate::error::RwLockExt;
+use crate::intersections::group::ArcGroup;
+use crate::intersections::intersection::Intersection;
+use crate::intersections::strategies;
+use crate::intersections::strategies::PhaseStrategy;
+
+/// Picks the set of non-conflicting groups with the highest combined score.
+pub struct MaxWeightStrategy;
+
+struct Search<'a> {
+    candidates: &'a [(ArcGroup, i32)],
+    conflicts: Vec<Vec<bool>>,
+
+    best: Vec<usize>,
+    best_score: i32,
+}
+
+impl<'a> Search<'a> {
+    fn run(&mut self, index: usize, chosen: &mut Vec<usize>, score: i32, remaining: i32) {
+        if score > self.best_score {
+            self.best_score = score;
+            self.best = chosen.clone();
+        }
+
+        // Even taking every remaining candidate can't beat the best set found so far.
+        if index >= self.candidates.len() || score + remaining <= self.best_score {
+            return;
+        }
+
+        let candidate_score = self.candidates[index].1;
+        let remaining = remaining - candidate_score;
+
+        if chosen.iter().all(|&c| !self.conflicts[c][index]) {
+            chosen.push(index);
+            self.run(index + 1, chosen, score + candidate_score, remaining);
+            chosen.pop();
+        }
+
+        self.run(index + 1, chosen, score, remaining);
+    }
+}
+
+impl PhaseStrategy for MaxWeightStrategy {
+    fn select(&mut self, intersection: &Intersection) -> Result<Vec<ArcGroup>, failure::Error> {
+        let mut candidates: Vec<(ArcGroup, i32)> = vec![];
+
+        for g in intersection.unblocked_groups()? {
+            let score = g.read_checked()?.score;
+
+            if score > 0 {
+                candidates.push((g, score));
+            }
+        }
+
+        // Trying high scores first finds good sets early, which prunes the search the most.
+        candidates.sort_by_key(|(_, score)| Reverse(*score));
+
+        let mut conflicts = vec![];
+
+        for (a, _) in &candidates {
+            let mut row = vec![];
+
+            for (b, _) in &candidates {
+                row.push(strategies::conflicts(a, b)?);
+            }
+
+            conflicts.push(row);
+        }
+
+        let mut search = Search {
+            candidates: &candidates,
+            conflicts,
+            best: vec![],
+            best_score: 0,
+        };
+
+        let total = candidates.iter().map(|(_, score)| score).sum();
+        search.run(0, &mut vec![], 0, total);
+
+        Ok(search
+            .best
+            .iter()
+            .map(|&i| Arc::clone(&candidates[i].0))
+            .collect())
+    }
+}
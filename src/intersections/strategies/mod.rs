@@ -0,0 +1,239 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use failure::Fail;
+
+use crate::config::groups::Strategy as ConfigStrategy;
+use crate::error::{Error, RwLockExt};
+use crate::intersections::group::ArcGroup;
+use crate::intersections::intersection::Intersection;
+use crate::intersections::strategies::fixed_cycle::FixedCycleStrategy;
+use crate::intersections::strategies::greedy::GreedyStrategy;
+use crate::intersections::strategies::max_weight::MaxWeightStrategy;
+use crate::intersections::strategies::round_robin::RoundRobinStrategy;
+
+pub mod fixed_cycle;
+pub mod greedy;
+pub mod max_weight;
+pub mod round_robin;
+pub mod starvation;
+
+#[derive(Debug, Fail)]
+#[fail(display = "Unknown phase strategy: {}", name)]
+pub struct UnknownStrategy {
+    name: String,
+}
+
+/// Decides which groups get to proceed in the next phase.
+pub trait PhaseStrategy: Send {
+    /// Selects the groups for the next phase, an empty selection means nothing has to run.
+    fn select(&mut self, intersection: &Intersection) -> Result<Vec<ArcGroup>, failure::Error>;
+}
+
+/// Builds the configured strategy, defaulting to the greedy strategy.
+pub fn build(config: &Option<ConfigStrategy>) -> Result<Box<dyn PhaseStrategy>, failure::Error> {
+    let config = match config {
+        Some(config) => config,
+        None => return Ok(Box::new(GreedyStrategy)),
+    };
+
+    match &config.name[..] {
+        "greedy" => Ok(Box::new(GreedyStrategy)),
+        "max_weight" => Ok(Box::new(MaxWeightStrategy)),
+        "fixed_cycle" => Ok(Box::new(FixedCycleStrategy::new())),
+        "round_robin" => Ok(Box::new(RoundRobinStrategy::new(Duration::from_millis(
+            config.max_wait.unwrap_or(60_000) as u64,
+        )))),
+        _ => Err(UnknownStrategy {
+            name: config.name.clone(),
+        }
+        .into()),
+    }
+}
+
+/// Whether two groups block each other, in either direction.
+pub fn conflicts(a: &ArcGroup, b: &ArcGroup) -> Result<bool, Error> {
+    Ok(a.read_checked()?.blocks_group(Arc::clone(b))?
+        || b.read_checked()?.blocks_group(Arc::clone(a))?)
+}
+
+/// Whether a group can join a phase, i.e. it isn't part of it yet and conflicts with none of its
+/// groups.
+pub fn fits(phase: &[ArcGroup], group: &ArcGroup) -> Result<bool, Error> {
+    for p in phase {
+        if Arc::ptr_eq(p, group) || conflicts(p, group)? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::blocks::{Block, Blocks, Group as BlocksGroup};
+    use crate::config::definitions::{Component, Definitions, Group as DefinitionsGroup};
+    use crate::config::groups::{
+        General as ConfigGeneral, Group as ConfigGroup, Groups as ConfigGroups,
+    };
+    use crate::intersections::group::{GroupId, GroupKind};
+    use crate::intersections::intersection::ArcIntersection;
+    use crate::intersections::intersection_builder::IntersectionsBuilder;
+    use crate::intersections::strategies::starvation::StarvationProtection;
+    use crate::intersections::timings::Timings;
+    use chrono::Utc;
+    use crossbeam_channel::unbounded;
+    use std::sync::Arc;
+
+    /// Builds motor vehicle groups 1, 2 and 3 where group 1 blocks both others, and gives them
+    /// the given scores.
+    fn intersection(scores: [i32; 3]) -> ArcIntersection {
+        let group = |id| DefinitionsGroup {
+            kind: String::from("motor_vehicle"),
+            id,
+            components: Some(vec![Component {
+                kind: String::from("light"),
+                id: 1,
+                distance: None,
+                initial_state: None,
+            }]),
+        };
+        let block = |id| Block {
+            kind: String::from("motor_vehicle"),
+            id,
+            clearance: None,
+        };
+        let blocking = |id, blocks| BlocksGroup {
+            kind: String::from("motor_vehicle"),
+            id,
+            blocks,
+        };
+
+        let defs = Definitions {
+            groups: vec![group(1), group(2), group(3)],
+        };
+        let blocks = Blocks {
+            groups: vec![
+                blocking(1, vec![block(2), block(3)]),
+                blocking(2, vec![block(1)]),
+                blocking(3, vec![block(1)]),
+            ],
+        };
+
+        let (sender, _receiver) = unbounded();
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_defs(&defs)
+            .with_blocks(&blocks)
+            .finish()
+            .unwrap();
+
+        for (i, score) in scores.iter().enumerate() {
+            let id = GroupId {
+                kind: GroupKind::MotorVehicle,
+                id: i as i32 + 1,
+            };
+
+            let group = intersection.read().unwrap().find_group(id).unwrap();
+            group.write().unwrap().score = *score;
+        }
+
+        intersection
+    }
+
+    fn ids(groups: Vec<ArcGroup>) -> Vec<i32> {
+        let mut ids: Vec<i32> = groups.iter().map(|g| g.read().unwrap().id.id).collect();
+        ids.sort();
+        ids
+    }
+
+    fn select(name: &str, scores: [i32; 3]) -> Vec<i32> {
+        let intersection = intersection(scores);
+        let mut strategy = build(&Some(ConfigStrategy {
+            name: String::from(name),
+            max_wait: None,
+        }))
+        .unwrap();
+
+        let runnables = intersection
+            .read()
+            .unwrap()
+            .get_runnables(strategy.as_mut())
+            .unwrap();
+
+        ids(runnables)
+    }
+
+    #[test]
+    fn test_greedy_takes_highest_scoring_group() {
+        assert_eq!(select("greedy", [5, 3, 3]), vec![1]);
+        assert_eq!(select("greedy", [0, 0, 0]), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_max_weight_takes_best_combination() {
+        assert_eq!(select("max_weight", [5, 3, 3]), vec![2, 3]);
+        assert_eq!(select("max_weight", [7, 3, 3]), vec![1]);
+    }
+
+    #[test]
+    fn test_round_robin_serves_groups_with_demand() {
+        assert_eq!(select("round_robin", [1, 0, 4]), vec![1]);
+        assert_eq!(select("round_robin", [0, 2, 4]), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_fixed_cycle_ignores_demand() {
+        assert_eq!(select("fixed_cycle", [0, 0, 0]), vec![1]);
+    }
+
+    #[test]
+    fn test_starvation_protection_serves_overdue_group() {
+        let intersection = intersection([5, 1, 0]);
+        let config = ConfigGroups {
+            groups: vec![ConfigGroup {
+                kind: String::from("motor_vehicle"),
+                min_go_time: 0,
+                min_transition_time: 0,
+                max_go_time: None,
+                gap_time: None,
+                max_wait: Some(60_000),
+            }],
+            general: ConfigGeneral { min_evac_time: 0 },
+            strategy: None,
+            overrides: None,
+        };
+        let timings = Timings::new(&intersection.read().unwrap(), &config).unwrap();
+        let mut strategy = StarvationProtection::new(build(&None).unwrap(), Arc::new(timings));
+
+        let runnables = intersection
+            .read()
+            .unwrap()
+            .get_runnables(&mut strategy)
+            .unwrap();
+        assert_eq!(ids(runnables), vec![1]);
+
+        let starved = GroupId {
+            kind: GroupKind::MotorVehicle,
+            id: 2,
+        };
+        let group = intersection.read().unwrap().find_group(starved).unwrap();
+        group.write().unwrap().waiting_since = Some(Utc::now() - chrono::Duration::seconds(61));
+
+        let runnables = intersection
+            .read()
+            .unwrap()
+            .get_runnables(&mut strategy)
+            .unwrap();
+        assert_eq!(ids(runnables), vec![2]);
+    }
+
+    #[test]
+    fn test_unknown_strategy() {
+        assert!(build(&Some(ConfigStrategy {
+            name: String::from("random"),
+            max_wait: None,
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::error::{Error, RwLockExt};
+use crate::intersections::group::{ArcGroup, GroupId};
+use crate::intersections::intersection::Intersection;
+use crate::intersections::strategies::{fits, PhaseStrategy};
+
+/// Serves groups with demand in a fixed order, a group that has been waiting for longer than the
+/// maximum wait time goes first. Every other group with demand that fits is added to the phase.
+pub struct RoundRobinStrategy {
+    max_wait: Duration,
+
+    /// The last group that started a phase.
+    last: Option<GroupId>,
+    /// When the groups that are currently waiting started having demand.
+    waiting_since: HashMap<GroupId, DateTime<Utc>>,
+}
+
+impl RoundRobinStrategy {
+    pub fn new(max_wait: Duration) -> Self {
+        Self {
+            max_wait,
+            last: None,
+            waiting_since: HashMap::new(),
+        }
+    }
+
+    /// The unblocked groups with demand, ordered by id.
+    fn demand(intersection: &Intersection) -> Result<Vec<(GroupId, ArcGroup)>, Error> {
+        let mut demand = vec![];
+
+        for group in intersection.unblocked_groups()? {
+            let (id, score) = {
+                let group = group.read_checked()?;
+                (group.id, group.score)
+            };
+
+            if score > 0 {
+                demand.push((id, group));
+            }
+        }
+
+        demand.sort_by_key(|(id, _)| (id.kind.to_string(), id.id));
+
+        Ok(demand)
+    }
+
+    /// The group that starts the next phase, the longest waiting overdue group or otherwise the
+    /// first group with demand after the last one.
+    fn lead(
+        &self,
+        intersection: &Intersection,
+        demand: &[(GroupId, ArcGroup)],
+    ) -> Option<(GroupId, ArcGroup)> {
+        let overdue = demand
+            .iter()
+            .filter_map(|(id, g)| {
+                let since = self.waiting_since.get(id)?;
+                Some(((*id, g), intersection.clock.since(*since)))
+            })
+            .filter(|(_, waited)| *waited >= self.max_wait)
+            .max_by_key(|(_, waited)| *waited);
+
+        if let Some(((id, group), _)) = overdue {
+            return Some((id, Arc::clone(group)));
+        }
+
+        let next = match self.last {
+            Some(last) => demand
+                .iter()
+                .position(|(id, _)| (id.kind.to_string(), id.id) > (last.kind.to_string(), last.id))
+                .unwrap_or(0),
+            None => 0,
+        };
+
+        demand.get(next).map(|(id, g)| (*id, Arc::clone(g)))
+    }
+}
+
+impl PhaseStrategy for RoundRobinStrategy {
+    fn select(&mut self, intersection: &Intersection) -> Result<Vec<ArcGroup>, failure::Error> {
+        let demand = Self::demand(intersection)?;
+
+        let now = intersection.clock.now();
+        let ids: Vec<GroupId> = demand.iter().map(|(id, _)| *id).collect();
+
+        self.waiting_since.retain(|id, _| ids.contains(id));
+
+        for id in ids {
+            self.waiting_since.entry(id).or_insert(now);
+        }
+
+        let (lead_id, lead) = match self.lead(intersection, &demand) {
+            Some(lead) => lead,
+            None => return Ok(vec![]),
+        };
+
+        self.last = Some(lead_id);
+        self.waiting_since.remove(&lead_id);
+
+        let mut phase = vec![lead];
+
+        for (id, group) in &demand {
+            if fits(&phase, group)? {
+                phase.push(Arc::clone(group));
+                self.waiting_since.remove(id);
+            }
+        }
+
+        Ok(phase)
+    }
+}
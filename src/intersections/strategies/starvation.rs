@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::{Error, RwLockExt};
+use crate::intersections::group::ArcGroup;
+use crate::intersections::intersection::Intersection;
+use crate::intersections::strategies::{fits, PhaseStrategy};
+use crate::intersections::timings::Timings;
+
+/// Wraps another strategy and makes sure no group waits longer than its maximum wait time. Overdue
+/// groups are served first, the longest waiting one leading, and the selection of the wrapped
+/// strategy fills up the rest of the phase.
+pub struct StarvationProtection {
+    inner: Box<dyn PhaseStrategy>,
+    timings: Arc<Timings>,
+}
+
+impl StarvationProtection {
+    pub fn new(inner: Box<dyn PhaseStrategy>, timings: Arc<Timings>) -> Self {
+        Self { inner, timings }
+    }
+
+    fn overdue(&self, intersection: &Intersection) -> Result<Vec<ArcGroup>, Error> {
+        let mut overdue: Vec<(ArcGroup, Duration)> = vec![];
+
+        for g in intersection.unblocked_groups()? {
+            let (id, since) = {
+                let group = g.read_checked()?;
+                (group.id, group.waiting_since)
+            };
+
+            let max_wait = self.timings.get(id).and_then(|timing| timing.max_wait);
+            let waited = since.map(|since| intersection.clock.since(since));
+
+            if let (Some(max_wait), Some(waited)) = (max_wait, waited) {
+                if waited >= max_wait {
+                    overdue.push((g, waited));
+                }
+            }
+        }
+
+        overdue.sort_by_key(|(_, waited)| std::cmp::Reverse(*waited));
+        Ok(overdue.into_iter().map(|(g, _)| g).collect())
+    }
+}
+
+impl PhaseStrategy for StarvationProtection {
+    fn select(&mut self, intersection: &Intersection) -> Result<Vec<ArcGroup>, failure::Error> {
+        let selection = self.inner.select(intersection)?;
+        let overdue = self.overdue(intersection)?;
+
+        if overdue.is_empty() {
+            return Ok(selection);
+        }
+
+        let mut phase: Vec<ArcGroup> = vec![];
+
+        for group in overdue.iter().chain(selection.iter()) {
+            if fits(&phase, group)? {
+                phase.push(Arc::clone(group));
+            }
+        }
+
+        for group in &overdue {
+            if phase.iter().any(|p| Arc::ptr_eq(p, group)) {
+                warn!(
+                    "Group {} waited too long, serving it regardless of its score",
+                    group.read_checked()?.id
+                );
+            }
+        }
+
+        Ok(phase)
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use failure::Fail;
+
+use crate::config::groups::Groups as ConfigGroups;
+use crate::error::RwLockExt;
+use crate::intersections::group::{GroupId, GroupKind};
+use crate::intersections::intersection::Intersection;
+
+#[derive(Debug, Fail)]
+pub enum TimingError {
+    #[fail(display = "Group kind {} has timings configured more than once", kind)]
+    DuplicateKind { kind: GroupKind },
+
+    #[fail(display = "Group {} has timings overridden more than once", id)]
+    DuplicateOverride { id: GroupId },
+
+    #[fail(
+        display = "Timings are overridden for group {}, which doesn't exist",
+        id
+    )]
+    UnknownGroup { id: GroupId },
+
+    #[fail(
+        display = "Group {} has no timings, configure its kind or override the group",
+        id
+    )]
+    Missing { id: GroupId },
+
+    #[fail(display = "Group {} has a negative {}: {}", id, name, time)]
+    Negative {
+        id: GroupId,
+        name: &'static str,
+        time: i32,
+    },
+
+    #[fail(
+        display = "Group {} has a gap time of {}, it must be more than 0",
+        id, gap
+    )]
+    InvalidGap { id: GroupId, gap: i32 },
+}
+
+/// The timings of a single group.
+#[derive(Clone, Copy)]
+pub struct Timing {
+    pub min_go: Duration,
+    pub max_go: Duration,
+    pub gap: Duration,
+    pub min_transition: Duration,
+    pub max_wait: Option<Duration>,
+}
+
+/// Partially configured timings, as found in a kind or group section.
+#[derive(Clone, Copy, Default)]
+struct PartialTiming {
+    min_go: Option<i32>,
+    max_go: Option<i32>,
+    gap: Option<i32>,
+    min_transition: Option<i32>,
+    max_wait: Option<i32>,
+}
+
+impl PartialTiming {
+    /// Fills the unset fields from the given fallback.
+    fn or(self, fallback: PartialTiming) -> PartialTiming {
+        PartialTiming {
+            min_go: self.min_go.or(fallback.min_go),
+            max_go: self.max_go.or(fallback.max_go),
+            gap: self.gap.or(fallback.gap),
+            min_transition: self.min_transition.or(fallback.min_transition),
+            max_wait: self.max_wait.or(fallback.max_wait),
+        }
+    }
+
+    fn finish(self, id: GroupId) -> Result<Timing, TimingError> {
+        let millis = |name, time: i32| match u64::try_from(time) {
+            Ok(time) => Ok(Duration::from_millis(time)),
+            Err(_) => Err(TimingError::Negative { id, name, time }),
+        };
+        let optional = |name, time: Option<i32>| time.map(|time| millis(name, time)).transpose();
+
+        let (min_go, min_transition) = match (self.min_go, self.min_transition) {
+            (Some(min_go), Some(min_transition)) => (min_go, min_transition),
+            _ => return Err(TimingError::Missing { id }),
+        };
+
+        // Green is extended while traffic arrives within the gap, without one it never waits.
+        let gap = self.gap.unwrap_or(2_000);
+
+        if gap <= 0 {
+            return Err(TimingError::InvalidGap { id, gap });
+        }
+
+        let min_go = millis("min_go_time", min_go)?;
+
+        Ok(Timing {
+            min_go,
+            max_go: optional("max_go_time", self.max_go)?
+                .unwrap_or(min_go)
+                .max(min_go),
+            gap: millis("gap_time", gap)?,
+            min_transition: millis("min_transition_time", min_transition)?,
+            max_wait: optional("max_wait", self.max_wait)?,
+        })
+    }
+}
+
+/// The timings of every group of an intersection, resolved from the kind defaults and the group
+/// overrides. Building them fails when a group ends up without timings, so lookups can't miss.
+pub struct Timings {
+    timings: HashMap<GroupId, Timing>,
+}
+
+impl Timings {
+    pub fn new(intersection: &Intersection, config: &ConfigGroups) -> Result<Self, failure::Error> {
+        let mut kinds: HashMap<GroupKind, PartialTiming> = HashMap::new();
+
+        for group in &config.groups {
+            let kind = GroupKind::try_from(&group.kind[..])?;
+            let timing = PartialTiming {
+                min_go: Some(group.min_go_time),
+                max_go: group.max_go_time,
+                gap: group.gap_time,
+                min_transition: Some(group.min_transition_time),
+                max_wait: group.max_wait,
+            };
+
+            if kinds.insert(kind, timing).is_some() {
+                return Err(TimingError::DuplicateKind { kind }.into());
+            }
+        }
+
+        let mut overrides: HashMap<GroupId, PartialTiming> = HashMap::new();
+
+        for group in config.overrides.iter().flatten() {
+            let id = GroupId::try_from(&group.group[..])?;
+            let timing = PartialTiming {
+                min_go: group.min_go_time,
+                max_go: group.max_go_time,
+                gap: group.gap_time,
+                min_transition: group.min_transition_time,
+                max_wait: group.max_wait,
+            };
+
+            if intersection.find_group(id).is_none() {
+                return Err(TimingError::UnknownGroup { id }.into());
+            }
+
+            if overrides.insert(id, timing).is_some() {
+                return Err(TimingError::DuplicateOverride { id }.into());
+            }
+        }
+
+        let mut timings = HashMap::new();
+
+        for group in intersection.groups() {
+            let id = group.read_checked()?.id;
+
+            let kind = kinds.get(&id.kind).cloned().unwrap_or_default();
+            let timing = overrides.get(&id).cloned().unwrap_or_default().or(kind);
+
+            timings.insert(id, timing.finish(id)?);
+        }
+
+        Ok(Self { timings })
+    }
+
+    pub fn get(&self, id: GroupId) -> Option<&Timing> {
+        self.timings.get(&id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::definitions::{Component, Definitions, Group as DefinitionsGroup};
+    use crate::config::groups::{
+        General as ConfigGeneral, Group as ConfigGroup, Override as ConfigOverride,
+    };
+    use crate::intersections::intersection::ArcIntersection;
+    use crate::intersections::intersection_builder::IntersectionsBuilder;
+    use crossbeam_channel::unbounded;
+
+    fn intersection() -> ArcIntersection {
+        let group = |kind: &str, id| DefinitionsGroup {
+            kind: String::from(kind),
+            id,
+            components: Some(vec![Component {
+                kind: String::from("light"),
+                id: 1,
+                distance: None,
+                initial_state: None,
+            }]),
+        };
+
+        let (sender, _receiver) = unbounded();
+        IntersectionsBuilder::new(sender)
+            .with_defs(&Definitions {
+                groups: vec![group("motor_vehicle", 1), group("motor_vehicle", 5)],
+            })
+            .finish()
+            .unwrap()
+    }
+
+    fn config(overrides: Vec<ConfigOverride>) -> ConfigGroups {
+        ConfigGroups {
+            groups: vec![ConfigGroup {
+                kind: String::from("motor_vehicle"),
+                min_go_time: 7_000,
+                min_transition_time: 4_000,
+                max_go_time: Some(20_000),
+                gap_time: None,
+                max_wait: None,
+            }],
+            general: ConfigGeneral { min_evac_time: 0 },
+            strategy: None,
+            overrides: Some(overrides),
+        }
+    }
+
+    fn motor_vehicle(id: i32) -> GroupId {
+        GroupId {
+            kind: GroupKind::MotorVehicle,
+            id,
+        }
+    }
+
+    #[test]
+    fn test_override_falls_back_to_kind() {
+        let timings = Timings::new(
+            &intersection().read().unwrap(),
+            &config(vec![ConfigOverride {
+                group: String::from("motor_vehicle/5"),
+                min_go_time: Some(10_000),
+                min_transition_time: None,
+                max_go_time: None,
+                gap_time: None,
+                max_wait: None,
+            }]),
+        )
+        .unwrap();
+
+        let default = timings.get(motor_vehicle(1)).unwrap();
+        let overridden = timings.get(motor_vehicle(5)).unwrap();
+
+        assert_eq!(default.min_go, Duration::from_millis(7_000));
+        assert_eq!(overridden.min_go, Duration::from_millis(10_000));
+        assert_eq!(overridden.min_transition, default.min_transition);
+        assert_eq!(overridden.max_go, Duration::from_millis(20_000));
+    }
+
+    #[test]
+    fn test_invalid_timings() {
+        let intersection = intersection();
+        let intersection = intersection.read().unwrap();
+
+        let unknown = ConfigOverride {
+            group: String::from("motor_vehicle/9"),
+            min_go_time: None,
+            min_transition_time: None,
+            max_go_time: None,
+            gap_time: None,
+            max_wait: None,
+        };
+        assert!(Timings::new(&intersection, &config(vec![unknown])).is_err());
+
+        let mut missing = config(vec![]);
+        missing.groups[0].kind = String::from("cycle");
+        assert!(Timings::new(&intersection, &missing).is_err());
+
+        let mut no_gap = config(vec![]);
+        no_gap.groups[0].gap_time = Some(0);
+        assert!(Timings::new(&intersection, &no_gap).is_err());
+
+        let negative = ConfigOverride {
+            group: String::from("motor_vehicle/5"),
+            min_go_time: None,
+            min_transition_time: None,
+            max_go_time: Some(-1),
+            gap_time: None,
+            max_wait: None,
+        };
+        assert!(Timings::new(&intersection, &config(vec![negative])).is_err());
+    }
+}
@@ -1,8 +1,14 @@
+use std::convert::TryFrom;
+use std::thread;
+use std::time::Duration;
+
 use crossbeam_channel::Receiver;
 use failure::Fail;
-use rumqtt::{LastWill, MqttClient, MqttOptions, Notification, QoS};
+use rumqtt::{LastWill, MqttOptions, Notification, QoS};
 
 use crate::io::topics::Topic;
+use crate::io::transport::mqtt::MqttTransport;
+use crate::io::transport::Transport;
 
 #[derive(Debug, Fail)]
 pub enum ClientError {
@@ -12,42 +18,160 @@ pub enum ClientError {
     NotYetStarted { client_id: String },
 }
 
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid reconnect: {}", reconnect)]
+pub struct InvalidReconnect {
+    reconnect: String,
+}
+
+/// When the client reconnects to the broker.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reconnect {
+    Never,
+    AfterFirstSuccess,
+    Always,
+}
+
+impl TryFrom<&str> for Reconnect {
+    type Error = InvalidReconnect;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "never" => Ok(Reconnect::Never),
+            "after_first_success" => Ok(Reconnect::AfterFirstSuccess),
+            "always" => Ok(Reconnect::Always),
+            _ => Err(InvalidReconnect {
+                reconnect: String::from(value),
+            }),
+        }
+    }
+}
+
 pub struct Client {
     pub options: MqttOptions,
-    client: Option<MqttClient>,
+    transport: Box<dyn Transport>,
     receiver: Option<Receiver<Notification>>,
 
     /// Client preferred QoS, will be used in `subscribe()` and `publish()`.
     qos: QoS,
 
     team_id: i32,
+
+    reconnect: Reconnect,
+    reconnect_delay: Duration,
+
+    /// Topics subscribed to, restored after a reconnect.
+    subscriptions: Vec<String>,
 }
 
 impl Client {
-    pub fn new(client_id: String, host: String, port: u16, qos: QoS, team_id: i32) -> Self {
-        info!(target: "mqtt", "Creating new MQTT client with client ID \"{}\"", client_id);
+    pub fn new(options: MqttOptions, qos: QoS, team_id: i32) -> Self {
+        Self::with_transport(options, qos, team_id, Box::new(MqttTransport::new()))
+    }
+
+    /// Creates a client that talks to the broker over the given transport instead of MQTT.
+    pub fn with_transport(
+        options: MqttOptions,
+        qos: QoS,
+        team_id: i32,
+        transport: Box<dyn Transport>,
+    ) -> Self {
+        info!(
+            target: "mqtt",
+            "Creating new MQTT client with client ID \"{}\"",
+            options.client_id()
+        );
 
         Self {
-            options: MqttOptions::new(client_id, host, port),
-            client: None,
+            options,
+            transport,
             receiver: None,
             qos,
             team_id,
+            reconnect: Reconnect::AfterFirstSuccess,
+            reconnect_delay: Duration::from_secs(10),
+            subscriptions: vec![],
         }
     }
 
-    pub fn set_last_will(&mut self, mut topic: Box<dyn Topic>, payload: Vec<u8>) {
+    pub fn set_reconnect(&mut self, reconnect: Reconnect, delay: Duration) {
+        self.reconnect = reconnect;
+        self.reconnect_delay = delay;
+    }
+
+    pub fn qos(&self) -> QoS {
+        self.qos
+    }
+
+    pub fn team_id(&self) -> i32 {
+        self.team_id
+    }
+
+    #[cfg(test)]
+    pub fn reconnect_options(&self) -> (Reconnect, Duration) {
+        (self.reconnect, self.reconnect_delay)
+    }
+
+    /// The MQTT library only takes text as the last will, so other payloads are rejected.
+    pub fn set_last_will(
+        &mut self,
+        mut topic: Box<dyn Topic>,
+        payload: Vec<u8>,
+    ) -> Result<(), failure::Error> {
         topic.set_team_id(self.team_id);
 
         self.options = self.options.clone().set_last_will(LastWill {
             topic: format!("{}", topic),
-            message: String::from_utf8(payload).unwrap(),
+            message: String::from_utf8(payload)?,
             qos: self.qos,
             retain: false,
         });
+
+        Ok(())
     }
 
+    /// Connects to the broker, retrying until it succeeds when reconnecting always.
     pub fn start(&mut self) -> Result<(), failure::Error> {
+        loop {
+            match self.connect() {
+                Ok(()) => return Ok(()),
+                Err(e) if self.reconnect == Reconnect::Always => {
+                    warn!(
+                        target: "mqtt",
+                        "MQTT client \"{}\" could not connect: {}",
+                        self.options.client_id(),
+                        e
+                    );
+                    thread::sleep(self.reconnect_delay);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Connects again after the connection was lost, and restores the subscriptions. Blocks until
+    /// connected, returns false when the client doesn't reconnect at all.
+    pub fn reconnect(&mut self) -> bool {
+        if self.reconnect == Reconnect::Never {
+            return false;
+        }
+
+        loop {
+            thread::sleep(self.reconnect_delay);
+
+            match self.connect() {
+                Ok(()) => return true,
+                Err(e) => warn!(
+                    target: "mqtt",
+                    "MQTT client \"{}\" could not reconnect: {}",
+                    self.options.client_id(),
+                    e
+                ),
+            }
+        }
+    }
+
+    fn connect(&mut self) -> Result<(), failure::Error> {
         info!(
             target: "mqtt",
             "MQTT client \"{}\" connecting to \"{}:{}\"",
@@ -56,7 +180,7 @@ impl Client {
             self.options.broker_address().1,
         );
 
-        let (client, receiver) = MqttClient::start(self.options.clone())?;
+        let receiver = self.transport.connect(&self.options)?;
 
         info!(
             target: "mqtt",
@@ -66,7 +190,17 @@ impl Client {
             self.options.broker_address().1
         );
 
-        self.client = Some(client);
+        for topic in &self.subscriptions {
+            self.transport.subscribe(topic.clone(), self.qos)?;
+
+            info!(
+                target: "mqtt",
+                "MQTT client \"{}\" resubscribed to topic \"{}\"",
+                self.options.client_id(),
+                topic
+            );
+        }
+
         self.receiver = Some(receiver);
 
         Ok(())
@@ -75,8 +209,9 @@ impl Client {
     pub fn subscribe(&mut self, mut topic: Box<dyn Topic>) -> Result<(), failure::Error> {
         topic.set_team_id(self.team_id);
 
-        if let Some(client) = &mut self.client {
-            client.subscribe(format!("{}", topic), self.qos)?;
+        if self.receiver.is_some() {
+            self.transport.subscribe(format!("{}", topic), self.qos)?;
+            self.subscriptions.push(format!("{}", topic));
 
             info!(
                 target: "mqtt",
@@ -105,8 +240,9 @@ impl Client {
     {
         topic.set_team_id(self.team_id);
 
-        if let Some(client) = &mut self.client {
-            client.publish(format!("{}", topic), self.qos, false, payload)?;
+        if self.receiver.is_some() {
+            self.transport
+                .publish(format!("{}", topic), self.qos, payload.into())?;
 
             info!(
                 target: "mqtt",
@@ -135,3 +271,101 @@ impl Client {
         .into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use crossbeam_channel::{unbounded, Sender};
+
+    use crate::io::topics::command_topic::{Command, CommandTopic};
+
+    /// Reads one MQTT packet, returning its type and body.
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut byte = [0; 1];
+        stream.read_exact(&mut byte).ok()?;
+        let kind = byte[0] >> 4;
+
+        let (mut length, mut shift) = (0, 0);
+        loop {
+            stream.read_exact(&mut byte).ok()?;
+            length += usize::from(byte[0] & 0x7f) << shift;
+            shift += 7;
+
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).ok()?;
+
+        Some((kind, body))
+    }
+
+    /// Starts a broker stand-in that reports the topic of every subscribe, and drops the first
+    /// connection after its first subscribe.
+    fn broker(subscriptions: Sender<String>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            for (connection, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+
+                while let Some((kind, body)) = read_packet(&mut stream) {
+                    match kind {
+                        // CONNECT
+                        1 => stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap(),
+                        // SUBSCRIBE, the topic follows the packet id
+                        8 => {
+                            let length = usize::from(body[2]) << 8 | usize::from(body[3]);
+                            let topic = String::from_utf8_lossy(&body[4..4 + length]);
+                            subscriptions.send(topic.to_string()).unwrap();
+                            stream
+                                .write_all(&[0x90, 0x03, body[0], body[1], 0x01])
+                                .unwrap();
+
+                            if connection == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        });
+
+        port
+    }
+
+    #[test]
+    fn test_resubscribe_after_reconnect() {
+        let (sender, subscriptions) = unbounded();
+        let options = MqttOptions::new("reconnect-test", "127.0.0.1", broker(sender));
+
+        let mut client = Client::new(options, QoS::AtLeastOnce, 4);
+        client.set_reconnect(Reconnect::AfterFirstSuccess, Duration::from_millis(10));
+        client.start().unwrap();
+        client
+            .subscribe(Box::new(CommandTopic::new(Command::Reload)))
+            .unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            subscriptions.recv_timeout(timeout).unwrap(),
+            "4/features/command/reload"
+        );
+
+        // The notifications end once the connection is lost.
+        for _ in client.listen().unwrap() {}
+
+        assert!(client.reconnect());
+        assert_eq!(
+            subscriptions.recv_timeout(timeout).unwrap(),
+            "4/features/command/reload"
+        );
+    }
+}
@@ -1,20 +1,63 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::net::IpAddr;
+use std::time::Duration;
+
 use failure::Fail;
-use rumqtt::QoS;
+use rumqtt::{ConnectionMethod, MqttOptions, QoS, SecurityOptions};
 
-use crate::config::io::MqConnection;
+use crate::config::io::{MqConnection, Tls};
 use crate::config::protocols::{Protocol, Protocols};
-use crate::io::client::Client;
+use crate::io::client::{Client, Reconnect};
+
+/// The MQTT library drops notifications when this many are waiting, so it's sized for bursts of
+/// sensor updates and acknowledgements.
+const NOTIFICATION_CAPACITY: usize = 1_000;
 
 #[derive(Debug, Fail)]
 pub enum ClientBuildError {
     #[fail(display = "Invalid protocol: {}", protocol)]
     InvalidProtocol { protocol: String },
+
+    #[fail(
+        display = "Protocol {} uses TLS, but no tls section is configured",
+        protocol
+    )]
+    MissingTls { protocol: String },
+
+    #[fail(
+        display = "TLS needs a host name to verify the broker certificate against, got {}",
+        host
+    )]
+    IpHost { host: String },
+
+    #[fail(display = "TLS client certificate and key must be configured together")]
+    IncompleteClientAuth,
+
+    #[fail(display = "Could not read {}: {}", path, error)]
+    UnreadableFile { path: String, error: String },
+
+    #[fail(display = "{} does not contain a PEM encoded {}", path, label)]
+    InvalidPem { path: String, label: &'static str },
+
+    #[fail(
+        display = "Keep alive must be between 10_000 and 65_535_000 milliseconds, got {}",
+        keep_alive
+    )]
+    InvalidKeepAlive { keep_alive: i32 },
+
+    #[fail(display = "A password is configured without a username")]
+    MissingUsername,
+
+    #[fail(display = "Max in flight must be at least 1, got {}", max_in_flight)]
+    InvalidMaxInFlight { max_in_flight: i32 },
 }
 
 pub struct ClientBuilder<'a> {
     io_config: &'a MqConnection,
     protocols_config: &'a Protocols,
     team_id: i32,
+    instance_id: Option<&'a str>,
 }
 
 impl<'a> ClientBuilder<'a> {
@@ -23,20 +66,47 @@ impl<'a> ClientBuilder<'a> {
             io_config,
             protocols_config,
             team_id,
+            instance_id: None,
         }
     }
 
+    /// Appends the controller instance id to the client id, so instances sharing a broker don't
+    /// take over each other's connection.
+    pub fn with_instance_id(mut self, instance_id: &'a str) -> Self {
+        self.instance_id = Some(instance_id);
+        self
+    }
+
     pub fn finalize(&self) -> Result<Client, failure::Error> {
         let protocol = self.find_protocol()?;
 
-        let client = Client::new(
-            self.io_config.client_id.clone(),
-            self.io_config.host.clone(),
-            protocol.port as u16,
+        let client_id = match self.instance_id {
+            Some(instance_id) => format!("{}-{}", self.io_config.client_id, instance_id),
+            None => self.io_config.client_id.clone(),
+        };
+
+        let mut options =
+            MqttOptions::new(client_id, self.io_config.host.clone(), protocol.port as u16)
+                .set_notification_channel_capacity(NOTIFICATION_CAPACITY);
+
+        if protocol.tls.unwrap_or(false) {
+            options = options.set_connection_method(self.tls_connection(protocol)?);
+        }
+
+        let mut client = Client::new(
+            self.session_options(options)?,
             QoS::from_u8(self.io_config.qos as u8)?,
             self.team_id,
         );
 
+        client.set_reconnect(
+            match &self.io_config.reconnect {
+                Some(reconnect) => Reconnect::try_from(&reconnect[..])?,
+                None => Reconnect::AfterFirstSuccess,
+            },
+            Duration::from_millis(self.io_config.reconnect_delay.unwrap_or(10_000).max(0) as u64),
+        );
+
         Ok(client)
     }
 
@@ -56,4 +126,311 @@ impl<'a> ClientBuilder<'a> {
 
         Err(ClientBuildError::InvalidProtocol { protocol }.into())
     }
+
+    /// Applies keep alive, credentials and session settings. Values the MQTT library
+    /// would panic on are rejected here instead.
+    fn session_options(&self, mut options: MqttOptions) -> Result<MqttOptions, failure::Error> {
+        let config = self.io_config;
+
+        if let Some(keep_alive) = config.keep_alive {
+            if !(10_000..=65_535_000).contains(&keep_alive) {
+                return Err(ClientBuildError::InvalidKeepAlive { keep_alive }.into());
+            }
+
+            // Rounded up, so the broker never sees a shorter keep alive than configured.
+            options = options.set_keep_alive(seconds(keep_alive) as u16);
+        }
+
+        match (&config.username, &config.password) {
+            (Some(username), password) => {
+                options = options.set_security_opts(SecurityOptions::UsernamePassword(
+                    username.clone(),
+                    password.clone().unwrap_or_default(),
+                ));
+            }
+            (None, Some(_)) => return Err(ClientBuildError::MissingUsername.into()),
+            (None, None) => {}
+        }
+
+        if let Some(clean_session) = config.clean_session {
+            options = options.set_clean_session(clean_session);
+        }
+
+        if let Some(max_in_flight) = config.max_in_flight {
+            if max_in_flight < 1 {
+                return Err(ClientBuildError::InvalidMaxInFlight { max_in_flight }.into());
+            }
+
+            // Publishing waits while more messages than this are unacknowledged.
+            let (_, delay) = options.outgoing_queuelimit();
+            options = options.set_outgoing_queuelimit(max_in_flight as usize, delay);
+        }
+
+        Ok(options)
+    }
+
+    /// Reads the TLS certificates. Everything the MQTT library would panic on is checked up front,
+    /// so a bad configuration fails at startup with a proper error.
+    fn tls_connection(&self, protocol: &Protocol) -> Result<ConnectionMethod, failure::Error> {
+        let tls: &Tls = match &self.io_config.tls {
+            Some(tls) => tls,
+            None => {
+                return Err(ClientBuildError::MissingTls {
+                    protocol: protocol.name.clone(),
+                }
+                .into())
+            }
+        };
+
+        if self.io_config.host.parse::<IpAddr>().is_ok() {
+            return Err(ClientBuildError::IpHost {
+                host: self.io_config.host.clone(),
+            }
+            .into());
+        }
+
+        let ca = read_pem(&tls.ca_file, "CERTIFICATE")?;
+
+        let client_auth = match (&tls.client_cert_file, &tls.client_key_file) {
+            (Some(cert), Some(key)) => Some((
+                read_pem(cert, "CERTIFICATE")?,
+                read_pem(key, "RSA PRIVATE KEY")?,
+            )),
+            (None, None) => None,
+            _ => return Err(ClientBuildError::IncompleteClientAuth.into()),
+        };
+
+        Ok(ConnectionMethod::Tls(ca, client_auth))
+    }
+}
+
+/// Converts milliseconds to whole seconds, rounding up.
+fn seconds(millis: i32) -> u64 {
+    (millis as u64).div_ceil(1_000)
+}
+
+fn read_pem(path: &str, label: &'static str) -> Result<Vec<u8>, failure::Error> {
+    let pem = fs::read(path).map_err(|e| ClientBuildError::UnreadableFile {
+        path: String::from(path),
+        error: e.to_string(),
+    })?;
+
+    if !String::from_utf8_lossy(&pem).contains(&format!("-----BEGIN {}-----", label)) {
+        return Err(ClientBuildError::InvalidPem {
+            path: String::from(path),
+            label,
+        }
+        .into());
+    }
+
+    Ok(pem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::{BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use rustls::internal::pemfile;
+    use rustls::{AllowAnyAuthenticatedClient, RootCertStore, ServerConfig, ServerSession, Stream};
+
+    fn fixture(name: &str) -> String {
+        format!("fixtures/tls/{}", name)
+    }
+
+    fn reader(name: &str) -> BufReader<File> {
+        BufReader::new(File::open(fixture(name)).unwrap())
+    }
+
+    /// Starts a broker stand-in that presents the given certificate, only accepts clients with a
+    /// certificate signed by the test CA, and acknowledges their MQTT connect.
+    fn broker(cert: &str, key: &str) -> u16 {
+        let mut roots = RootCertStore::empty();
+        roots.add_pem_file(&mut reader("ca.pem")).unwrap();
+
+        let mut config = ServerConfig::new(AllowAnyAuthenticatedClient::new(roots));
+        let certs = pemfile::certs(&mut reader(cert)).unwrap();
+        let key = pemfile::rsa_private_keys(&mut reader(key)).unwrap();
+        config.set_single_cert(certs, key[0].clone()).unwrap();
+        let config = Arc::new(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            for tcp in listener.incoming() {
+                let mut tcp = tcp.unwrap();
+                let config = config.clone();
+
+                thread::spawn(move || {
+                    let mut session = ServerSession::new(&config);
+                    let mut tls = Stream::new(&mut session, &mut tcp);
+
+                    let mut buffer = [0; 1024];
+
+                    // CONNECT is answered with an accepting CONNACK, the rest is ignored until the
+                    // client goes away.
+                    if tls.read(&mut buffer).map(|n| n > 0).unwrap_or(false)
+                        && tls.write_all(&[0x20, 0x02, 0x00, 0x00]).is_ok()
+                    {
+                        while tls.read(&mut buffer).map(|n| n > 0).unwrap_or(false) {}
+                    }
+                });
+            }
+        });
+
+        port
+    }
+
+    fn io_config(host: &str, tls: Option<Tls>) -> MqConnection {
+        MqConnection {
+            client_id: String::from("tls-test"),
+            host: String::from(host),
+            protocol: String::from("ssl"),
+            qos: 1,
+            keep_alive: None,
+            username: None,
+            password: None,
+            clean_session: None,
+            reconnect: None,
+            reconnect_delay: None,
+            max_in_flight: None,
+            tls,
+        }
+    }
+
+    fn tls(ca: &str, cert: Option<&str>, key: Option<&str>) -> Option<Tls> {
+        Some(Tls {
+            ca_file: fixture(ca),
+            client_cert_file: cert.map(fixture),
+            client_key_file: key.map(fixture),
+        })
+    }
+
+    fn protocols(port: u16) -> Protocols {
+        Protocols {
+            protocols: vec![Protocol {
+                name: String::from("ssl"),
+                port: i32::from(port),
+                tls: Some(true),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_tls_connect() {
+        let protocols = protocols(broker("server.pem", "server.key"));
+
+        let trusted = io_config(
+            "localhost",
+            tls("ca.pem", Some("client.pem"), Some("client.key")),
+        );
+        let mut client = ClientBuilder::new(&trusted, &protocols, 4)
+            .finalize()
+            .unwrap();
+        assert!(client.start().is_ok());
+
+        let untrusted = io_config(
+            "localhost",
+            tls("other_ca.pem", Some("client.pem"), Some("client.key")),
+        );
+        let mut client = ClientBuilder::new(&untrusted, &protocols, 4)
+            .finalize()
+            .unwrap();
+        assert!(client.start().is_err());
+    }
+
+    #[test]
+    fn test_host_name_mismatch() {
+        // Signed by a trusted CA, but issued for broker.example instead of localhost.
+        let protocols = protocols(broker("other_host.pem", "other_host.key"));
+
+        let config = io_config(
+            "localhost",
+            tls("other_host_ca.pem", Some("client.pem"), Some("client.key")),
+        );
+        let mut client = ClientBuilder::new(&config, &protocols, 4)
+            .finalize()
+            .unwrap();
+        assert!(client.start().is_err());
+    }
+
+    #[test]
+    fn test_invalid_tls() {
+        let protocols = protocols(8883);
+        let build = |config: MqConnection| ClientBuilder::new(&config, &protocols, 4).finalize();
+
+        assert!(build(io_config("localhost", None)).is_err());
+        assert!(build(io_config("127.0.0.1", tls("ca.pem", None, None))).is_err());
+        assert!(build(io_config(
+            "localhost",
+            tls("ca.pem", Some("client.pem"), None)
+        ))
+        .is_err());
+        assert!(build(io_config(
+            "localhost",
+            tls("ca.pem", Some("client.pem"), Some("client.pem"))
+        ))
+        .is_err());
+        assert!(build(io_config("localhost", tls("ca.pem", None, None))).is_ok());
+    }
+
+    #[test]
+    fn test_session_options() {
+        let protocols = Protocols {
+            protocols: vec![Protocol {
+                name: String::from("tcp"),
+                port: 1883,
+                tls: None,
+            }],
+        };
+
+        let mut config = io_config("localhost", None);
+        config.protocol = String::from("tcp");
+        config.keep_alive = Some(30_000);
+        config.username = Some(String::from("controller"));
+        config.password = Some(String::from("secret"));
+        config.clean_session = Some(false);
+        config.reconnect = Some(String::from("always"));
+        config.reconnect_delay = Some(2_500);
+        config.max_in_flight = Some(20);
+
+        let client = ClientBuilder::new(&config, &protocols, 4)
+            .with_instance_id("0001-42")
+            .finalize()
+            .unwrap();
+        let options = &client.options;
+
+        assert_eq!(options.client_id(), "tls-test-0001-42");
+        assert_eq!(options.keep_alive(), Duration::from_secs(30));
+        assert!(!options.clean_session());
+        assert_eq!(
+            client.reconnect_options(),
+            (Reconnect::Always, Duration::from_millis(2_500))
+        );
+        assert_eq!(options.outgoing_queuelimit().0, 20);
+        match options.security_opts() {
+            SecurityOptions::UsernamePassword(username, password) => {
+                assert_eq!(username, "controller");
+                assert_eq!(password, "secret");
+            }
+            _ => panic!("credentials not set"),
+        }
+
+        config.keep_alive = Some(5_000);
+        assert!(ClientBuilder::new(&config, &protocols, 4)
+            .finalize()
+            .is_err());
+
+        config.keep_alive = None;
+        config.reconnect = Some(String::from("sometimes"));
+        assert!(ClientBuilder::new(&config, &protocols, 4)
+            .finalize()
+            .is_err());
+    }
 }
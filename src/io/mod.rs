@@ -1,3 +1,5 @@
 pub mod client;
 pub mod client_builder;
+pub mod recording;
 pub mod topics;
+pub mod transport;
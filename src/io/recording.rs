@@ -0,0 +1,128 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::clock::ArcClock;
+use crate::error::MutexExt;
+
+pub type ArcRecorder = Arc<Recorder>;
+
+#[derive(Debug, Fail)]
+#[fail(display = "Line {} of the recording is invalid: {}", line, reason)]
+pub struct InvalidRecording {
+    line: usize,
+    reason: String,
+}
+
+/// Whether the controller received or published a message.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// A message as it passed the broker, one per line of a recording.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// Milliseconds since the Unix epoch.
+    pub at: i64,
+    pub direction: Direction,
+    pub topic: String,
+    pub payload: String,
+}
+
+/// Writes every message the controller receives and publishes to a file, so a session can be
+/// replayed later. The file holds one JSON object per line.
+pub struct Recorder {
+    clock: ArcClock,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    /// Starts a recording, overwriting the file if it exists.
+    pub fn create(path: &str, clock: ArcClock) -> Result<Self, failure::Error> {
+        Ok(Self {
+            clock,
+            writer: Mutex::new(BufWriter::new(File::create(path)?)),
+        })
+    }
+
+    pub fn record(
+        &self,
+        direction: Direction,
+        topic: &str,
+        payload: &[u8],
+    ) -> Result<(), failure::Error> {
+        let message = RecordedMessage {
+            at: self.clock.now().timestamp_millis(),
+            direction,
+            topic: String::from(topic),
+            payload: String::from_utf8_lossy(payload).into_owned(),
+        };
+
+        let mut writer = self.writer.lock_checked()?;
+        serde_json::to_writer(&mut *writer, &message)?;
+        writeln!(writer)?;
+        // A crashing controller is exactly what the recording is for, so nothing stays buffered.
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Reads a recording back in the order it was written.
+pub fn read(path: &str) -> Result<Vec<RecordedMessage>, failure::Error> {
+    let mut messages = vec![];
+
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message = serde_json::from_str(&line).map_err(|e| InvalidRecording {
+            line: index + 1,
+            reason: e.to_string(),
+        })?;
+
+        messages.push(message);
+    }
+
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::clock::manual::ManualClock;
+
+    #[test]
+    fn test_read_back_recording() {
+        let path = std::env::temp_dir().join(format!("recording-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let clock = Arc::new(ManualClock::new());
+        let recorder = Recorder::create(path, Arc::clone(&clock) as ArcClock).unwrap();
+
+        recorder
+            .record(Direction::Inbound, "4/cycle/1/sensor/1", b"1")
+            .unwrap();
+        clock.advance(std::time::Duration::from_millis(1_500));
+        recorder
+            .record(Direction::Outbound, "4/cycle/1/light/1", b"2")
+            .unwrap();
+
+        let messages = read(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].direction, Direction::Inbound);
+        assert_eq!(messages[0].topic, "4/cycle/1/sensor/1");
+        assert_eq!(messages[1].payload, "2");
+        assert_eq!(messages[1].at - messages[0].at, 1_500);
+    }
+}
@@ -0,0 +1,130 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use failure;
+use regex::Regex;
+
+use crate::io::topics::{NoTeamIdSet, Topic};
+
+#[derive(Debug, Fail)]
+enum CommandTopicBuildError {
+    #[fail(display = "Command topic could not be built: Invalid format.")]
+    InvalidFormat,
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Unknown command: {}.", command)]
+struct UnknownCommand {
+    command: String,
+}
+
+#[derive(PartialEq)]
+pub enum Command {
+    OutOfOrder,
+    Resume,
+    Reload,
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Command::OutOfOrder => write!(f, "out_of_order"),
+            Command::Resume => write!(f, "resume"),
+            Command::Reload => write!(f, "reload"),
+        }
+    }
+}
+
+impl TryFrom<&str> for Command {
+    type Error = failure::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "out_of_order" => Ok(Command::OutOfOrder),
+            "resume" => Ok(Command::Resume),
+            "reload" => Ok(Command::Reload),
+            _ => Err(UnknownCommand {
+                command: String::from(value),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Operator commands sent to the controller.
+pub struct CommandTopic {
+    pub team_id: Option<i32>,
+    pub command: Command,
+}
+
+impl CommandTopic {
+    pub fn new(command: Command) -> Self {
+        Self {
+            team_id: None,
+            command,
+        }
+    }
+}
+
+impl Topic for CommandTopic {
+    fn team_id(&self) -> Result<i32, failure::Error> {
+        match self.team_id {
+            Some(team_id) => Ok(team_id),
+            None => Err(NoTeamIdSet.into()),
+        }
+    }
+
+    fn set_team_id(&mut self, team_id: i32) {
+        self.team_id = Some(team_id)
+    }
+}
+
+impl TryFrom<&str> for CommandTopic {
+    type Error = failure::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let regex = Regex::new("^(\\d+)/features/command/(\\w+)$")?;
+
+        let captures = match regex.captures(value) {
+            Some(captures) => captures,
+            None => return Err(CommandTopicBuildError::InvalidFormat.into()),
+        };
+
+        Ok(Self {
+            team_id: Some(captures[1].parse::<i32>()?),
+            command: Command::try_from(&captures[2])?,
+        })
+    }
+}
+
+impl Display for CommandTopic {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let team_id = match self.team_id {
+            Some(team_id) => format!("{}", team_id),
+            None => String::from("None"),
+        };
+
+        write!(f, "{}/features/command/{}", team_id, self.command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_topic() {
+        let topic = CommandTopic::try_from("4/features/command/out_of_order").unwrap();
+
+        assert_eq!(topic.team_id, Some(4));
+        assert!(topic.command == Command::OutOfOrder);
+        assert_eq!(format!("{}", topic), "4/features/command/out_of_order");
+    }
+
+    #[test]
+    fn test_invalid_topic() {
+        assert!(CommandTopic::try_from("4/features/command/explode").is_err());
+        assert!(CommandTopic::try_from("4/features/lifecycle/controller/onconnect").is_err());
+    }
+}
@@ -119,31 +119,57 @@ impl TryFrom<&str> for LifeCycleTopic {
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let regex = Regex::new(
-            "^\\d+/features/lifecycle/(controller|simulator)/(onconnect|ondisconnect)$",
+            "^(\\d+)/features/lifecycle/(controller|simulator)/(onconnect|ondisconnect)$",
         )?;
 
-        if !regex.is_match(value) {
-            return Err(LifeCycleTopicBuildError::InvalidFormat.into());
-        }
-
-        let captures = regex.captures(value).unwrap();
+        let captures = match regex.captures(value) {
+            Some(captures) => captures,
+            None => return Err(LifeCycleTopicBuildError::InvalidFormat.into()),
+        };
 
         Ok(Self {
-            team_id: Some(4),
-            device: Device::try_from(&captures[1])?,
-            handler: Handler::try_from(&captures[2])?,
+            team_id: Some(captures[1].parse::<i32>()?),
+            device: Device::try_from(&captures[2])?,
+            handler: Handler::try_from(&captures[3])?,
         })
     }
 }
 
 impl Display for LifeCycleTopic {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let team_id = match self.team_id {
+            Some(team_id) => format!("{}", team_id),
+            None => String::from("None"),
+        };
+
         write!(
             f,
             "{}/features/lifecycle/{}/{}",
-            self.team_id.unwrap(),
-            self.device,
-            self.handler
+            team_id, self.device, self.handler
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_topic() {
+        let topic = LifeCycleTopic::try_from("12/features/lifecycle/simulator/onconnect").unwrap();
+
+        assert_eq!(topic.team_id, Some(12));
+        assert!(topic.device == Device::Simulator);
+        assert!(topic.handler == Handler::Connect);
+        assert_eq!(
+            format!("{}", topic),
+            "12/features/lifecycle/simulator/onconnect"
+        );
+    }
+
+    #[test]
+    fn test_invalid_topic() {
+        assert!(LifeCycleTopic::try_from("4/features/lifecycle/bridge/onconnect").is_err());
+        assert!(LifeCycleTopic::try_from("features/lifecycle/simulator/onconnect").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use failure::Fail;
 use std::fmt::Display;
 
+pub mod command_topic;
 pub mod component_topic;
 pub mod lifecycle_topic;
 
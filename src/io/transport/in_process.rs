@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rumqtt::{LastWill, MqttOptions, Notification, PacketIdentifier, Publish, QoS};
+
+use crate::error::{Error, MutexExt};
+use crate::io::transport::{NotConnected, Transport};
+
+struct Connection {
+    notifications: Sender<Notification>,
+    subscriptions: Vec<String>,
+    last_will: Option<LastWill>,
+}
+
+struct BrokerState {
+    connections: HashMap<usize, Connection>,
+    next_connection: usize,
+    next_pkid: u16,
+    /// Receive a copy of every message that passes the broker.
+    taps: Vec<Sender<(String, Vec<u8>)>>,
+}
+
+/// A broker inside the process, so the controller can run without a real one. Messages are routed
+/// to the matching subscriptions of all its transports, and anyone can inject messages as if the
+/// simulator published them or tap every message passing through.
+#[derive(Clone)]
+pub struct InProcessBroker {
+    state: Arc<Mutex<BrokerState>>,
+}
+
+impl InProcessBroker {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BrokerState {
+                connections: HashMap::new(),
+                next_connection: 0,
+                next_pkid: 1,
+                taps: vec![],
+            })),
+        }
+    }
+
+    /// Creates a transport that connects to this broker.
+    pub fn transport(&self) -> InProcessTransport {
+        InProcessTransport {
+            broker: self.clone(),
+            connection: None,
+        }
+    }
+
+    /// Publishes a message to every matching subscription.
+    pub fn inject(&self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        self.route(String::from(topic), payload.to_vec())
+    }
+
+    /// Receives every message that passes the broker from now on, as `(topic, payload)`.
+    pub fn tap(&self) -> Result<Receiver<(String, Vec<u8>)>, Error> {
+        let (sender, receiver) = unbounded();
+        self.state.lock_checked()?.taps.push(sender);
+
+        Ok(receiver)
+    }
+
+    /// Whether every transport took all the messages routed to it so far.
+    pub fn delivered(&self) -> Result<bool, Error> {
+        let state = self.state.lock_checked()?;
+
+        Ok(state
+            .connections
+            .values()
+            .all(|connection| connection.notifications.is_empty()))
+    }
+
+    fn disconnect(&self, connection: usize) -> Result<(), Error> {
+        let removed = self.state.lock_checked()?.connections.remove(&connection);
+
+        if let Some(LastWill { topic, message, .. }) = removed.and_then(|c| c.last_will) {
+            self.route(topic, message.into_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn route(&self, topic: String, payload: Vec<u8>) -> Result<(), Error> {
+        let mut state = self.state.lock_checked()?;
+        let payload = Arc::new(payload);
+
+        state
+            .taps
+            .retain(|tap| tap.send((topic.clone(), payload.to_vec())).is_ok());
+
+        for connection in state.connections.values() {
+            if connection.subscriptions.iter().any(|f| matches(f, &topic)) {
+                let message = Publish {
+                    dup: false,
+                    qos: QoS::AtMostOnce,
+                    retain: false,
+                    topic_name: topic.clone(),
+                    pkid: None,
+                    payload: Arc::clone(&payload),
+                };
+
+                // A connection whose client is gone is cleaned up when its transport drops.
+                connection
+                    .notifications
+                    .send(Notification::Publish(message))
+                    .unwrap_or_default();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a topic matches a subscription filter, with the MQTT wildcards `+` and `#`.
+fn matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(topic_level)) if level == topic_level => {}
+            _ => return false,
+        }
+    }
+
+    topic_levels.next().is_none()
+}
+
+/// A connection to an `InProcessBroker`, acknowledging publishes the way a broker does.
+pub struct InProcessTransport {
+    broker: InProcessBroker,
+    connection: Option<usize>,
+}
+
+impl InProcessTransport {
+    fn with_connection<F>(&self, f: F) -> Result<(), failure::Error>
+    where
+        F: FnOnce(&mut Connection, &mut u16),
+    {
+        let mut state = self.broker.state.lock_checked()?;
+        let BrokerState {
+            connections,
+            next_pkid,
+            ..
+        } = &mut *state;
+
+        match self.connection.and_then(|id| connections.get_mut(&id)) {
+            Some(connection) => {
+                f(connection, next_pkid);
+                Ok(())
+            }
+            None => Err(NotConnected.into()),
+        }
+    }
+}
+
+impl Transport for InProcessTransport {
+    fn connect(&mut self, options: &MqttOptions) -> Result<Receiver<Notification>, failure::Error> {
+        if let Some(connection) = self.connection.take() {
+            self.broker.disconnect(connection)?;
+        }
+
+        let (notifications, receiver) = unbounded();
+        let mut state = self.broker.state.lock_checked()?;
+
+        let id = state.next_connection;
+        state.next_connection += 1;
+        state.connections.insert(
+            id,
+            Connection {
+                notifications,
+                subscriptions: vec![],
+                last_will: options.last_will(),
+            },
+        );
+
+        self.connection = Some(id);
+
+        Ok(receiver)
+    }
+
+    fn subscribe(&mut self, topic: String, _qos: QoS) -> Result<(), failure::Error> {
+        self.with_connection(|connection, _| connection.subscriptions.push(topic))
+    }
+
+    fn publish(&mut self, topic: String, qos: QoS, payload: Vec<u8>) -> Result<(), failure::Error> {
+        self.with_connection(|connection, next_pkid| {
+            let pkid = PacketIdentifier(*next_pkid);
+            *next_pkid = next_pkid.wrapping_add(1).max(1);
+
+            let acknowledgement = match qos {
+                QoS::AtMostOnce => None,
+                QoS::AtLeastOnce => Some(Notification::PubAck(pkid)),
+                QoS::ExactlyOnce => Some(Notification::PubComp(pkid)),
+            };
+
+            if let Some(acknowledgement) = acknowledgement {
+                connection
+                    .notifications
+                    .send(acknowledgement)
+                    .unwrap_or_default();
+            }
+        })?;
+
+        Ok(self.broker.route(topic, payload)?)
+    }
+}
+
+impl Drop for InProcessTransport {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.broker
+                .disconnect(connection)
+                .unwrap_or_else(|e| error!("{}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(
+            "4/motor_vehicle/1/sensor/1",
+            "4/motor_vehicle/1/sensor/1"
+        ));
+        assert!(matches("4/+/1/sensor/+", "4/cycle/1/sensor/2"));
+        assert!(matches("4/#", "4/features/lifecycle/simulator/onconnect"));
+        assert!(!matches("4/+/1", "4/cycle/1/sensor/2"));
+        assert!(!matches("4/cycle/1/sensor/1", "4/cycle/1/sensor"));
+    }
+}
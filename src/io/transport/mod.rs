@@ -0,0 +1,19 @@
+use crossbeam_channel::Receiver;
+use rumqtt::{MqttOptions, Notification, QoS};
+
+pub mod in_process;
+pub mod mqtt;
+
+#[derive(Debug, Fail)]
+#[fail(display = "The transport is not connected")]
+pub struct NotConnected;
+
+/// Carries the messages of a `Client` to and from a broker.
+pub trait Transport: Send {
+    /// Opens a new connection. The returned notifications end when the connection is lost.
+    fn connect(&mut self, options: &MqttOptions) -> Result<Receiver<Notification>, failure::Error>;
+
+    fn subscribe(&mut self, topic: String, qos: QoS) -> Result<(), failure::Error>;
+
+    fn publish(&mut self, topic: String, qos: QoS, payload: Vec<u8>) -> Result<(), failure::Error>;
+}
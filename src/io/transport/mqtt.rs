@@ -0,0 +1,42 @@
+use crossbeam_channel::Receiver;
+use rumqtt::{MqttClient, MqttOptions, Notification, QoS, ReconnectOptions};
+
+use crate::io::transport::{NotConnected, Transport};
+
+/// Talks to a real broker over the network.
+pub struct MqttTransport {
+    client: Option<MqttClient>,
+}
+
+impl MqttTransport {
+    pub fn new() -> Self {
+        Self { client: None }
+    }
+}
+
+impl Transport for MqttTransport {
+    fn connect(&mut self, options: &MqttOptions) -> Result<Receiver<Notification>, failure::Error> {
+        // Reconnecting is done by the client rather than by the library, which gives no notice of
+        // it and would leave the subscriptions lost.
+        let options = options.clone().set_reconnect_opts(ReconnectOptions::Never);
+        let (client, receiver) = MqttClient::start(options)?;
+
+        self.client = Some(client);
+
+        Ok(receiver)
+    }
+
+    fn subscribe(&mut self, topic: String, qos: QoS) -> Result<(), failure::Error> {
+        match &mut self.client {
+            Some(client) => Ok(client.subscribe(topic, qos)?),
+            None => Err(NotConnected.into()),
+        }
+    }
+
+    fn publish(&mut self, topic: String, qos: QoS, payload: Vec<u8>) -> Result<(), failure::Error> {
+        match &mut self.client {
+            Some(client) => Ok(client.publish(topic, qos, false, payload)?),
+            None => Err(NotConnected.into()),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 extern crate chrono;
+extern crate clap;
 extern crate config as conf;
 #[macro_use]
 extern crate crossbeam_channel;
@@ -14,72 +15,202 @@ extern crate serde;
 extern crate serde_derive;
 extern crate time;
 
+use std::sync::Arc;
+
 use chrono::Local;
 use crossbeam_channel::unbounded;
 
+use crate::cli::Options;
+use crate::clock::{ArcClock, SystemClock};
+use crate::config::validator;
 use crate::config::Config;
 use crate::core::controller::Controller;
+use crate::intersections::component::ComponentId;
+use crate::intersections::group::GroupId;
+use crate::intersections::intersection::ArcIntersection;
 use crate::intersections::intersection_builder::IntersectionsBuilder;
 use crate::io::client_builder::ClientBuilder;
-use crate::io::topics::lifecycle_topic::{Device, Handler, LifeCycleTopic};
+use crate::io::recording::{self, Recorder};
+use crate::simulation::replay::Replayer;
+use crate::simulation::Simulator;
 use colored::Color;
 use fern::colors::ColoredLevelConfig;
 use log::LevelFilter;
 
+mod cli;
+mod clock;
 mod config;
 mod core;
+mod error;
 mod intersections;
 mod io;
+mod metrics;
+mod simulation;
 
 fn main() -> Result<(), failure::Error> {
+    let options = Options::from_args()?;
+
     // Set up logging.
-    set_up_logger()?;
+    set_up_logger(&options)?;
 
+    // Panics end up in the log, runner panics are also handled by the controller.
     std::panic::set_hook(Box::new(|info| {
-        println!("{}", info);
+        error!("{}", info);
     }));
 
-    let config = Config::new("config").expect("Could not read config");
+    let mut config = Config::new(&options.config_dir)
+        .map_err(|e| format_err!("Could not read the config in {}: {}", options.config_dir, e))?;
+
+    if let Some(team_id) = options.team_id {
+        config.general.team_id = team_id;
+    }
+
+    let problems = validator::validate(&config);
+
+    for problem in &problems {
+        error!("{}", problem);
+    }
+
+    if options.check_config {
+        if problems.is_empty() {
+            info!("Configuration is valid");
+            return Ok(());
+        }
+
+        std::process::exit(1);
+    }
+
+    if !problems.is_empty() {
+        return Err(format_err!(
+            "Found {} problem(s) in the configuration",
+            problems.len()
+        ));
+    }
+
+    if options.simulate {
+        let mut simulator = Simulator::new(config, &options.config_dir, options.mode);
+
+        if let Some(record_file) = &options.record_file {
+            simulator = simulator.record_to(record_file);
+        }
+
+        let report = simulator.run()?;
+        println!("{}", report);
+
+        return Ok(());
+    }
+
+    if let Some(replay_file) = &options.replay_file {
+        let recording = recording::read(replay_file)?;
+        let report = Replayer::new(config, &options.config_dir, options.mode, options.speed)
+            .run(&recording)?;
+        println!("{}", report);
+
+        return Ok(());
+    }
 
     let (notification_sender, notification_receiver) = unbounded();
 
+    let clock: ArcClock = Arc::new(SystemClock);
+
     let traffic_lights = IntersectionsBuilder::new(notification_sender.clone())
+        .with_clock(Arc::clone(&clock))
         .with_defs(&config.traffic_lights)
         .with_blocks(&config.traffic_lights_blocks)
         .finish()?;
 
     let bridge = IntersectionsBuilder::new(notification_sender.clone())
+        .with_clock(Arc::clone(&clock))
         .with_defs(&config.bridge)
         .finish()?;
 
-    let mut publisher = ClientBuilder::new(
-        &config.io.publisher,
-        &config.protocols,
-        config.general.team_id,
-    )
-    .finalize()
-    .unwrap();
-
-    publisher.set_last_will(
-        Box::new(LifeCycleTopic::new(Device::Controller, Handler::Disconnect)),
-        vec![],
-    );
-
-    let subscriber = ClientBuilder::new(
-        &config.io.subscriber,
-        &config.protocols,
-        config.general.team_id,
-    )
-    .finalize()
-    .unwrap();
-
-    let mut controller = Controller::new(traffic_lights, bridge, notification_receiver, config);
+    if options.dry_run {
+        if options.mode.runs_traffic_lights() {
+            print_topology("Traffic lights", &traffic_lights);
+        }
+
+        if options.mode.runs_bridge() {
+            print_topology("Bridge", &bridge);
+        }
+
+        return Ok(());
+    }
+
+    let (publisher_config, subscriber_config) =
+        (config.io.publisher.clone(), config.io.subscriber.clone());
+    let protocols = config.protocols.clone();
+    let team_id = config.general.team_id;
+
+    let mut controller = Controller::new(
+        traffic_lights,
+        bridge,
+        notification_receiver,
+        config,
+        &options.config_dir,
+        options.mode,
+    )?;
+
+    let publisher = ClientBuilder::new(&publisher_config, &protocols, team_id)
+        .with_instance_id(controller.instance_id())
+        .finalize()?;
+
+    let subscriber = ClientBuilder::new(&subscriber_config, &protocols, team_id)
+        .with_instance_id(controller.instance_id())
+        .finalize()?;
+
+    if let Some(record_file) = &options.record_file {
+        controller.set_recorder(Arc::new(Recorder::create(record_file, clock)?));
+    }
+
     controller.start(publisher, subscriber)?;
 
     Ok(())
 }
 
-fn set_up_logger() -> Result<(), failure::Error> {
+/// Prints the groups of an intersection with their components and the groups they block.
+fn print_topology(name: &str, intersection: &ArcIntersection) {
+    println!("{}", name);
+
+    let mut groups = intersection.read().unwrap().groups();
+    groups.sort_by_key(|g| {
+        let id = g.read().unwrap().id;
+        (id.kind.to_string(), id.id)
+    });
+
+    for group in groups {
+        let group = group.read().unwrap();
+
+        let mut components: Vec<ComponentId> = group
+            .lights
+            .keys()
+            .chain(group.sensors.keys())
+            .chain(group.gates.keys())
+            .chain(group.decks.keys())
+            .cloned()
+            .collect();
+        components.sort_by_key(|id| (id.kind.to_string(), id.id));
+
+        let mut blocks: Vec<GroupId> = group.blocks.iter().map(|b| b.read().unwrap().id).collect();
+        blocks.sort_by_key(|id| (id.kind.to_string(), id.id));
+
+        println!("  {}", group.id);
+        println!("    components: {}", join(&components));
+
+        if !blocks.is_empty() {
+            println!("    blocks: {}", join(&blocks));
+        }
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn set_up_logger(options: &Options) -> Result<(), failure::Error> {
     let colors = ColoredLevelConfig::new()
         .error(Color::Red)
         .warn(Color::Yellow)
@@ -98,21 +229,39 @@ fn set_up_logger() -> Result<(), failure::Error> {
             ))
         })
         .level(LevelFilter::Off)
-        .level_for("mqtt", LevelFilter::Trace)
-        .chain(fern::log_file("log/mqtt.log")?);
+        .level_for("mqtt", options.mqtt_log_level)
+        .chain(fern::log_file(&options.mqtt_log_file)?);
 
-    let log = fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "[{}] [{}] {}",
-                Local::now().format("%Y-%m-%d %H:%M:%S"),
-                colors.color(record.level()),
-                message
-            ))
-        })
+    let mut log = fern::Dispatch::new()
         .level(LevelFilter::Off)
-        .level_for("intersection_controller", LevelFilter::Trace)
-        .chain(std::io::stdout());
+        .level_for("intersection_controller", options.log_level)
+        .chain(
+            fern::Dispatch::new()
+                .format(move |out, message, record| {
+                    out.finish(format_args!(
+                        "[{}] [{}] {}",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        colors.color(record.level()),
+                        message
+                    ))
+                })
+                .chain(std::io::stdout()),
+        );
+
+    if let Some(log_file) = &options.log_file {
+        log = log.chain(
+            fern::Dispatch::new()
+                .format(|out, message, record| {
+                    out.finish(format_args!(
+                        "[{}] [{}] {}",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        record.level(),
+                        message
+                    ))
+                })
+                .chain(fern::log_file(log_file)?),
+        );
+    }
 
     fern::Dispatch::new().chain(mqtt_log).chain(log).apply()?;
 
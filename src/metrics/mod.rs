@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use crate::intersections::group::GroupId;
+use crate::io::topics::command_topic::CommandTopic;
+use crate::io::topics::component_topic::ComponentTopic;
+use crate::io::topics::lifecycle_topic::LifeCycleTopic;
+
+pub mod server;
+
+pub type ArcMetrics = Arc<Metrics>;
+
+/// Upper bounds of the wait time buckets, in seconds.
+const WAIT_BUCKETS: &[f64] = &[1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0];
+
+/// Upper bounds of the bridge cycle duration buckets, in seconds.
+const CYCLE_BUCKETS: &[f64] = &[30.0, 60.0, 90.0, 120.0, 180.0, 300.0, 600.0];
+
+struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket, not cumulative.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Writes the samples of the histogram, `labels` being empty or like `group="foot/1"`.
+    fn write(&self, out: &mut String, name: &str, labels: &str) -> std::fmt::Result {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+
+        for (bound, count) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += count;
+            writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, cumulative
+            )?;
+        }
+
+        writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, separator, self.count
+        )?;
+
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+
+        writeln!(out, "{}_sum{} {}", name, labels, self.sum)?;
+        writeln!(out, "{}_count{} {}", name, labels, self.count)
+    }
+}
+
+/// What the controller counts while it runs, served by the metrics server in the Prometheus text
+/// format.
+pub struct Metrics {
+    received: Mutex<BTreeMap<String, u64>>,
+    published: Mutex<BTreeMap<String, u64>>,
+    phases: AtomicU64,
+    publisher_queue: AtomicU64,
+    waits: Mutex<BTreeMap<String, Histogram>>,
+    jam_blocks: Mutex<BTreeMap<String, u64>>,
+    bridge_cycles: Mutex<Histogram>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            received: Mutex::new(BTreeMap::new()),
+            published: Mutex::new(BTreeMap::new()),
+            phases: AtomicU64::new(0),
+            publisher_queue: AtomicU64::new(0),
+            waits: Mutex::new(BTreeMap::new()),
+            jam_blocks: Mutex::new(BTreeMap::new()),
+            bridge_cycles: Mutex::new(Histogram::new(CYCLE_BUCKETS)),
+        }
+    }
+
+    pub fn received(&self, topic: &str) {
+        *lock(&self.received).entry(topic_kind(topic)).or_default() += 1;
+    }
+
+    pub fn published(&self, topic: &str) {
+        *lock(&self.published).entry(topic_kind(topic)).or_default() += 1;
+    }
+
+    pub fn phase_started(&self) {
+        self.phases.fetch_add(1, Relaxed);
+    }
+
+    /// Messages left waiting when the publisher took the last one off its queue.
+    pub fn publisher_queue(&self, depth: usize) {
+        self.publisher_queue.store(depth as u64, Relaxed);
+    }
+
+    /// A group that had to wait for the given time got to proceed.
+    pub fn waited(&self, group: GroupId, wait: Duration) {
+        lock(&self.waits)
+            .entry(group.to_string())
+            .or_insert_with(|| Histogram::new(WAIT_BUCKETS))
+            .observe(wait.as_secs_f64());
+    }
+
+    /// A traffic jam blocked the given group.
+    pub fn jam_blocked(&self, group: GroupId) {
+        *lock(&self.jam_blocks).entry(group.to_string()).or_default() += 1;
+    }
+
+    pub fn bridge_cycled(&self, duration: Duration) {
+        lock(&self.bridge_cycles).observe(duration.as_secs_f64());
+    }
+
+    pub fn write(&self, out: &mut String) -> std::fmt::Result {
+        let counters = [
+            (
+                "controller_messages_received_total",
+                "Messages received per topic kind.",
+                &self.received,
+            ),
+            (
+                "controller_messages_published_total",
+                "Messages published per topic kind.",
+                &self.published,
+            ),
+        ];
+
+        for (name, help, counts) in &counters {
+            write_header(out, name, help, "counter")?;
+
+            for (kind, count) in lock(counts).iter() {
+                writeln!(out, "{}{{kind=\"{}\"}} {}", name, kind, count)?;
+            }
+        }
+
+        let name = "controller_phases_total";
+        write_header(out, name, "Traffic lights phases started.", "counter")?;
+        writeln!(out, "{} {}", name, self.phases.load(Relaxed))?;
+
+        let name = "controller_publisher_queue_depth";
+        write_header(out, name, "Messages waiting to be published.", "gauge")?;
+        writeln!(out, "{} {}", name, self.publisher_queue.load(Relaxed))?;
+
+        let name = "controller_group_wait_seconds";
+        write_header(out, name, "Time groups waited for proceed.", "histogram")?;
+        for (group, histogram) in lock(&self.waits).iter() {
+            histogram.write(out, name, &format!("group=\"{}\"", group))?;
+        }
+
+        let name = "controller_jam_blocks_total";
+        write_header(out, name, "Times a traffic jam blocked a group.", "counter")?;
+        for (group, count) in lock(&self.jam_blocks).iter() {
+            writeln!(out, "{}{{group=\"{}\"}} {}", name, group, count)?;
+        }
+
+        let name = "controller_bridge_cycle_seconds";
+        write_header(
+            out,
+            name,
+            "Duration of completed bridge cycles.",
+            "histogram",
+        )?;
+        lock(&self.bridge_cycles).write(out, name, "")
+    }
+}
+
+pub fn write_header(out: &mut String, name: &str, help: &str, kind: &str) -> std::fmt::Result {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, kind)
+}
+
+/// Counting goes on after a thread panicked while counting, a count may be off by one then.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The component kind of a component topic, otherwise the kind of topic.
+fn topic_kind(topic: &str) -> String {
+    if let Ok(topic) = ComponentTopic::try_from(topic) {
+        return topic.uid.component_id.kind.to_string();
+    }
+
+    if LifeCycleTopic::try_from(topic).is_ok() {
+        return String::from("lifecycle");
+    }
+
+    if CommandTopic::try_from(topic).is_ok() {
+        return String::from("command");
+    }
+
+    String::from("other")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intersections::group::GroupKind;
+
+    #[test]
+    fn test_write_metrics() {
+        let metrics = Metrics::new();
+        let group = GroupId {
+            kind: GroupKind::Foot,
+            id: 1,
+        };
+
+        metrics.received("4/foot/1/sensor/1");
+        metrics.received("4/foot/1/sensor/2");
+        metrics.received("4/features/lifecycle/simulator/onconnect");
+        metrics.published("4/foot/1/light/1");
+        metrics.phase_started();
+        metrics.publisher_queue(3);
+        metrics.waited(group, Duration::from_secs(4));
+        metrics.waited(group, Duration::from_secs(50));
+        metrics.bridge_cycled(Duration::from_secs(75));
+
+        let mut out = String::new();
+        metrics.write(&mut out).unwrap();
+
+        assert!(out.contains("controller_messages_received_total{kind=\"sensor\"} 2\n"));
+        assert!(out.contains("controller_messages_received_total{kind=\"lifecycle\"} 1\n"));
+        assert!(out.contains("controller_messages_published_total{kind=\"light\"} 1\n"));
+        assert!(out.contains("controller_phases_total 1\n"));
+        assert!(out.contains("controller_publisher_queue_depth 3\n"));
+        let bucket = "controller_group_wait_seconds_bucket{group=\"foot/1\",le=\"5\"} 1\n";
+        assert!(out.contains(bucket));
+        assert!(
+            out.contains("controller_group_wait_seconds_bucket{group=\"foot/1\",le=\"+Inf\"} 2\n")
+        );
+        assert!(out.contains("controller_group_wait_seconds_sum{group=\"foot/1\"} 54\n"));
+        assert!(out.contains("controller_bridge_cycle_seconds_bucket{le=\"90\"} 1\n"));
+        assert!(out.contains("controller_bridge_cycle_seconds_count 1\n"));
+    }
+}
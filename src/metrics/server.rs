@@ -0,0 +1,190 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::RwLockExt;
+use crate::intersections::component::{Component, ComponentUid};
+use crate::intersections::intersection::ArcIntersection;
+use crate::metrics::{write_header, ArcMetrics};
+
+/// How long a client gets to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the metrics on `GET /metrics`, together with the light states at the time of the
+/// request.
+pub struct MetricsServer {
+    listener: TcpListener,
+    metrics: ArcMetrics,
+    intersections: Vec<ArcIntersection>,
+}
+
+impl MetricsServer {
+    pub fn bind(
+        address: &str,
+        metrics: ArcMetrics,
+        intersections: Vec<ArcIntersection>,
+    ) -> Result<Self, failure::Error> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            metrics,
+            intersections,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, failure::Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Answers every request on its own thread, so a slow client doesn't hold up the others, for
+    /// as long as the process runs.
+    pub fn run(self) {
+        let server = Arc::new(self);
+
+        for stream in server.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Could not accept a metrics request: {}", e);
+                    continue;
+                }
+            };
+
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                server
+                    .respond(stream)
+                    .unwrap_or_else(|e| warn!("Could not serve metrics: {}", e));
+            });
+        }
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> Result<(), failure::Error> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+
+        let mut parts = request.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render()?),
+            _ => ("404 Not Found", String::from("Not found\n")),
+        };
+
+        write!(
+            stream,
+            concat!(
+                "HTTP/1.1 {}\r\n",
+                "Content-Type: text/plain; version=0.0.4\r\n",
+                "Content-Length: {}\r\n",
+                "Connection: close\r\n",
+                "\r\n",
+                "{}"
+            ),
+            status,
+            body.len(),
+            body
+        )?;
+
+        Ok(())
+    }
+
+    fn render(&self) -> Result<String, failure::Error> {
+        let mut out = String::new();
+        self.metrics.write(&mut out)?;
+
+        let name = "controller_light_state";
+        write_header(
+            &mut out,
+            name,
+            "Light state, 0 prohibit, 1 transitioning, 2 proceed, 3 out of order.",
+            "gauge",
+        )?;
+
+        for (uid, state) in self.light_states()? {
+            writeln!(out, "{}{{light=\"{}\"}} {}", name, uid, state)?;
+        }
+
+        Ok(out)
+    }
+
+    fn light_states(&self) -> Result<Vec<(ComponentUid, i32)>, failure::Error> {
+        let mut states = vec![];
+
+        for intersection in &self.intersections {
+            let groups = intersection.read_checked()?.groups();
+
+            for group in groups {
+                let group = group.read_checked()?;
+
+                for light in group.lights.values() {
+                    let light = light.read_checked()?;
+                    let uid = ComponentUid {
+                        group_id: group.id,
+                        component_id: light.id(),
+                    };
+
+                    states.push((uid, light.state().into()));
+                }
+            }
+        }
+
+        states.sort_by_key(|(uid, _)| uid.to_string());
+
+        Ok(states)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::sync::Arc;
+    use std::thread;
+
+    use crossbeam_channel::unbounded;
+
+    use crate::config::Config;
+    use crate::intersections::intersection_builder::IntersectionsBuilder;
+    use crate::metrics::Metrics;
+
+    #[test]
+    fn test_serve_metrics() {
+        let config = Config::new("config").unwrap();
+        let (sender, _receiver) = unbounded();
+        let intersection = IntersectionsBuilder::new(sender)
+            .with_defs(&config.traffic_lights)
+            .finish()
+            .unwrap();
+
+        let server =
+            MetricsServer::bind("127.0.0.1:0", Arc::new(Metrics::new()), vec![intersection])
+                .unwrap();
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(2)))
+                .unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        // A client that doesn't send its request doesn't keep the others waiting.
+        let _idle = TcpStream::connect(address).unwrap();
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("controller_light_state{light=\"motor_vehicle/1/light/1\"} 0\n"));
+        assert!(response.contains("controller_publisher_queue_depth 0\n"));
+
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rumqtt::{MqttOptions, QoS};
+
+use crate::clock::manual::ManualClock;
+use crate::clock::ArcClock;
+use crate::config::Config;
+use crate::core::controller::{Controller, Mode};
+use crate::error::Error;
+use crate::intersections::intersection::ArcIntersection;
+use crate::intersections::intersection_builder::IntersectionsBuilder;
+use crate::io::client::Client;
+use crate::io::recording::Recorder;
+use crate::io::topics::lifecycle_topic::{Device, Handler, LifeCycleTopic};
+use crate::io::topics::Topic;
+use crate::io::transport::in_process::InProcessBroker;
+use crate::simulation::SimulationError;
+
+/// Simulated time per step.
+pub const STEP: Duration = Duration::from_millis(100);
+
+/// Real time per step at the least, for the work the controller threads do once woken.
+pub const STEP_PAUSE: Duration = Duration::from_millis(2);
+
+/// How long a step waits at most for the controller to take the messages sent to it.
+const DELIVERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long a step waits at most for the controller threads it woke up, so a thread that never
+/// gets back to the clock can't stall the simulation.
+const WAKE_UP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the controller gets to connect to the in-process broker.
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The controller running on its own thread, connected to an in-process broker and with its
+/// intersections on a manually advanced clock.
+pub struct Harness {
+    pub clock: Arc<ManualClock>,
+    pub broker: InProcessBroker,
+    /// Every message passing the broker since the controller announced itself.
+    pub messages: Receiver<(String, Vec<u8>)>,
+
+    pub traffic_lights: ArcIntersection,
+    pub bridge: ArcIntersection,
+
+    team_id: i32,
+    shutdown: Sender<()>,
+    handle: JoinHandle<Result<(), failure::Error>>,
+}
+
+impl Harness {
+    /// Builds the intersections and starts the controller, returns once it announced itself. The
+    /// messages of the controller are recorded to the given file, if any.
+    pub fn start(
+        mut config: Config,
+        config_dir: &str,
+        mode: Mode,
+        recording: Option<&str>,
+    ) -> Result<Self, failure::Error> {
+        // Alone on its broker, the controller has no running instances to wait for.
+        config.general.takeover_delay = Some(0);
+
+        let team_id = config.general.team_id;
+        let clock = Arc::new(ManualClock::new());
+
+        let (notification_sender, notification_receiver) = unbounded();
+        let traffic_lights = IntersectionsBuilder::new(notification_sender.clone())
+            .with_clock(Arc::clone(&clock) as ArcClock)
+            .with_defs(&config.traffic_lights)
+            .with_blocks(&config.traffic_lights_blocks)
+            .finish()?;
+        let bridge = IntersectionsBuilder::new(notification_sender)
+            .with_clock(Arc::clone(&clock) as ArcClock)
+            .with_defs(&config.bridge)
+            .finish()?;
+
+        let broker = InProcessBroker::new();
+        let messages = broker.tap()?;
+        let client = |id: &str| {
+            Client::with_transport(
+                MqttOptions::new(id, "localhost", 1883),
+                QoS::AtLeastOnce,
+                team_id,
+                Box::new(broker.transport()),
+            )
+        };
+        let (publisher, subscriber) = (
+            client("simulated-publisher"),
+            client("simulated-subscriber"),
+        );
+
+        let mut controller = Controller::new(
+            Arc::clone(&traffic_lights),
+            Arc::clone(&bridge),
+            notification_receiver,
+            config,
+            config_dir,
+            mode,
+        )?;
+        if let Some(path) = recording {
+            let recorder = Recorder::create(path, Arc::clone(&clock) as ArcClock)?;
+            controller.set_recorder(Arc::new(recorder));
+        }
+
+        let shutdown = controller.shutdown_sender();
+        let handle = thread::spawn(move || controller.start(publisher, subscriber));
+
+        let harness = Self {
+            clock,
+            broker,
+            messages,
+            traffic_lights,
+            bridge,
+            team_id,
+            shutdown,
+            handle,
+        };
+
+        let connect = harness.topic(Box::new(LifeCycleTopic::new(
+            Device::Controller,
+            Handler::Connect,
+        )));
+
+        loop {
+            match harness.messages.recv_timeout(START_TIMEOUT) {
+                Ok((name, _)) if name == connect => return Ok(harness),
+                Ok(_) => {}
+                Err(_) => {
+                    return Err(SimulationError::ControllerNotStarted {
+                        seconds: START_TIMEOUT.as_secs(),
+                    }
+                    .into())
+                }
+            }
+        }
+    }
+
+    /// The name of a topic of the team the controller runs for.
+    pub fn topic(&self, mut topic: Box<dyn Topic>) -> String {
+        topic.set_team_id(self.team_id);
+        topic.to_string()
+    }
+
+    /// Advances the clock by one step, waits for the controller threads it woke up and gives them
+    /// the given real time to finish.
+    pub fn step(&self, pause: Duration) -> Result<(), failure::Error> {
+        if self.handle.is_finished() {
+            return Err(SimulationError::ControllerStopped.into());
+        }
+
+        // The controller receives the messages at the time they were sent, as far as it keeps up.
+        let deadline = Instant::now() + DELIVERY_TIMEOUT;
+        while !self.broker.delivered()? && Instant::now() < deadline {
+            thread::sleep(Duration::from_micros(100));
+        }
+
+        self.clock.advance(STEP);
+
+        // However busy the machine is, the woken threads run before the clock moves on.
+        let deadline = Instant::now() + WAKE_UP_TIMEOUT;
+        while !self.clock.caught_up() && Instant::now() < deadline {
+            thread::sleep(Duration::from_micros(100));
+        }
+
+        thread::sleep(pause.max(STEP_PAUSE));
+
+        Ok(())
+    }
+
+    /// Shuts the controller down and waits until it is gone.
+    pub fn stop(self) -> Result<(), failure::Error> {
+        self.shutdown.send(())?;
+
+        self.handle.join().map_err(Error::from_panic)?
+    }
+}
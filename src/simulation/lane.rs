@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::distributions::{Distribution, Exp};
+use rand::Rng;
+
+use crate::intersections::component::ComponentUid;
+use crate::intersections::group::GroupId;
+use crate::intersections::sensor::SensorState;
+
+/// The road users of one group. They arrive at random, wait in front of the first sensor of the
+/// group and leave one by one while its light shows proceed.
+pub struct Lane {
+    pub group: GroupId,
+    pub sensor: ComponentUid,
+    pub lights: Vec<ComponentUid>,
+
+    /// Time between two arrivals in milliseconds.
+    interval: Exp,
+    departure_time: Duration,
+
+    next_arrival: DateTime<Utc>,
+    last_departure: Option<DateTime<Utc>>,
+    queue: VecDeque<DateTime<Utc>>,
+    sensor_state: SensorState,
+
+    pub arrived: usize,
+    /// The waiting times of everyone that left.
+    pub waits: Vec<Duration>,
+}
+
+impl Lane {
+    /// Creates a lane with the given average number of arrivals per minute, which must be above 0.
+    pub fn new<R: Rng>(
+        group: GroupId,
+        sensor: ComponentUid,
+        lights: Vec<ComponentUid>,
+        rate: f64,
+        departure_time: Duration,
+        now: DateTime<Utc>,
+        rng: &mut R,
+    ) -> Self {
+        let interval = Exp::new(rate / 60_000.0);
+        let next_arrival = now + millis(interval.sample(rng));
+
+        Self {
+            group,
+            sensor,
+            lights,
+            interval,
+            departure_time,
+            next_arrival,
+            last_departure: None,
+            queue: VecDeque::new(),
+            sensor_state: SensorState::Low,
+            arrived: 0,
+            waits: vec![],
+        }
+    }
+
+    /// Lets everyone arrive until the given time and lets them leave while the light shows
+    /// proceed. Returns the new state of the sensor when it changed, and how many left.
+    pub fn update<R: Rng>(
+        &mut self,
+        now: DateTime<Utc>,
+        proceed: bool,
+        rng: &mut R,
+    ) -> (Option<SensorState>, usize) {
+        while self.next_arrival <= now {
+            self.queue.push_back(self.next_arrival);
+            self.arrived += 1;
+            self.next_arrival += millis(self.interval.sample(rng));
+        }
+
+        let mut departed = 0;
+
+        while proceed && !self.queue.is_empty() && self.may_depart(now) {
+            if let Some(arrival) = self.queue.pop_front() {
+                self.waits
+                    .push((now - arrival).to_std().unwrap_or_default());
+                self.last_departure = Some(now);
+                departed += 1;
+            }
+        }
+
+        let state = if self.queue.is_empty() {
+            SensorState::Low
+        } else {
+            SensorState::High
+        };
+
+        if state == self.sensor_state {
+            return (None, departed);
+        }
+
+        self.sensor_state = state;
+
+        (Some(state), departed)
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn may_depart(&self, now: DateTime<Utc>) -> bool {
+        match self.last_departure {
+            Some(last) => (now - last).to_std().unwrap_or_default() >= self.departure_time,
+            None => true,
+        }
+    }
+}
+
+fn millis(value: f64) -> chrono::Duration {
+    chrono::Duration::microseconds((value * 1_000.0) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::intersections::component::ComponentKind;
+    use crate::intersections::group::GroupKind;
+
+    #[test]
+    fn test_leaves_while_proceed() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let group = GroupId {
+            kind: GroupKind::MotorVehicle,
+            id: 1,
+        };
+        let sensor = ComponentUid::new(GroupKind::MotorVehicle, 1, ComponentKind::Sensor, 1);
+
+        let start = Utc::now();
+        let mut lane = Lane::new(
+            group,
+            sensor,
+            vec![],
+            60.0,
+            Duration::from_secs(2),
+            start,
+            &mut rng,
+        );
+
+        let (state, departed) = lane.update(start + chrono::Duration::minutes(10), false, &mut rng);
+        assert!(state == Some(SensorState::High));
+        assert_eq!(departed, 0);
+
+        // Around one arrival per second, so ten minutes bring hundreds of road users.
+        let arrived = lane.arrived;
+        assert!(arrived > 400 && arrived < 800, "{} arrived", arrived);
+        assert_eq!(lane.waiting(), arrived);
+
+        let (_, departed) = lane.update(start + chrono::Duration::minutes(10), true, &mut rng);
+        assert_eq!(departed, 1);
+        assert_eq!(lane.waits.len(), 1);
+        assert!(lane.waits[0] > Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use failure::Fail;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::clock::Clock;
+use crate::config::simulation::Simulation;
+use crate::config::Config;
+use crate::core::controller::Mode;
+use crate::error::{Error, RwLockExt};
+use crate::intersections::component::{Component, ComponentKind, ComponentUid};
+use crate::intersections::deck::DeckState;
+use crate::intersections::group::{GroupId, GroupKind};
+use crate::intersections::intersection::ArcIntersection;
+use crate::intersections::light::LightState;
+use crate::intersections::sensor::SensorState;
+use crate::io::topics::component_topic::ComponentTopic;
+use crate::io::topics::lifecycle_topic::{Device, Handler, LifeCycleTopic};
+use crate::simulation::harness::{Harness, STEP_PAUSE};
+use crate::simulation::lane::Lane;
+use crate::simulation::report::{GroupReport, Report, Violation};
+
+pub mod harness;
+pub mod lane;
+pub mod replay;
+pub mod report;
+
+#[derive(Debug, Fail)]
+pub enum SimulationError {
+    #[fail(display = "The controller did not start within {} seconds", seconds)]
+    ControllerNotStarted { seconds: u64 },
+
+    #[fail(display = "The controller stopped before the simulation ended")]
+    ControllerStopped,
+}
+
+/// Runs the controller against an in-process broker and a manually advanced clock, and plays the
+/// simulator: road users arrive at random and their sensors are published like the simulator
+/// would. The published light states decide when they leave and are checked for conflicts.
+pub struct Simulator {
+    config: Config,
+    config_dir: String,
+    mode: Mode,
+    recording: Option<String>,
+}
+
+impl Simulator {
+    pub fn new(config: Config, config_dir: &str, mode: Mode) -> Self {
+        Self {
+            config,
+            config_dir: String::from(config_dir),
+            mode,
+            recording: None,
+        }
+    }
+
+    /// Records the messages of the simulated session to a file, to replay it later.
+    pub fn record_to(mut self, path: &str) -> Self {
+        self.recording = Some(String::from(path));
+        self
+    }
+
+    pub fn run(self) -> Result<Report, failure::Error> {
+        let config = self.config;
+        let duration = Duration::from_millis(config.simulation.general.duration as u64);
+        let seed = config.simulation.general.seed.unwrap_or_else(rand::random);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let passages = Self::passages(&config)?;
+        let arrivals = config.simulation.clone();
+
+        let harness = Harness::start(
+            config,
+            &self.config_dir,
+            self.mode,
+            self.recording.as_deref(),
+        )?;
+        let clock = &harness.clock;
+        let start = clock.now();
+
+        let mut intersections = vec![];
+
+        if self.mode.runs_traffic_lights() {
+            intersections.push(&harness.traffic_lights);
+        }
+
+        if self.mode.runs_bridge() {
+            intersections.push(&harness.bridge);
+        }
+
+        let mut lanes = vec![];
+        let mut safety = Safety::new();
+
+        for intersection in intersections {
+            lanes.extend(Self::build_lanes(
+                &arrivals,
+                intersection,
+                clock.now(),
+                &mut rng,
+            )?);
+            safety.watch(intersection)?;
+        }
+
+        info!("Simulating {} s of traffic", duration.as_secs());
+
+        harness.broker.inject(
+            &harness.topic(Box::new(LifeCycleTopic::new(
+                Device::Simulator,
+                Handler::Connect,
+            ))),
+            b"",
+        )?;
+
+        let mut passing = vec![];
+
+        while clock.since(start) < duration {
+            let now = clock.now();
+
+            for (name, payload) in harness.messages.try_iter() {
+                safety.record(&name, &payload);
+            }
+
+            safety.check(clock.since(start));
+
+            // Vessels passed the passage sensor during the previous step.
+            for sensor in passing.drain(..) {
+                Self::publish_sensor(&harness, sensor, SensorState::Low)?;
+            }
+
+            for lane in &mut lanes {
+                let proceed = safety.proceeds(&lane.lights);
+                let (state, departed) = lane.update(now, proceed, &mut rng);
+
+                if let Some(state) = state {
+                    Self::publish_sensor(&harness, lane.sensor, state)?;
+                }
+
+                if let (true, Some(sensor)) = (departed > 0, passages.get(&lane.group)) {
+                    Self::publish_sensor(&harness, *sensor, SensorState::High)?;
+                    passing.push(*sensor);
+                }
+            }
+
+            harness.step(STEP_PAUSE)?;
+        }
+
+        let at = clock.since(start);
+        let messages = harness.messages.clone();
+        harness.stop()?;
+
+        for (name, payload) in messages.try_iter() {
+            safety.record(&name, &payload);
+        }
+
+        safety.check(at);
+
+        Ok(Report {
+            duration,
+            seed,
+            groups: lanes.iter().map(GroupReport::from).collect(),
+            violations: safety.violations,
+        })
+    }
+
+    /// A lane for every group with a light, a sensor and arrivals configured for its kind.
+    fn build_lanes(
+        simulation: &Simulation,
+        intersection: &ArcIntersection,
+        now: DateTime<Utc>,
+        rng: &mut StdRng,
+    ) -> Result<Vec<Lane>, failure::Error> {
+        let mut lanes = vec![];
+
+        let mut groups = intersection.read_checked()?.groups();
+        groups.sort_by_key(|g| g.read().map(|g| (g.id.kind.to_string(), g.id.id)).ok());
+
+        for group in groups {
+            let group = group.read_checked()?;
+
+            let arrivals = simulation
+                .arrivals
+                .iter()
+                .find(|a| GroupKind::try_from(&a.kind[..]).ok() == Some(group.id.kind));
+
+            let arrivals = match arrivals {
+                Some(arrivals) if arrivals.rate > 0.0 => arrivals,
+                _ => continue,
+            };
+
+            let sensor = match group.sensors.keys().min_by_key(|id| id.id) {
+                Some(id) => ComponentUid {
+                    group_id: group.id,
+                    component_id: *id,
+                },
+                None => continue,
+            };
+
+            let lights: Vec<ComponentUid> = group
+                .lights
+                .keys()
+                .map(|id| ComponentUid {
+                    group_id: group.id,
+                    component_id: *id,
+                })
+                .collect();
+
+            if lights.is_empty() {
+                continue;
+            }
+
+            lanes.push(Lane::new(
+                group.id,
+                sensor,
+                lights,
+                arrivals.rate,
+                Duration::from_millis(arrivals.departure_time as u64),
+                now,
+                rng,
+            ));
+        }
+
+        Ok(lanes)
+    }
+
+    /// The sensor every vessel group passes, from the pass_vessels steps of the bridge sequence.
+    fn passages(config: &Config) -> Result<HashMap<GroupId, ComponentUid>, failure::Error> {
+        let mut passages = HashMap::new();
+
+        for step in &config.bridge_sequence.steps {
+            if let (Some(groups), Some(sensor)) = (&step.groups, &step.sensor) {
+                let sensor = ComponentUid::try_from(&sensor[..])?;
+
+                for group in groups {
+                    passages.insert(GroupId::try_from(&group[..])?, sensor);
+                }
+            }
+        }
+
+        Ok(passages)
+    }
+
+    fn publish_sensor(
+        harness: &Harness,
+        sensor: ComponentUid,
+        state: SensorState,
+    ) -> Result<(), Error> {
+        let state: i32 = state.into();
+
+        harness.broker.inject(
+            &harness.topic(Box::new(ComponentTopic::from(sensor))),
+            state.to_string().as_bytes(),
+        )
+    }
+}
+
+/// Follows the published light and deck states and records every moment they let conflicting
+/// traffic in.
+struct Safety {
+    blocks: HashMap<GroupId, Vec<GroupId>>,
+    lights: HashMap<ComponentUid, LightState>,
+    decks: HashMap<ComponentUid, DeckState>,
+
+    /// The violations going on at the last check, so a violation is only recorded once.
+    ongoing: HashSet<String>,
+    violations: Vec<Violation>,
+}
+
+impl Safety {
+    fn new() -> Self {
+        Self {
+            blocks: HashMap::new(),
+            lights: HashMap::new(),
+            decks: HashMap::new(),
+            ongoing: HashSet::new(),
+            violations: vec![],
+        }
+    }
+
+    /// Starts from the states and block relations the intersection was built with.
+    fn watch(&mut self, intersection: &ArcIntersection) -> Result<(), Error> {
+        for group in intersection.read_checked()?.groups() {
+            let group = group.read_checked()?;
+
+            let mut blocks = vec![];
+            for block in &group.blocks {
+                blocks.push(block.read_checked()?.id);
+            }
+            self.blocks.insert(group.id, blocks);
+
+            for light in group.lights.values() {
+                let light = light.read_checked()?;
+                self.lights.insert(light.uid()?, light.state());
+            }
+
+            for deck in group.decks.values() {
+                let deck = deck.read_checked()?;
+                self.decks.insert(deck.uid()?, deck.state());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record(&mut self, name: &str, payload: &[u8]) {
+        let topic = match ComponentTopic::try_from(name) {
+            Ok(topic) => topic,
+            Err(_) => return,
+        };
+
+        let state = match String::from_utf8_lossy(payload).parse::<i32>() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+
+        match topic.uid.component_id.kind {
+            ComponentKind::Light => {
+                if let Ok(state) = LightState::try_from(state) {
+                    self.lights.insert(topic.uid, state);
+                }
+            }
+            ComponentKind::Deck => {
+                if let Ok(state) = DeckState::try_from(state) {
+                    self.decks.insert(topic.uid, state);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether one of the given lights shows proceed.
+    fn proceeds(&self, lights: &[ComponentUid]) -> bool {
+        lights
+            .iter()
+            .any(|uid| self.lights.get(uid) == Some(&LightState::Proceed))
+    }
+
+    fn check(&mut self, at: Duration) {
+        let mut exclusive: Vec<GroupId> = self
+            .lights
+            .iter()
+            .filter(|(_, state)| {
+                **state == LightState::Proceed || **state == LightState::Transitioning
+            })
+            .map(|(uid, _)| uid.group_id)
+            .collect::<HashSet<GroupId>>()
+            .into_iter()
+            .collect();
+        exclusive.sort_by_key(|id| (id.kind.to_string(), id.id));
+
+        let deck_open = self.decks.values().any(|state| *state == DeckState::Open);
+        let deck_closed = self.decks.values().any(|state| *state == DeckState::Close);
+
+        let mut current = HashSet::new();
+
+        for (index, a) in exclusive.iter().enumerate() {
+            for b in &exclusive[index + 1..] {
+                if self.blocks.get(a).is_some_and(|blocks| blocks.contains(b)) {
+                    current.insert(format!("{} and {} proceed together", a, b));
+                }
+            }
+
+            match a.kind {
+                GroupKind::Vessel if deck_closed => {
+                    current.insert(format!("{} proceeds while the deck is closed", a));
+                }
+                GroupKind::Bridge if deck_open => {
+                    current.insert(format!("{} proceeds while the deck is open", a));
+                }
+                _ => {}
+            }
+        }
+
+        for description in &current {
+            if !self.ongoing.contains(description) {
+                warn!("Safety violation: {}", description);
+
+                self.violations.push(Violation {
+                    at,
+                    description: description.clone(),
+                });
+            }
+        }
+
+        self.ongoing = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulation_without_violations() {
+        let mut config = Config::new("config").unwrap();
+        config.simulation.general.duration = 60_000;
+        config.simulation.general.seed = Some(4);
+
+        let report = Simulator::new(config, "config", Mode::All).run().unwrap();
+
+        assert_eq!(report.duration, Duration::from_secs(60));
+        assert!(!report.groups.is_empty());
+        assert!(report.throughput() > 0);
+        assert!(report.violations.is_empty());
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+use failure::Fail;
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::core::controller::Mode;
+use crate::intersections::component::{ComponentKind, ComponentUid};
+use crate::io::recording::{Direction, RecordedMessage};
+use crate::io::topics::component_topic::ComponentTopic;
+use crate::io::topics::lifecycle_topic::{Device, Handler, LifeCycleTopic};
+use crate::io::topics::Topic;
+use crate::simulation::harness::{Harness, STEP};
+
+#[derive(Debug, Fail)]
+pub enum ReplayError {
+    #[fail(display = "The recording holds no messages")]
+    EmptyRecording,
+
+    #[fail(display = "The recording has no controller announcing itself")]
+    NoController,
+
+    #[fail(
+        display = "The recording is of team {}, but the configuration is of team {}",
+        recorded, configured
+    )]
+    OtherTeam { recorded: i32, configured: i32 },
+}
+
+/// The states an actuator was set to over time, without repeats.
+type History = Vec<(Duration, String)>;
+
+/// An actuator the replayed controller set to other states than the recorded one did.
+pub struct Difference {
+    pub uid: ComponentUid,
+    pub recorded: History,
+    pub replayed: History,
+}
+
+impl Difference {
+    /// The first change that differs.
+    fn first(&self) -> usize {
+        self.recorded
+            .iter()
+            .zip(&self.replayed)
+            .position(|(recorded, replayed)| recorded.1 != replayed.1)
+            .unwrap_or_else(|| self.recorded.len().min(self.replayed.len()))
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let change = |history: &History, index: usize| match history.get(index) {
+            Some((at, state)) => format!("{} at {:.1} s", state, at.as_secs_f64()),
+            None => String::from("nothing"),
+        };
+
+        let first = self.first();
+
+        write!(
+            f,
+            "{}: change {} was {}, replayed {}",
+            self.uid,
+            first + 1,
+            change(&self.recorded, first),
+            change(&self.replayed, first)
+        )
+    }
+}
+
+pub struct ReplayReport {
+    pub duration: Duration,
+    /// Recorded messages fed into the controller.
+    pub messages: usize,
+    /// Actuators set to the same states as in the recording.
+    pub matching: usize,
+    pub differences: Vec<Difference>,
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Replayed {} messages over {:.1} s",
+            self.messages,
+            self.duration.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "Actuators: {} matching, {} differing",
+            self.matching,
+            self.differences.len()
+        )?;
+
+        for difference in &self.differences {
+            writeln!(f, "  {}", difference)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Feeds the messages a controller received in a recorded session into a new controller, and
+/// compares the actuator states it publishes with the recorded ones.
+pub struct Replayer {
+    config: Config,
+    config_dir: String,
+    mode: Mode,
+    /// How much faster than recorded the messages are fed in.
+    speed: f64,
+}
+
+impl Replayer {
+    pub fn new(config: Config, config_dir: &str, mode: Mode, speed: f64) -> Self {
+        Self {
+            config,
+            config_dir: String::from(config_dir),
+            mode,
+            speed,
+        }
+    }
+
+    pub fn run(self, recording: &[RecordedMessage]) -> Result<ReplayReport, failure::Error> {
+        let first = recording.first().ok_or(ReplayError::EmptyRecording)?.at;
+        let offset = |message: &RecordedMessage| Duration::from_millis((message.at - first) as u64);
+
+        // The recorded controller hears its own announcements, the replayed one must not take
+        // them for another instance.
+        let mut instances = HashSet::new();
+        let mut announced = None;
+
+        for (index, message) in recording.iter().enumerate() {
+            if message.direction != Direction::Outbound {
+                continue;
+            }
+
+            if let Ok(topic) = LifeCycleTopic::try_from(&message.topic[..]) {
+                if topic.device == Device::Controller && topic.handler == Handler::Connect {
+                    instances.insert(&message.payload[..]);
+                    announced = announced.or(Some((index, topic.team_id()?)));
+                }
+            }
+        }
+
+        let (announced, team_id) = announced.ok_or(ReplayError::NoController)?;
+
+        if team_id != self.config.general.team_id {
+            return Err(ReplayError::OtherTeam {
+                recorded: team_id,
+                configured: self.config.general.team_id,
+            }
+            .into());
+        }
+
+        let inbound: Vec<&RecordedMessage> = recording
+            .iter()
+            .filter(|message| message.direction == Direction::Inbound)
+            .filter(
+                |message| match LifeCycleTopic::try_from(&message.topic[..]) {
+                    Ok(topic) => {
+                        topic.device != Device::Controller
+                            || !instances.contains(&message.payload[..])
+                    }
+                    Err(_) => true,
+                },
+            )
+            .collect();
+
+        // The replayed controller is only heard from once it announced itself.
+        let mut recorded = HashMap::new();
+        for message in &recording[announced..] {
+            if message.direction == Direction::Outbound {
+                record(
+                    &mut recorded,
+                    &message.topic,
+                    offset(message),
+                    &message.payload,
+                );
+            }
+        }
+
+        // The replay ends where the recording does, with the controller shutting down.
+        let end = recording.last().map(offset).unwrap_or_default();
+        let pause = Duration::from_secs_f64(STEP.as_secs_f64() / self.speed);
+
+        let harness = Harness::start(self.config, &self.config_dir, self.mode, None)?;
+        let clock = &harness.clock;
+        let start = clock.now();
+
+        info!(
+            "Replaying {} messages over {} s",
+            inbound.len(),
+            end.as_secs()
+        );
+
+        let mut replayed = HashMap::new();
+        let mut pending = inbound.iter().peekable();
+
+        while clock.since(start) <= end {
+            let at = clock.since(start);
+
+            while let Some(message) = pending.next_if(|message| offset(message) <= at) {
+                harness
+                    .broker
+                    .inject(&message.topic, message.payload.as_bytes())?;
+            }
+
+            for (name, payload) in harness.messages.try_iter() {
+                record(&mut replayed, &name, at, &String::from_utf8_lossy(&payload));
+            }
+
+            harness.step(pause)?;
+        }
+
+        let messages = harness.messages.clone();
+        harness.stop()?;
+
+        for (name, payload) in messages.try_iter() {
+            record(
+                &mut replayed,
+                &name,
+                end,
+                &String::from_utf8_lossy(&payload),
+            );
+        }
+
+        let mut uids: Vec<ComponentUid> = recorded.keys().chain(replayed.keys()).cloned().collect();
+        uids.sort_by_key(|uid| uid.to_string());
+        uids.dedup();
+
+        let mut matching = 0;
+        let mut differences = vec![];
+
+        for uid in uids {
+            let recorded = recorded.remove(&uid).unwrap_or_default();
+            let replayed = replayed.remove(&uid).unwrap_or_default();
+
+            let same = recorded.len() == replayed.len()
+                && recorded.iter().zip(&replayed).all(|(a, b)| a.1 == b.1);
+
+            if same {
+                matching += 1;
+            } else {
+                differences.push(Difference {
+                    uid,
+                    recorded,
+                    replayed,
+                });
+            }
+        }
+
+        Ok(ReplayReport {
+            duration: end,
+            messages: inbound.len(),
+            matching,
+            differences,
+        })
+    }
+}
+
+/// Adds the state of an actuator topic to its history, unless it repeats the last one.
+fn record(histories: &mut HashMap<ComponentUid, History>, name: &str, at: Duration, state: &str) {
+    let uid = match ComponentTopic::try_from(name) {
+        Ok(topic) if topic.uid.component_id.kind != ComponentKind::Sensor => topic.uid,
+        _ => return,
+    };
+
+    let history = histories.entry(uid).or_default();
+
+    if history.last().map(|(_, last)| &last[..]) != Some(state) {
+        history.push((at, String::from(state)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::intersections::sensor::SensorState;
+    use crate::io::recording;
+    use crate::io::topics::lifecycle_topic::{Device, Handler};
+    use crate::simulation::harness::STEP_PAUSE;
+
+    #[test]
+    fn test_replay_matches_recording() {
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        // One road user at a time with plenty of time in between, so every phase is decided the
+        // same way however the controller threads happen to be scheduled.
+        let arrivals = [
+            (1, "motor_vehicle/1/sensor/1"),
+            (31, "foot/1/sensor/1"),
+            (61, "cycle/2/sensor/1"),
+        ];
+        let end = Duration::from_secs(100);
+
+        let config = Config::new("config").unwrap();
+        let harness = Harness::start(config, "config", Mode::TrafficLights, Some(path)).unwrap();
+        let start = harness.clock.now();
+
+        let connect = harness.topic(Box::new(LifeCycleTopic::new(
+            Device::Simulator,
+            Handler::Connect,
+        )));
+        harness.broker.inject(&connect, b"").unwrap();
+
+        while harness.clock.since(start) < end {
+            let at = harness.clock.since(start);
+
+            for (second, sensor) in &arrivals {
+                let state = if at == Duration::from_secs(*second) {
+                    SensorState::High
+                } else if at == Duration::from_secs(second + 2) {
+                    SensorState::Low
+                } else {
+                    continue;
+                };
+
+                let uid = ComponentUid::try_from(*sensor).unwrap();
+                let state: i32 = state.into();
+
+                harness
+                    .broker
+                    .inject(
+                        &harness.topic(Box::new(ComponentTopic::from(uid))),
+                        state.to_string().as_bytes(),
+                    )
+                    .unwrap();
+            }
+
+            harness.step(STEP_PAUSE).unwrap();
+        }
+
+        harness.stop().unwrap();
+
+        let recording = recording::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let config = Config::new("config").unwrap();
+        let report = Replayer::new(config, "config", Mode::TrafficLights, 50.0)
+            .run(&recording)
+            .unwrap();
+
+        assert_eq!(report.messages, 1 + 2 * arrivals.len());
+        assert!(report.differences.is_empty(), "{}", report);
+        assert!(report.matching >= arrivals.len());
+    }
+}
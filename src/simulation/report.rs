@@ -0,0 +1,109 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::intersections::group::GroupId;
+use crate::simulation::lane::Lane;
+
+/// The traffic of one group over the whole simulation.
+pub struct GroupReport {
+    pub group: GroupId,
+    pub arrived: usize,
+    pub departed: usize,
+    pub waiting: usize,
+    pub average_wait: Duration,
+    pub max_wait: Duration,
+}
+
+impl From<&Lane> for GroupReport {
+    fn from(lane: &Lane) -> Self {
+        let total: Duration = lane.waits.iter().sum();
+
+        Self {
+            group: lane.group,
+            arrived: lane.arrived,
+            departed: lane.waits.len(),
+            waiting: lane.waiting(),
+            average_wait: match lane.waits.len() {
+                0 => Duration::from_millis(0),
+                departed => total / departed as u32,
+            },
+            max_wait: lane.waits.iter().max().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// A moment the published states let conflicting traffic in at the same time.
+pub struct Violation {
+    /// Simulated time since the start.
+    pub at: Duration,
+    pub description: String,
+}
+
+pub struct Report {
+    pub duration: Duration,
+    pub seed: u64,
+    pub groups: Vec<GroupReport>,
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    /// Road users that left the intersection.
+    pub fn throughput(&self) -> usize {
+        self.groups.iter().map(|group| group.departed).sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Simulated {} s of traffic with seed {}",
+            self.duration.as_secs(),
+            self.seed
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<18} {:>8} {:>8} {:>8} {:>10} {:>10}",
+            "group", "arrived", "departed", "waiting", "avg wait", "max wait"
+        )?;
+
+        for group in &self.groups {
+            writeln!(
+                f,
+                "{:<18} {:>8} {:>8} {:>8} {:>8.1} s {:>8.1} s",
+                group.group.to_string(),
+                group.arrived,
+                group.departed,
+                group.waiting,
+                group.average_wait.as_secs_f64(),
+                group.max_wait.as_secs_f64()
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Throughput: {} road users, {:.1} per minute",
+            self.throughput(),
+            self.throughput() as f64 * 60.0 / self.duration.as_secs_f64().max(1.0)
+        )?;
+
+        if self.violations.is_empty() {
+            return writeln!(f, "Safety violations: none");
+        }
+
+        writeln!(f, "Safety violations: {}", self.violations.len())?;
+
+        for violation in &self.violations {
+            writeln!(
+                f,
+                "  at {:.1} s: {}",
+                violation.at.as_secs_f64(),
+                violation.description
+            )?;
+        }
+
+        Ok(())
+    }
+}